@@ -0,0 +1,94 @@
+//! Compare loading a large chart from JSON and from the binary encoding
+//!
+//! Run with `cargo bench -p phichain-chart --features binary`
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use phichain_chart::beat;
+use phichain_chart::binary;
+use phichain_chart::event::{LineEvent, LineEventKind, LineEventValue};
+use phichain_chart::line::Line;
+use phichain_chart::note::{Note, NoteKind};
+use phichain_chart::primitive::{Format, PrimitiveChart};
+use phichain_chart::serialization::{LineWrapper, PhichainChart};
+use std::hint::black_box;
+
+/// A chart shaped like one compiled from RPE, with many short linear events on every line
+fn large_chart() -> PhichainChart {
+    let lines = (0..20)
+        .map(|index| {
+            let notes = (0..200)
+                .map(|x| {
+                    Note::new(
+                        NoteKind::Tap,
+                        true,
+                        beat!(x),
+                        (x * index) as f32 % 900.0 - 450.0,
+                        1.0,
+                    )
+                })
+                .collect();
+            let events = [LineEventKind::X, LineEventKind::Y, LineEventKind::Rotation]
+                .into_iter()
+                .flat_map(|kind| {
+                    (0..1000).map(move |x| LineEvent {
+                        id: None,
+                        kind,
+                        start_beat: beat!(x / 32, x % 32, 32),
+                        end_beat: beat!((x + 1) / 32, (x + 1) % 32, 32),
+                        value: LineEventValue::transition(
+                            x as f32 * 0.37,
+                            (x + 1) as f32 * 0.37,
+                            Default::default(),
+                        ),
+                    })
+                })
+                .collect();
+
+            LineWrapper::new(Line::default(), notes, events, vec![])
+        })
+        .collect();
+
+    let mut chart = PhichainChart::new(0.0, Default::default(), lines);
+    chart.assign_ids();
+    chart
+}
+
+fn load(c: &mut Criterion) {
+    let chart = large_chart();
+    let phichain_json = serde_json::to_vec(&chart).unwrap();
+    let phichain_binary = binary::encode_phichain(&chart).unwrap();
+
+    let primitive = chart.into_primitive().unwrap();
+    let primitive_json = serde_json::to_vec(&primitive).unwrap();
+    let primitive_binary = binary::encode_primitive(&primitive).unwrap();
+
+    println!(
+        "phichain: {} bytes as JSON, {} bytes as binary",
+        phichain_json.len(),
+        phichain_binary.len()
+    );
+    println!(
+        "primitive: {} bytes as JSON, {} bytes as binary",
+        primitive_json.len(),
+        primitive_binary.len()
+    );
+
+    let mut group = c.benchmark_group("load");
+    group.sample_size(20);
+    group.bench_function("phichain json", |b| {
+        b.iter(|| PhichainChart::read(black_box(&phichain_json)).unwrap())
+    });
+    group.bench_function("phichain binary", |b| {
+        b.iter(|| PhichainChart::read(black_box(&phichain_binary)).unwrap())
+    });
+    group.bench_function("primitive json", |b| {
+        b.iter(|| PrimitiveChart::read(black_box(&primitive_json)).unwrap())
+    });
+    group.bench_function("primitive binary", |b| {
+        b.iter(|| PrimitiveChart::read(black_box(&primitive_binary)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, load);
+criterion_main!(benches);
@@ -0,0 +1,292 @@
+//! Compact binary encoding of Phichain and primitive charts, which loads much faster than JSON for large charts
+//!
+//! A binary chart starts with a header, followed by the chart encoded as MessagePack:
+//!
+//! | Bytes | Content                                                                  |
+//! |-------|--------------------------------------------------------------------------|
+//! | 4     | The magic number `PHCB`                                                  |
+//! | 1     | The version of the binary encoding, see [`ENCODING_VERSION`]             |
+//! | 1     | The kind of the chart, see [`ChartKind`]                                 |
+//! | 8     | The `format` of the chart as a little-endian integer                     |
+//!
+//! Encoding and decoding require the `binary` feature and fail without it, while the header can always be read
+
+use anyhow::{bail, Context};
+
+/// The magic number at the start of binary charts
+pub const MAGIC: &[u8; 4] = b"PHCB";
+
+/// The current version of the binary encoding, bumped when the header or the encoding of the body changes
+pub const ENCODING_VERSION: u8 = 1;
+
+const HEADER_LEN: usize = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ChartKind {
+    Phichain = 0,
+    Primitive = 1,
+}
+
+impl ChartKind {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Phichain),
+            1 => Some(Self::Primitive),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u8,
+    pub kind: ChartKind,
+    /// The format of the encoded chart, [`PhichainChart::format`](crate::serialization::PhichainChart::format) or
+    /// [`PrimitiveChart::format`](crate::primitive::PrimitiveChart::format)
+    pub format: u64,
+}
+
+impl Header {
+    /// Read the header of a binary chart, returns [`None`] if the data is not a binary chart
+    pub fn read(data: &[u8]) -> anyhow::Result<Option<Self>> {
+        if !is_binary(data) {
+            return Ok(None);
+        }
+        if data.len() < HEADER_LEN {
+            bail!("Binary chart header is truncated");
+        }
+
+        let version = data[4];
+        if version > ENCODING_VERSION {
+            bail!(
+                "Binary chart encoding version {} is newer than the supported version {}",
+                version,
+                ENCODING_VERSION
+            );
+        }
+        let kind = ChartKind::from_byte(data[5]).context("Unknown binary chart kind")?;
+        let format = u64::from_le_bytes(data[6..HEADER_LEN].try_into()?);
+
+        Ok(Some(Self {
+            version,
+            kind,
+            format,
+        }))
+    }
+
+    #[cfg(feature = "binary")]
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(MAGIC);
+        out.push(self.version);
+        out.push(self.kind as u8);
+        out.extend_from_slice(&self.format.to_le_bytes());
+    }
+}
+
+/// Whether the data is a binary chart, judging by the magic number
+pub fn is_binary(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+#[cfg(feature = "binary")]
+mod codec {
+    use super::*;
+    use crate::migration::{migrate, CURRENT_FORMAT};
+    use crate::primitive::PrimitiveChart;
+    use crate::serialization::PhichainChart;
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+
+    pub(super) fn encode<T: Serialize>(
+        chart: &T,
+        kind: ChartKind,
+        format: u64,
+    ) -> anyhow::Result<Vec<u8>> {
+        let mut out = vec![];
+        Header {
+            version: ENCODING_VERSION,
+            kind,
+            format,
+        }
+        .write(&mut out);
+        // fields are encoded with their names, as optional fields are skipped when serializing
+        rmp_serde::encode::write_named(&mut out, chart).context("Failed to encode chart")?;
+
+        Ok(out)
+    }
+
+    /// Read the header and make sure the chart is of the expected kind, returns the header and the body
+    fn split(data: &[u8], expected: ChartKind) -> anyhow::Result<(Header, &[u8])> {
+        let header = Header::read(data)?.context("Not a binary chart")?;
+        if header.kind != expected {
+            bail!(
+                "Expected a binary {:?} chart, got a {:?} chart",
+                expected,
+                header.kind
+            );
+        }
+
+        Ok((header, &data[HEADER_LEN..]))
+    }
+
+    fn decode<T: DeserializeOwned>(body: &[u8]) -> anyhow::Result<T> {
+        rmp_serde::from_slice(body).context("Failed to decode chart")
+    }
+
+    /// Encode a Phichain chart into the binary encoding
+    pub fn encode_phichain(chart: &PhichainChart) -> anyhow::Result<Vec<u8>> {
+        encode(chart, ChartKind::Phichain, chart.format)
+    }
+
+    /// Encode a primitive chart into the binary encoding
+    pub fn encode_primitive(chart: &PrimitiveChart) -> anyhow::Result<Vec<u8>> {
+        encode(chart, ChartKind::Primitive, chart.format)
+    }
+
+    /// Decode a binary Phichain chart, migrating it from older formats
+    pub fn decode_phichain(data: &[u8]) -> anyhow::Result<PhichainChart> {
+        let (header, body) = split(data, ChartKind::Phichain)?;
+        if header.format == CURRENT_FORMAT {
+            return decode(body);
+        }
+
+        let chart: serde_json::Value = decode(body)?;
+        let migrated = migrate(&chart).context("Migration failed")?;
+        Ok(serde_json::from_value(migrated)?)
+    }
+
+    /// Decode a binary primitive chart
+    pub fn decode_primitive(data: &[u8]) -> anyhow::Result<PrimitiveChart> {
+        let (_, body) = split(data, ChartKind::Primitive)?;
+        decode(body)
+    }
+}
+
+/// Stubs failing at runtime, so callers do not need to check for the feature themselves
+#[cfg(not(feature = "binary"))]
+mod codec {
+    use crate::primitive::PrimitiveChart;
+    use crate::serialization::PhichainChart;
+    use anyhow::bail;
+
+    const UNSUPPORTED: &str = "Binary charts require the `binary` feature of phichain-chart";
+
+    pub fn encode_phichain(_: &PhichainChart) -> anyhow::Result<Vec<u8>> {
+        bail!(UNSUPPORTED)
+    }
+
+    pub fn encode_primitive(_: &PrimitiveChart) -> anyhow::Result<Vec<u8>> {
+        bail!(UNSUPPORTED)
+    }
+
+    pub fn decode_phichain(_: &[u8]) -> anyhow::Result<PhichainChart> {
+        bail!(UNSUPPORTED)
+    }
+
+    pub fn decode_primitive(_: &[u8]) -> anyhow::Result<PrimitiveChart> {
+        bail!(UNSUPPORTED)
+    }
+}
+
+pub use codec::*;
+
+#[cfg(all(test, feature = "binary"))]
+mod tests {
+    use super::*;
+    use crate::beat;
+    use crate::event::{LineEvent, LineEventKind, LineEventValue};
+    use crate::line::Line;
+    use crate::note::{Note, NoteKind};
+    use crate::primitive::Format;
+    use crate::serialization::{LineWrapper, PhichainChart};
+
+    fn chart() -> PhichainChart {
+        let notes = vec![
+            Note::new(NoteKind::Tap, true, beat!(1), 100.0, 1.0),
+            Note {
+                visible_time: Some(1.5),
+                ..Note::new(
+                    NoteKind::Hold {
+                        hold_beat: beat!(1, 1, 2),
+                    },
+                    false,
+                    beat!(2),
+                    -100.0,
+                    1.0,
+                )
+            },
+        ];
+        let events = vec![LineEvent {
+            id: None,
+            kind: LineEventKind::X,
+            start_beat: beat!(0),
+            end_beat: beat!(4),
+            value: LineEventValue::constant(10.0),
+        }];
+        let child = LineWrapper::new(Line::default(), notes.clone(), events.clone(), vec![]);
+
+        let mut chart = PhichainChart::new(
+            0.5,
+            Default::default(),
+            vec![LineWrapper::new(
+                Line::default(),
+                notes,
+                events,
+                vec![child],
+            )],
+        );
+        chart.assign_ids();
+        chart
+    }
+
+    #[test]
+    fn test_phichain_round_trip() {
+        let chart = chart();
+        let data = encode_phichain(&chart).unwrap();
+        assert_eq!(
+            Header::read(&data).unwrap(),
+            Some(Header {
+                version: ENCODING_VERSION,
+                kind: ChartKind::Phichain,
+                format: chart.format,
+            })
+        );
+
+        let decoded = decode_phichain(&data).unwrap();
+        assert_eq!(decoded.offset.0, chart.offset.0);
+        assert_eq!(decoded.lines, chart.lines);
+        assert!(decode_primitive(&data).is_err());
+    }
+
+    #[test]
+    fn test_primitive_round_trip() {
+        let primitive = chart().into_primitive().unwrap();
+        let data = encode_primitive(&primitive).unwrap();
+
+        let decoded = decode_primitive(&data).unwrap();
+        assert_eq!(decoded.lines, primitive.lines);
+        assert!(decode_phichain(&data).is_err());
+    }
+
+    #[test]
+    fn test_migrate_old_format() {
+        let old = serde_json::json!({
+          "format": 4,
+          "offset": 0.0,
+          "bpm_list": [{ "beat": [0, 0, 1], "bpm": 120.0 }],
+          "lines": [{ "name": "Unnamed Line", "notes": [], "events": [], "children": [] }]
+        });
+        let data = codec::encode(&old, ChartKind::Phichain, 4).unwrap();
+
+        let decoded = decode_phichain(&data).unwrap();
+        assert_eq!(decoded.format, crate::migration::CURRENT_FORMAT);
+        assert!(decoded.lines[0].line.id.is_some());
+    }
+
+    #[test]
+    fn test_not_binary() {
+        assert_eq!(Header::read(b"{\"format\": 5}").unwrap(), None);
+        assert!(Header::read(b"PHCB\x01").is_err());
+    }
+}
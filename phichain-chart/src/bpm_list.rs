@@ -27,7 +27,7 @@ impl BpmPoint {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 #[cfg_attr(feature = "bevy", derive(bevy::prelude::Resource))]
 pub struct BpmList(pub Vec<BpmPoint>);
 
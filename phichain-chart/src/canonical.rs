@@ -0,0 +1,253 @@
+//! Deterministic serialization of Phichain charts, producing small diffs under version control
+//!
+//! Notes are sorted by beat, x and kind, events are sorted by kind and start beat. The chart is pretty-printed with
+//! each note, event and BPM point on its own line, and floats are written in their shortest form
+
+use crate::note::{Note, NoteKind};
+use crate::serialization::{LineWrapper, PhichainChart};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fmt::Write;
+
+const INDENT: &str = "  ";
+
+/// Arrays whose items are written with the same layout as the chart, any other array of objects is written with one
+/// compact item per line
+const NESTED_KEYS: [&str; 2] = ["lines", "children"];
+
+fn note_kind_tag(kind: &NoteKind) -> u8 {
+    match kind {
+        NoteKind::Tap => 0,
+        NoteKind::Drag => 1,
+        NoteKind::Hold { .. } => 2,
+        NoteKind::Flick => 3,
+    }
+}
+
+fn compare_notes(a: &Note, b: &Note) -> Ordering {
+    a.beat
+        .cmp(&b.beat)
+        .then(a.x.total_cmp(&b.x))
+        .then(note_kind_tag(&a.kind).cmp(&note_kind_tag(&b.kind)))
+        .then(a.hold_beat().cmp(&b.hold_beat()))
+        .then(a.above.cmp(&b.above))
+}
+
+fn sort_line(line: &mut LineWrapper) {
+    line.notes.sort_by(compare_notes);
+    line.events.sort_by(|a, b| {
+        u8::from(a.kind)
+            .cmp(&b.kind.into())
+            .then(a.start_beat.cmp(&b.start_beat))
+            .then(a.end_beat.cmp(&b.end_beat))
+    });
+    for child in &mut line.children {
+        sort_line(child);
+    }
+}
+
+/// Sort the notes and events of all lines in the chart
+///
+/// Lines are not reordered, since the order of lines is meaningful
+pub fn sort(chart: &mut PhichainChart) {
+    for line in &mut chart.lines {
+        sort_line(line);
+    }
+}
+
+/// Write a number in its shortest form
+///
+/// All floats in a chart are `f32`, they are written as `f32` to avoid the noise of widening to `f64`
+fn write_number(out: &mut String, number: &serde_json::Number) -> serde_json::Result<()> {
+    match number.as_f64() {
+        Some(x) if number.is_f64() => {
+            // `-0.0` and `0.0` are the same value
+            let x = if x == 0.0 { 0.0 } else { x as f32 };
+            out.push_str(&serde_json::to_string(&x)?);
+        }
+        _ => out.push_str(&number.to_string()),
+    }
+
+    Ok(())
+}
+
+/// Write a value on a single line
+fn write_compact(out: &mut String, value: &Value) -> serde_json::Result<()> {
+    match value {
+        Value::Number(number) => write_number(out, number)?,
+        Value::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                write_compact(out, item)?;
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            out.push('{');
+            for (index, (key, item)) in map.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(&serde_json::to_string(key)?);
+                out.push_str(": ");
+                write_compact(out, item)?;
+            }
+            out.push('}');
+        }
+        other => out.push_str(&serde_json::to_string(other)?),
+    }
+
+    Ok(())
+}
+
+/// Write a value across multiple lines, `key` is the key of the value in its parent object
+fn write_pretty(
+    out: &mut String,
+    key: Option<&str>,
+    value: &Value,
+    depth: usize,
+) -> serde_json::Result<()> {
+    let indent = INDENT.repeat(depth);
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push_str("{\n");
+            for (index, (key, item)) in map.iter().enumerate() {
+                out.push_str(&indent);
+                out.push_str(INDENT);
+                out.push_str(&serde_json::to_string(key)?);
+                out.push_str(": ");
+                write_pretty(out, Some(key), item, depth + 1)?;
+                out.push_str(if index + 1 < map.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&indent);
+            out.push('}');
+        }
+        Value::Array(items) if items.iter().any(|x| x.is_object() || x.is_array()) => {
+            let nested = key.is_some_and(|x| NESTED_KEYS.contains(&x));
+            out.push_str("[\n");
+            for (index, item) in items.iter().enumerate() {
+                out.push_str(&indent);
+                out.push_str(INDENT);
+                if nested {
+                    write_pretty(out, None, item, depth + 1)?;
+                } else {
+                    write_compact(out, item)?;
+                }
+                out.push_str(if index + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&indent);
+            out.push(']');
+        }
+        other => write_compact(out, other)?,
+    }
+
+    Ok(())
+}
+
+/// Serialize a chart deterministically, see the [module documentation](self)
+///
+/// The output is still a regular Phichain chart, which can be loaded with [`serde_json`]
+pub fn to_string(chart: &PhichainChart) -> serde_json::Result<String> {
+    let mut chart = chart.clone();
+    sort(&mut chart);
+
+    value_to_string(&serde_json::to_value(&chart)?)
+}
+
+/// Write a chart already serialized into a [`Value`] with the canonical layout, e.g. a chart migrated to an older
+/// format
+///
+/// Notes and events are written in their current order, sort the chart with [`sort`] before serializing it
+pub fn value_to_string(chart: &Value) -> serde_json::Result<String> {
+    let mut out = String::new();
+    write_pretty(&mut out, None, chart, 0)?;
+    writeln!(out).expect("writing to a string never fails");
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat;
+    use crate::beat::Beat;
+    use crate::event::{LineEvent, LineEventKind, LineEventValue};
+    use crate::line::Line;
+
+    fn note(kind: NoteKind, beat: Beat, x: f32) -> Note {
+        Note::new(kind, true, beat, x, 1.0)
+    }
+
+    fn event(kind: LineEventKind, start_beat: Beat, value: f32) -> LineEvent {
+        LineEvent {
+            id: None,
+            kind,
+            start_beat,
+            end_beat: start_beat + beat!(1),
+            value: LineEventValue::constant(value),
+        }
+    }
+
+    fn chart(notes: Vec<Note>, events: Vec<LineEvent>) -> PhichainChart {
+        PhichainChart::new(
+            0.0,
+            Default::default(),
+            vec![LineWrapper::new(Line::default(), notes, events, vec![])],
+        )
+    }
+
+    #[test]
+    fn test_order_independent() {
+        let notes = vec![
+            note(NoteKind::Tap, beat!(1), 0.0),
+            note(NoteKind::Drag, beat!(0), 100.0),
+            note(NoteKind::Flick, beat!(0), -100.0),
+            note(NoteKind::Tap, beat!(0), 100.0),
+        ];
+        let events = vec![
+            event(LineEventKind::Y, beat!(0), 0.0),
+            event(LineEventKind::X, beat!(1), 0.1),
+            event(LineEventKind::X, beat!(0), -0.0),
+        ];
+
+        let a = to_string(&chart(notes.clone(), events.clone())).unwrap();
+        let b = to_string(&chart(
+            notes.into_iter().rev().collect(),
+            events.into_iter().rev().collect(),
+        ))
+        .unwrap();
+        assert_eq!(a, b);
+
+        let loaded: PhichainChart = serde_json::from_str(&a).unwrap();
+        let kinds = loaded.lines[0]
+            .notes
+            .iter()
+            .map(|x| (note_kind_tag(&x.kind), x.x))
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, vec![(3, -100.0), (0, 100.0), (1, 100.0), (0, 0.0)]);
+    }
+
+    #[test]
+    fn test_layout() {
+        let output = to_string(&chart(
+            vec![note(NoteKind::Tap, beat!(0), 0.0)],
+            vec![
+                event(LineEventKind::X, beat!(0), 0.1),
+                event(LineEventKind::Y, beat!(0), -0.0),
+            ],
+        ))
+        .unwrap();
+
+        assert!(output.contains(
+            "      \"notes\": [\n        {\"above\": true, \"alpha\": 255.0, \"beat\": [0, 0, 1],"
+        ));
+        assert!(output.contains(
+            "\"kind\": \"x\", \"start_beat\": [0, 0, 1], \"value\": {\"constant\": 0.1}},\n"
+        ));
+        assert!(output.contains("\"value\": {\"constant\": 0.0}}\n"));
+        assert!(output.ends_with("}\n"));
+    }
+}
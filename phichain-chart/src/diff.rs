@@ -0,0 +1,729 @@
+//! Semantic diff and three-way merge of Phichain charts
+//!
+//! Lines are matched by their position in the line hierarchy and their names,
+//! notes are matched by beat and kind, events are matched by kind and start beat
+
+use crate::beat::Beat;
+use crate::bpm_list::BpmList;
+use crate::event::{LineEvent, LineEventKind};
+use crate::line::{Line, LinePath};
+use crate::note::{Note, NoteKind};
+use crate::serialization::{LineWrapper, PhichainChart};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::hash::Hash;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<T> {
+    Added(T),
+    Removed(T),
+    Modified { old: T, new: T },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LineChange {
+    /// The line is added, together with all its children
+    Added(LineWrapper),
+    /// The line is removed, together with all its children
+    Removed(LineWrapper),
+    /// The line exists in both charts, but its properties, notes or events are changed
+    ///
+    /// Changes of child lines are reported separately
+    Modified {
+        line: Option<(Line, Line)>,
+        notes: Vec<Change<Note>>,
+        events: Vec<Change<LineEvent>>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineDiff {
+    /// The path of the line in the new chart, or in the old chart for removed lines
+    pub path: LinePath,
+    pub name: String,
+    pub change: LineChange,
+}
+
+/// The semantic difference between two charts
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChartDiff {
+    pub offset: Option<(f32, f32)>,
+    pub bpm_list: Option<(BpmList, BpmList)>,
+    pub lines: Vec<LineDiff>,
+}
+
+impl ChartDiff {
+    pub fn is_empty(&self) -> bool {
+        self.offset.is_none() && self.bpm_list.is_none() && self.lines.is_empty()
+    }
+}
+
+/// An item in a line which can be matched between charts
+trait DiffItem: Clone + PartialEq {
+    type Key: Ord + Hash + Clone;
+
+    fn key(&self) -> Self::Key;
+
+    /// Ordering between items sharing the same key, used to match duplicated items deterministically
+    fn tiebreak(&self, other: &Self) -> Ordering;
+}
+
+fn note_kind_tag(kind: &NoteKind) -> u8 {
+    match kind {
+        NoteKind::Tap => 0,
+        NoteKind::Drag => 1,
+        NoteKind::Hold { .. } => 2,
+        NoteKind::Flick => 3,
+    }
+}
+
+impl DiffItem for Note {
+    type Key = (Beat, u8);
+
+    fn key(&self) -> Self::Key {
+        (self.beat.reduced(), note_kind_tag(&self.kind))
+    }
+
+    fn tiebreak(&self, other: &Self) -> Ordering {
+        self.x
+            .total_cmp(&other.x)
+            .then(self.above.cmp(&other.above))
+    }
+}
+
+impl DiffItem for LineEvent {
+    type Key = (u8, Beat);
+
+    fn key(&self) -> Self::Key {
+        (self.kind.into(), self.start_beat.reduced())
+    }
+
+    fn tiebreak(&self, other: &Self) -> Ordering {
+        self.end_beat.cmp(&other.end_beat)
+    }
+}
+
+/// Index items by their keys, duplicated keys are distinguished by their occurrence
+fn keyed<T: DiffItem>(items: &[T]) -> BTreeMap<(T::Key, usize), &T> {
+    let mut sorted = items.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.key().cmp(&b.key()).then_with(|| a.tiebreak(b)));
+
+    let mut map = BTreeMap::new();
+    let mut occurrences: HashMap<T::Key, usize> = HashMap::new();
+    for item in sorted {
+        let occurrence = occurrences.entry(item.key()).or_insert(0);
+        map.insert((item.key(), *occurrence), item);
+        *occurrence += 1;
+    }
+
+    map
+}
+
+fn diff_items<T: DiffItem>(old: &[T], new: &[T]) -> Vec<Change<T>> {
+    let old = keyed(old);
+    let new = keyed(new);
+
+    let mut keys = old.keys().chain(new.keys()).collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter_map(|key| match (old.get(key), new.get(key)) {
+            (Some(old), Some(new)) if old != new => Some(Change::Modified {
+                old: (*old).clone(),
+                new: (*new).clone(),
+            }),
+            (Some(old), None) => Some(Change::Removed((*old).clone())),
+            (None, Some(new)) => Some(Change::Added((*new).clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Index sibling lines by their names, lines with the same name are distinguished by their occurrence
+fn keyed_lines(lines: &[LineWrapper]) -> Vec<((String, usize), usize)> {
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| {
+            let occurrence = occurrences.entry(&line.line.name).or_insert(0);
+            let key = (line.line.name.clone(), *occurrence);
+            *occurrence += 1;
+            (key, index)
+        })
+        .collect()
+}
+
+fn diff_lines(
+    old: &[LineWrapper],
+    new: &[LineWrapper],
+    parent: Option<&LinePath>,
+    diffs: &mut Vec<LineDiff>,
+) {
+    let path = |index: usize| match parent {
+        Some(parent) => parent.child(index),
+        None => LinePath(vec![index]),
+    };
+
+    let old_keys = keyed_lines(old).into_iter().collect::<HashMap<_, _>>();
+    let new_keys = keyed_lines(new);
+
+    for (key, index) in &new_keys {
+        let new_line = &new[*index];
+        let path = path(*index);
+        let Some(old_index) = old_keys.get(key) else {
+            diffs.push(LineDiff {
+                path,
+                name: new_line.line.name.clone(),
+                change: LineChange::Added(new_line.clone()),
+            });
+            continue;
+        };
+        let old_line = &old[*old_index];
+
+        let line = (old_line.line != new_line.line)
+            .then(|| (old_line.line.clone(), new_line.line.clone()));
+        let notes = diff_items(&old_line.notes, &new_line.notes);
+        let events = diff_items(&old_line.events, &new_line.events);
+
+        if line.is_some() || !notes.is_empty() || !events.is_empty() {
+            diffs.push(LineDiff {
+                path: path.clone(),
+                name: new_line.line.name.clone(),
+                change: LineChange::Modified {
+                    line,
+                    notes,
+                    events,
+                },
+            });
+        }
+
+        diff_lines(&old_line.children, &new_line.children, Some(&path), diffs);
+    }
+
+    let new_keys = new_keys.into_iter().collect::<HashMap<_, _>>();
+    let mut removed = old_keys
+        .into_iter()
+        .filter(|(key, _)| !new_keys.contains_key(key))
+        .map(|(_, index)| index)
+        .collect::<Vec<_>>();
+    removed.sort();
+    for index in removed {
+        diffs.push(LineDiff {
+            path: path(index),
+            name: old[index].line.name.clone(),
+            change: LineChange::Removed(old[index].clone()),
+        });
+    }
+}
+
+/// Compute the semantic difference from `old` to `new`
+pub fn diff(old: &PhichainChart, new: &PhichainChart) -> ChartDiff {
+    let mut lines = vec![];
+    diff_lines(&old.lines, &new.lines, None, &mut lines);
+
+    ChartDiff {
+        offset: (old.offset.0 != new.offset.0).then_some((old.offset.0, new.offset.0)),
+        bpm_list: (old.bpm_list.0 != new.bpm_list.0)
+            .then(|| (old.bpm_list.clone(), new.bpm_list.clone())),
+        lines,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictKind {
+    Offset,
+    BpmList,
+    /// The properties of a line are changed differently, or one side changed a line the other side removed
+    Line,
+    Note {
+        beat: Beat,
+    },
+    Event {
+        kind: LineEventKind,
+        start_beat: Beat,
+    },
+}
+
+/// A conflict found during merging, conflicts are always resolved by taking our side
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// The path of the line in the merged chart
+    pub path: Option<LinePath>,
+    pub kind: ConflictKind,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "line {}: ", path)?;
+        }
+        match &self.kind {
+            ConflictKind::Offset => write!(f, "conflicting offset"),
+            ConflictKind::BpmList => write!(f, "conflicting bpm list"),
+            ConflictKind::Line => write!(f, "conflicting line"),
+            ConflictKind::Note { beat } => write!(f, "conflicting note at {:?}", beat),
+            ConflictKind::Event { kind, start_beat } => {
+                write!(f, "conflicting {:?} event at {:?}", kind, start_beat)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MergeResult {
+    pub chart: PhichainChart,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Three-way merge a single value, returns the merged value and if there's a conflict
+fn merge_value<T: Clone + PartialEq>(
+    base: Option<&T>,
+    ours: Option<&T>,
+    theirs: Option<&T>,
+) -> (Option<T>, bool) {
+    if ours == theirs || theirs == base {
+        (ours.cloned(), false)
+    } else if ours == base {
+        (theirs.cloned(), false)
+    } else {
+        (ours.cloned(), true)
+    }
+}
+
+fn merge_items<T: DiffItem>(
+    base: &[T],
+    ours: &[T],
+    theirs: &[T],
+    mut on_conflict: impl FnMut(&T),
+) -> Vec<T> {
+    let base = keyed(base);
+    let ours = keyed(ours);
+    let theirs = keyed(theirs);
+
+    let mut keys = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    let mut merged = vec![];
+    for key in keys {
+        let (base, ours, theirs) = (
+            base.get(key).copied(),
+            ours.get(key).copied(),
+            theirs.get(key).copied(),
+        );
+        let (item, conflict) = merge_value(base, ours, theirs);
+        if conflict {
+            if let Some(item) = ours.or(theirs) {
+                on_conflict(item);
+            }
+        }
+        merged.extend(item);
+    }
+
+    merged
+}
+
+fn merge_line(
+    base: Option<&LineWrapper>,
+    ours: &LineWrapper,
+    theirs: &LineWrapper,
+    path: &LinePath,
+    conflicts: &mut Vec<Conflict>,
+) -> LineWrapper {
+    let (line, conflict) = merge_value(base.map(|x| &x.line), Some(&ours.line), Some(&theirs.line));
+    if conflict {
+        conflicts.push(Conflict {
+            path: Some(path.clone()),
+            kind: ConflictKind::Line,
+        });
+    }
+
+    let (extended, conflict) = merge_value(
+        base.map(|x| &x.extended),
+        Some(&ours.extended),
+        Some(&theirs.extended),
+    );
+    if conflict {
+        conflicts.push(Conflict {
+            path: Some(path.clone()),
+            kind: ConflictKind::Line,
+        });
+    }
+
+    let notes = merge_items(
+        base.map_or(&[], |x| &x.notes),
+        &ours.notes,
+        &theirs.notes,
+        |note| {
+            conflicts.push(Conflict {
+                path: Some(path.clone()),
+                kind: ConflictKind::Note { beat: note.beat },
+            })
+        },
+    );
+    let events = merge_items(
+        base.map_or(&[], |x| &x.events),
+        &ours.events,
+        &theirs.events,
+        |event| {
+            conflicts.push(Conflict {
+                path: Some(path.clone()),
+                kind: ConflictKind::Event {
+                    kind: event.kind,
+                    start_beat: event.start_beat,
+                },
+            })
+        },
+    );
+    let children = merge_lines(
+        base.map_or(&[], |x| &x.children),
+        &ours.children,
+        &theirs.children,
+        Some(path),
+        conflicts,
+    );
+
+    // unwrap: both sides have the line, the merged line must exist
+    LineWrapper {
+        extended: extended.unwrap(),
+        ..LineWrapper::new(line.unwrap(), notes, events, children)
+    }
+}
+
+fn merge_lines(
+    base: &[LineWrapper],
+    ours: &[LineWrapper],
+    theirs: &[LineWrapper],
+    parent: Option<&LinePath>,
+    conflicts: &mut Vec<Conflict>,
+) -> Vec<LineWrapper> {
+    let base_keys = keyed_lines(base).into_iter().collect::<HashMap<_, _>>();
+    let our_keys = keyed_lines(ours);
+    let their_keys = keyed_lines(theirs);
+    let their_map = their_keys.iter().cloned().collect::<HashMap<_, _>>();
+    let our_map = our_keys.iter().cloned().collect::<HashMap<_, _>>();
+
+    // keep our order, then append lines only existing in theirs
+    let keys = our_keys
+        .iter()
+        .map(|x| &x.0)
+        .chain(
+            their_keys
+                .iter()
+                .map(|x| &x.0)
+                .filter(|x| !our_map.contains_key(*x)),
+        )
+        .collect::<Vec<_>>();
+
+    let mut merged = vec![];
+    for key in keys {
+        let path = match parent {
+            Some(parent) => parent.child(merged.len()),
+            None => LinePath(vec![merged.len()]),
+        };
+
+        let base = base_keys.get(key).map(|x| &base[*x]);
+        let ours = our_map.get(key).map(|x| &ours[*x]);
+        let theirs = their_map.get(key).map(|x| &theirs[*x]);
+
+        match (ours, theirs) {
+            (Some(ours), Some(theirs)) => {
+                merged.push(merge_line(base, ours, theirs, &path, conflicts));
+            }
+            (Some(side), None) | (None, Some(side)) => match base {
+                // added by one side
+                None => merged.push(side.clone()),
+                // removed by the other side without modifications on this side
+                Some(base) if base == side => {}
+                // modified by one side, removed by the other side
+                Some(_) => {
+                    conflicts.push(Conflict {
+                        path: Some(path),
+                        kind: ConflictKind::Line,
+                    });
+                    if ours.is_some() {
+                        merged.push(side.clone());
+                    }
+                }
+            },
+            (None, None) => unreachable!(),
+        }
+    }
+
+    merged
+}
+
+/// Three-way merge two charts `ours` and `theirs` derived from a common ancestor `base`
+///
+/// Changes made by only one side are applied, conflicting changes are resolved by taking `ours` and reported
+pub fn merge(base: &PhichainChart, ours: &PhichainChart, theirs: &PhichainChart) -> MergeResult {
+    let mut conflicts = vec![];
+
+    let (offset, conflict) = merge_value(
+        Some(&base.offset.0),
+        Some(&ours.offset.0),
+        Some(&theirs.offset.0),
+    );
+    if conflict {
+        conflicts.push(Conflict {
+            path: None,
+            kind: ConflictKind::Offset,
+        });
+    }
+
+    let (bpm_list, conflict) = merge_value(
+        Some(&base.bpm_list.0),
+        Some(&ours.bpm_list.0),
+        Some(&theirs.bpm_list.0),
+    );
+    if conflict {
+        conflicts.push(Conflict {
+            path: None,
+            kind: ConflictKind::BpmList,
+        });
+    }
+
+    let lines = merge_lines(
+        &base.lines,
+        &ours.lines,
+        &theirs.lines,
+        None,
+        &mut conflicts,
+    );
+
+    // unwrap: all sides have offset and bpm list, the merged values must exist
+    let chart = PhichainChart::new(offset.unwrap(), BpmList::new(bpm_list.unwrap()), lines);
+
+    MergeResult { chart, conflicts }
+}
+
+impl Display for ChartDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn note(note: &Note) -> String {
+            let kind = match note.kind {
+                NoteKind::Tap => "tap",
+                NoteKind::Drag => "drag",
+                NoteKind::Hold { .. } => "hold",
+                NoteKind::Flick => "flick",
+            };
+            format!(
+                "{} note at {:?} (x = {}, {})",
+                kind,
+                note.beat,
+                note.x,
+                if note.above { "above" } else { "below" }
+            )
+        }
+
+        fn event(event: &LineEvent) -> String {
+            format!(
+                "{:?} event {:?} ~ {:?}",
+                event.kind, event.start_beat, event.end_beat
+            )
+        }
+
+        if let Some((old, new)) = self.offset {
+            writeln!(f, "offset: {} -> {}", old, new)?;
+        }
+        if self.bpm_list.is_some() {
+            writeln!(f, "bpm list changed")?;
+        }
+
+        for line in &self.lines {
+            match &line.change {
+                LineChange::Added(_) => writeln!(f, "+ line {} \"{}\"", line.path, line.name)?,
+                LineChange::Removed(_) => writeln!(f, "- line {} \"{}\"", line.path, line.name)?,
+                LineChange::Modified {
+                    line: properties,
+                    notes,
+                    events,
+                } => {
+                    writeln!(f, "~ line {} \"{}\"", line.path, line.name)?;
+                    if properties.is_some() {
+                        writeln!(f, "    ~ properties")?;
+                    }
+                    for change in notes {
+                        match change {
+                            Change::Added(x) => writeln!(f, "    + {}", note(x))?,
+                            Change::Removed(x) => writeln!(f, "    - {}", note(x))?,
+                            Change::Modified { new, .. } => writeln!(f, "    ~ {}", note(new))?,
+                        }
+                    }
+                    for change in events {
+                        match change {
+                            Change::Added(x) => writeln!(f, "    + {}", event(x))?,
+                            Change::Removed(x) => writeln!(f, "    - {}", event(x))?,
+                            Change::Modified { new, .. } => writeln!(f, "    ~ {}", event(new))?,
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat;
+    use crate::event::LineEventValue;
+
+    fn named(name: &str) -> LineWrapper {
+        LineWrapper {
+            line: Line {
+                name: name.to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn chart(lines: Vec<LineWrapper>) -> PhichainChart {
+        PhichainChart::new(0.0, Default::default(), lines)
+    }
+
+    fn tap(beat: Beat, x: f32) -> Note {
+        Note::new(NoteKind::Tap, true, beat, x, 1.0)
+    }
+
+    #[test]
+    fn test_identical() {
+        let a = chart(vec![named("a"), named("b")]);
+        let b = chart(vec![named("a"), named("b")]);
+        assert!(diff(&a, &b).is_empty());
+    }
+
+    #[test]
+    fn test_reordered_notes_and_lines() {
+        let mut a = named("a");
+        a.notes = vec![tap(beat!(1), 0.0), tap(beat!(2), 0.0)];
+        let mut b = a.clone();
+        b.notes.reverse();
+
+        let old = chart(vec![a, named("b")]);
+        let new = chart(vec![named("b"), b]);
+        assert!(diff(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn test_notes_and_events() {
+        let mut a = named("a");
+        a.notes = vec![tap(beat!(1), 0.0), tap(beat!(2), 0.0)];
+        let mut b = a.clone();
+        b.notes[0].x = 100.0;
+        b.notes.remove(1);
+        b.notes.push(tap(beat!(3), 0.0));
+        b.events[0].value = LineEventValue::constant(10.0);
+
+        let result = diff(&chart(vec![a.clone()]), &chart(vec![b.clone()]));
+        assert_eq!(
+            result.lines,
+            vec![LineDiff {
+                path: LinePath(vec![0]),
+                name: "a".to_owned(),
+                change: LineChange::Modified {
+                    line: None,
+                    notes: vec![
+                        Change::Modified {
+                            old: a.notes[0],
+                            new: b.notes[0],
+                        },
+                        Change::Removed(a.notes[1]),
+                        Change::Added(b.notes[1]),
+                    ],
+                    events: vec![Change::Modified {
+                        old: a.events[0],
+                        new: b.events[0],
+                    }],
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_nested_lines() {
+        let mut parent = named("parent");
+        parent.children = vec![named("a")];
+        let old = chart(vec![parent.clone()]);
+
+        parent.children = vec![named("b")];
+        let new = chart(vec![parent]);
+
+        let result = diff(&old, &new);
+        assert_eq!(result.lines.len(), 2);
+        assert_eq!(result.lines[0].path, LinePath(vec![0, 0]));
+        assert!(matches!(result.lines[0].change, LineChange::Added(_)));
+        assert_eq!(result.lines[1].name, "a");
+        assert!(matches!(result.lines[1].change, LineChange::Removed(_)));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut base_line = named("a");
+        base_line.notes = vec![tap(beat!(1), 0.0)];
+        let base = chart(vec![base_line.clone()]);
+
+        let mut ours_line = base_line.clone();
+        ours_line.notes.push(tap(beat!(2), 0.0));
+        let ours = chart(vec![ours_line, named("ours")]);
+
+        let mut theirs_line = base_line.clone();
+        theirs_line.notes[0].x = 50.0;
+        let mut theirs = chart(vec![theirs_line, named("theirs")]);
+        theirs.offset.0 = 10.0;
+
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.conflicts.is_empty());
+        assert_eq!(result.chart.offset.0, 10.0);
+        assert_eq!(
+            result.chart.lines[0].notes,
+            vec![tap(beat!(1), 50.0), tap(beat!(2), 0.0)]
+        );
+        assert_eq!(
+            result
+                .chart
+                .lines
+                .iter()
+                .map(|x| x.line.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "ours", "theirs"]
+        );
+    }
+
+    #[test]
+    fn test_merge_conflict() {
+        let mut base_line = named("a");
+        base_line.notes = vec![tap(beat!(1), 0.0)];
+        let base = chart(vec![base_line.clone(), named("b")]);
+
+        let mut ours_line = base_line.clone();
+        ours_line.notes[0].x = 10.0;
+        let ours = chart(vec![ours_line, named("b")]);
+
+        let mut theirs_line = base_line.clone();
+        theirs_line.notes[0].x = 20.0;
+        let mut theirs_b = named("b");
+        theirs_b.notes.push(tap(beat!(1), 0.0));
+        let theirs = chart(vec![theirs_line, theirs_b]);
+
+        let result = merge(&base, &ours, &theirs);
+        assert_eq!(
+            result.conflicts,
+            vec![Conflict {
+                path: Some(LinePath(vec![0])),
+                kind: ConflictKind::Note { beat: beat!(1) },
+            }]
+        );
+        assert_eq!(result.chart.lines[0].notes[0].x, 10.0);
+        assert_eq!(result.chart.lines[1].notes.len(), 1);
+    }
+}
@@ -2,12 +2,23 @@ use crate::easing::{Easing, Tween};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use strum::EnumIter;
 
 use crate::beat::Beat;
+use crate::id::Id;
 use crate::primitive;
 
 #[derive(
-    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, IntoPrimitive, TryFromPrimitive,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    IntoPrimitive,
+    TryFromPrimitive,
+    EnumIter,
 )]
 #[serde(rename_all = "snake_case")]
 #[repr(u8)]
@@ -112,6 +123,9 @@ impl LineEventValue {
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "bevy", derive(bevy::prelude::Component))]
 pub struct LineEvent {
+    /// The persistent id of the event, [`None`] if not assigned yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Id>,
     pub kind: LineEventKind,
     pub start_beat: Beat,
     pub end_beat: Beat,
@@ -305,6 +319,7 @@ impl From<LineEvent> for primitive::event::LineEvent {
 impl From<primitive::event::LineEvent> for LineEvent {
     fn from(event: primitive::event::LineEvent) -> Self {
         Self {
+            id: None,
             kind: event.kind,
             start_beat: event.start_beat,
             end_beat: event.end_beat,
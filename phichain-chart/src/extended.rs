@@ -0,0 +1,188 @@
+//! Extended line events
+//!
+//! Besides the core [`LineEvent`](crate::event::LineEvent)s, lines can have events changing their scale, color,
+//! text, paint and incline. These come from the `extended` block of RPE charts
+
+use crate::beat::Beat;
+use crate::easing::{Easing, Tween};
+use serde::{Deserialize, Serialize};
+
+/// A RGB color of a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineColor(pub u8, pub u8, pub u8);
+
+/// Values which can be transitioned by an [`ExtendedEvent`]
+pub trait Interpolate: Clone {
+    fn interpolate(&self, other: &Self, t: f32, easing: Easing) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, other: &Self, t: f32, easing: Easing) -> Self {
+        self.ease_to(*other, t, easing)
+    }
+}
+
+impl Interpolate for LineColor {
+    fn interpolate(&self, other: &Self, t: f32, easing: Easing) -> Self {
+        let channel = |a: u8, b: u8| a.ease_to(b, t, easing).round().clamp(0.0, 255.0) as u8;
+        Self(
+            channel(self.0, other.0),
+            channel(self.1, other.1),
+            channel(self.2, other.2),
+        )
+    }
+}
+
+/// Texts are not interpolated, the start text is shown until the event ends
+impl Interpolate for String {
+    fn interpolate(&self, other: &Self, t: f32, _: Easing) -> Self {
+        if t < 1.0 {
+            self.clone()
+        } else {
+            other.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtendedEvent<T> {
+    pub start_beat: Beat,
+    pub end_beat: Beat,
+    pub start: T,
+    pub end: T,
+    pub easing: Easing,
+}
+
+impl<T: Interpolate> ExtendedEvent<T> {
+    /// Evaluate the event at the given beat, returns [`None`] if the event has not started yet
+    pub fn evaluate(&self, beat: f32) -> Option<T> {
+        let start_beat = self.start_beat.value();
+        let end_beat = self.end_beat.value();
+        if beat < start_beat {
+            None
+        } else if beat >= end_beat {
+            Some(self.end.clone())
+        } else {
+            let percent = (beat - start_beat) / (end_beat - start_beat);
+            Some(self.start.interpolate(&self.end, percent, self.easing))
+        }
+    }
+}
+
+/// Evaluate a sequence of events at the given beat using the latest started event
+fn evaluate<T: Interpolate>(events: &[ExtendedEvent<T>], beat: f32) -> Option<T> {
+    events
+        .iter()
+        .filter(|x| x.start_beat.value() <= beat)
+        .max_by_key(|x| x.start_beat)
+        .and_then(|x| x.evaluate(beat))
+}
+
+/// All extended events of a line
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "bevy", derive(bevy::prelude::Component))]
+pub struct ExtendedEvents {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scale_x: Vec<ExtendedEvent<f32>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scale_y: Vec<ExtendedEvent<f32>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub color: Vec<ExtendedEvent<LineColor>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub text: Vec<ExtendedEvent<String>>,
+    /// Paint events are kept for round trip but not rendered
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paint: Vec<ExtendedEvent<f32>>,
+    /// Incline events are kept for round trip but not rendered
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub incline: Vec<ExtendedEvent<f32>>,
+}
+
+impl ExtendedEvents {
+    pub fn is_empty(&self) -> bool {
+        self.scale_x.is_empty()
+            && self.scale_y.is_empty()
+            && self.color.is_empty()
+            && self.text.is_empty()
+            && self.paint.is_empty()
+            && self.incline.is_empty()
+    }
+
+    /// The scale of the line at the given beat, defaults to `(1.0, 1.0)`
+    pub fn scale_at(&self, beat: f32) -> (f32, f32) {
+        (
+            evaluate(&self.scale_x, beat).unwrap_or(1.0),
+            evaluate(&self.scale_y, beat).unwrap_or(1.0),
+        )
+    }
+
+    /// The color of the line at the given beat, [`None`] if no color event has started
+    pub fn color_at(&self, beat: f32) -> Option<LineColor> {
+        evaluate(&self.color, beat)
+    }
+
+    /// The text shown instead of the line at the given beat, [`None`] if no text event has started
+    pub fn text_at(&self, beat: f32) -> Option<String> {
+        evaluate(&self.text, beat)
+    }
+
+    pub fn paint_at(&self, beat: f32) -> Option<f32> {
+        evaluate(&self.paint, beat)
+    }
+
+    pub fn incline_at(&self, beat: f32) -> Option<f32> {
+        evaluate(&self.incline, beat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat;
+
+    fn event<T>(start_beat: Beat, end_beat: Beat, start: T, end: T) -> ExtendedEvent<T> {
+        ExtendedEvent {
+            start_beat,
+            end_beat,
+            start,
+            end,
+            easing: Easing::Linear,
+        }
+    }
+
+    #[test]
+    fn test_scale() {
+        let events = ExtendedEvents {
+            scale_x: vec![
+                event(beat!(2), beat!(3), 2.0, 4.0),
+                event(beat!(0), beat!(1), 1.0, 2.0),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(events.scale_at(0.5), (1.5, 1.0));
+        assert_eq!(events.scale_at(1.5), (2.0, 1.0));
+        assert_eq!(events.scale_at(2.5), (3.0, 1.0));
+        assert_eq!(events.scale_at(10.0), (4.0, 1.0));
+    }
+
+    #[test]
+    fn test_color_and_text() {
+        let events = ExtendedEvents {
+            color: vec![event(
+                beat!(0),
+                beat!(2),
+                LineColor(0, 100, 255),
+                LineColor(255, 100, 0),
+            )],
+            text: vec![event(beat!(1), beat!(2), "a".to_owned(), "b".to_owned())],
+            ..Default::default()
+        };
+
+        assert_eq!(events.color_at(1.0), Some(LineColor(128, 100, 128)));
+        assert_eq!(events.text_at(0.5), None);
+        assert_eq!(events.text_at(1.5), Some("a".to_owned()));
+        assert_eq!(events.text_at(2.0), Some("b".to_owned()));
+        assert!(!events.is_empty());
+    }
+}
@@ -0,0 +1,328 @@
+//! Curve fitting of linear events
+//!
+//! The inverse of [`segmentation`](crate::segmentation): consecutive linear events, e.g. from an official chart,
+//! are merged back into a single eased event when the easing fits all of them within a tolerance
+
+use crate::easing::{Easing, Tween};
+use crate::primitive::event::LineEvent;
+use strum::IntoEnumIterator;
+
+/// The default maximum value error of a fitted curve
+///
+/// Linear events in official charts are sampled from the original curve, so their ends lie on the curve and a
+/// small tolerance is enough. A larger tolerance lets a constant event swallow the flat start of a following ease
+pub const DEFAULT_FIT_TOLERANCE: f32 = 0.01;
+
+/// How many events past the greedy end of a chain are tried with named easings, see [`fit_events`]
+const LOOKAHEAD: usize = 1024;
+
+/// The range of the y of fitted bezier control points
+const BEZIER_RANGE: std::ops::RangeInclusive<f32> = -1.0..=2.0;
+
+/// The number of points added for each event, see [`add_points`]
+const POINTS_PER_EVENT: usize = 5;
+
+/// A point a fitted curve has to pass
+struct Point {
+    beat: f32,
+    value: f32,
+    /// The error allowed beyond the tolerance
+    slack: f32,
+}
+
+/// Add the ends of a linear event to the points, along with a few points between them
+///
+/// The curve between two ends is unknown, but it stays around the event, so points between the ends are only
+/// checked loosely. This keeps a bezier from passing all ends while swinging far away between them
+fn add_points(points: &mut Vec<Point>, event: &LineEvent) {
+    let (start_beat, end_beat) = (event.start_beat.value(), event.end_beat.value());
+    points.push(Point {
+        beat: start_beat,
+        value: event.start,
+        slack: 0.0,
+    });
+    for t in [0.25, 0.5, 0.75] {
+        points.push(Point {
+            beat: start_beat + (end_beat - start_beat) * t,
+            value: event.start + (event.end - event.start) * t,
+            slack: (event.end - event.start).abs() / 2.0,
+        });
+    }
+    points.push(Point {
+        beat: end_beat,
+        value: event.end,
+        slack: 0.0,
+    });
+}
+
+/// The easing from the first point to the last point, or [`None`] if the points span no time
+fn ease(points: &[Point], easing: Easing) -> Option<impl Fn(f32) -> f32> {
+    let (first, last) = (points.first()?, points.last()?);
+    let (start_beat, start) = (first.beat, first.value);
+    let (end_beat, end) = (last.beat, last.value);
+    if end_beat <= start_beat {
+        return None;
+    }
+
+    Some(move |beat| start.ease_to(end, (beat - start_beat) / (end_beat - start_beat), easing))
+}
+
+/// Whether the easing passes all points within `tolerance`
+fn fits(points: &[Point], easing: Easing, tolerance: f32) -> bool {
+    let Some(f) = ease(points, easing) else {
+        return false;
+    };
+    let check = |point: &Point| (f(point.beat) - point.value).abs() <= tolerance + point.slack;
+
+    // check a coarse subset first, so easings far off are rejected early
+    let stride = (points.len() / 8).max(1);
+    points.iter().step_by(stride).all(check) && points.iter().all(check)
+}
+
+/// The maximum error of the easing over all points
+fn error(points: &[Point], easing: Easing) -> f32 {
+    match ease(points, easing) {
+        Some(f) => points
+            .iter()
+            .map(|point| (f(point.beat) - point.value).abs())
+            .fold(0.0, f32::max),
+        None => f32::INFINITY,
+    }
+}
+
+/// Find the named easing other than [`Easing::Linear`] with the smallest error which passes all points
+fn fit_named(points: &[Point], tolerance: f32) -> Option<Easing> {
+    Easing::iter()
+        .filter(|x| !matches!(x, Easing::Linear | Easing::Custom(..)))
+        .filter(|x| fits(points, *x, tolerance))
+        .min_by(|a, b| error(points, *a).total_cmp(&error(points, *b)))
+}
+
+/// Find an easing from the first point to the last point which passes all points within `tolerance`
+///
+/// [`Easing::Linear`] is preferred, followed by the named easing with the smallest error, and finally an
+/// [`Easing::Custom`] bezier
+fn fit(points: &[Point], tolerance: f32) -> Option<Easing> {
+    if fits(points, Easing::Linear, tolerance) {
+        return Some(Easing::Linear);
+    }
+
+    fit_named(points, tolerance)
+        .or_else(|| fit_bezier(points).filter(|x| fits(points, *x, tolerance)))
+}
+
+/// Least squares fit of a bezier easing to the points
+///
+/// The control points are fixed at `x = 1/3` and `x = 2/3`, which makes the bezier a cubic polynomial of the
+/// progress and leaves `y1` and `y2` as the only, linear, parameters
+fn fit_bezier(points: &[Point]) -> Option<Easing> {
+    let (first, last) = (points.first()?, points.last()?);
+    let (start_beat, start) = (first.beat, first.value);
+    let (end_beat, end) = (last.beat, last.value);
+    if end == start {
+        return None;
+    }
+
+    let (mut aa, mut ac, mut cc, mut ar, mut cr) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for point in points.iter().filter(|x| x.slack == 0.0) {
+        let t = (point.beat - start_beat) / (end_beat - start_beat);
+        let y = (point.value - start) / (end - start);
+        let a = 3.0 * (1.0 - t) * (1.0 - t) * t;
+        let c = 3.0 * (1.0 - t) * t * t;
+        let r = y - t * t * t;
+        aa += a * a;
+        ac += a * c;
+        cc += c * c;
+        ar += a * r;
+        cr += c * r;
+    }
+
+    let determinant = aa * cc - ac * ac;
+    let (y1, y2) = if determinant.abs() > 1e-6 {
+        (
+            (ar * cc - cr * ac) / determinant,
+            (cr * aa - ar * ac) / determinant,
+        )
+    } else {
+        // too few points to tell y1 and y2 apart, fit a symmetric bezier instead
+        let denominator = aa + 2.0 * ac + cc;
+        if denominator.abs() < 1e-6 {
+            return None;
+        }
+        let y = (ar + cr) / denominator;
+        (y, y)
+    };
+
+    // control points far outside the range are a sign of overfitting rather than a real easing
+    if !BEZIER_RANGE.contains(&y1) || !BEZIER_RANGE.contains(&y2) {
+        return None;
+    }
+
+    Some(Easing::Custom(1.0 / 3.0, y1, 2.0 / 3.0, y2))
+}
+
+/// Merge consecutive linear events of the same kind into eased events
+///
+/// Events are merged when they are continuous, i.e. an event starts where the previous one ends with a value
+/// within `tolerance`, and a single easing passes through all of their start and end points within `tolerance`.
+/// Events which are not linear are kept as is
+///
+/// A chain is first extended greedily while any easing fits. Since a named easing usually only fits when the chain
+/// ends exactly where the original event ended, chains further ahead are then tried with named easings
+pub fn fit_events(mut events: Vec<LineEvent>, tolerance: f32) -> Vec<LineEvent> {
+    events.sort_by_key(|x| x.start_beat);
+
+    let mut points = vec![];
+    for event in &events {
+        add_points(&mut points, event);
+    }
+    let chain =
+        |from: usize, to: usize| &points[from * POINTS_PER_EVENT..(to + 1) * POINTS_PER_EVENT];
+
+    // the index of the last event in the continuous run each event belongs to
+    let mut run_ends = vec![0; events.len()];
+    for i in (0..events.len()).rev() {
+        let continuous = i + 1 < events.len() && {
+            let (a, b) = (&events[i], &events[i + 1]);
+            a.kind == b.kind
+                && a.end_beat == b.start_beat
+                && (a.end - b.start).abs() <= tolerance
+                && matches!(a.easing, Easing::Linear)
+                && matches!(b.easing, Easing::Linear)
+        };
+        run_ends[i] = if continuous { run_ends[i + 1] } else { i };
+    }
+
+    let mut fitted = vec![];
+    let mut i = 0;
+
+    while i < events.len() {
+        let first = events[i];
+        if !matches!(first.easing, Easing::Linear) {
+            fitted.push(first);
+            i += 1;
+            continue;
+        }
+
+        let mut last = i;
+        let mut easing = Easing::Linear;
+
+        while last < run_ends[i] {
+            match fit(chain(i, last + 1), tolerance) {
+                Some(fitted) => {
+                    easing = fitted;
+                    last += 1;
+                }
+                None => break,
+            }
+        }
+
+        let lookahead_end = run_ends[i].min(last + LOOKAHEAD);
+        for end in (last + 1..=lookahead_end).rev() {
+            if let Some(named) = fit_named(chain(i, end), tolerance) {
+                last = end;
+                easing = named;
+                break;
+            }
+        }
+
+        fitted.push(LineEvent {
+            kind: first.kind,
+            start: first.start,
+            end: events[last].end,
+            easing,
+            start_beat: first.start_beat,
+            end_beat: events[last].end_beat,
+        });
+        i = last + 1;
+    }
+
+    fitted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat;
+    use crate::beat::Beat;
+    use crate::event::LineEventKind;
+    use crate::segmentation::segment;
+
+    /// Cut an eased event into linear events, like an official chart does
+    fn cut(
+        start_beat: Beat,
+        end_beat: Beat,
+        start: f32,
+        end: f32,
+        easing: Easing,
+    ) -> Vec<LineEvent> {
+        let f = |beat: f32| {
+            let t = (beat - start_beat.value()) / (end_beat.value() - start_beat.value());
+            [start.ease_to(end, t, easing)]
+        };
+        segment(start_beat, end_beat, [start], [end], 0.01, f)
+            .into_iter()
+            .map(|x| LineEvent {
+                kind: LineEventKind::X,
+                start: x.start[0],
+                end: x.end[0],
+                easing: Easing::Linear,
+                start_beat: x.start_beat,
+                end_beat: x.end_beat,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fit_named_easing() {
+        let events = [
+            cut(beat!(0), beat!(2), 0.0, 100.0, Easing::EaseOutQuad),
+            cut(beat!(2), beat!(4), 100.0, 100.0, Easing::Linear),
+            cut(beat!(4), beat!(8), 100.0, -300.0, Easing::EaseInOutSine),
+        ]
+        .concat();
+        assert!(events.len() > 3);
+
+        let fitted = fit_events(events, DEFAULT_FIT_TOLERANCE);
+        assert_eq!(fitted.len(), 3);
+        assert_eq!(fitted[0].easing, Easing::EaseOutQuad);
+        assert_eq!(fitted[1].easing, Easing::Linear);
+        assert_eq!(fitted[2].easing, Easing::EaseInOutSine);
+        assert_eq!(fitted[2].start_beat, beat!(4));
+        assert_eq!(fitted[2].end_beat, beat!(8));
+    }
+
+    #[test]
+    fn test_fit_bezier() {
+        let bezier = Easing::Custom(1.0 / 3.0, 0.8, 2.0 / 3.0, 0.1);
+        let events = cut(beat!(0), beat!(4), 0.0, 100.0, bezier);
+
+        let fitted = fit_events(events, DEFAULT_FIT_TOLERANCE);
+        assert_eq!(fitted.len(), 1);
+        assert!(matches!(fitted[0].easing, Easing::Custom(..)));
+    }
+
+    #[test]
+    fn test_keep_discontinuous() {
+        let events = vec![
+            LineEvent {
+                kind: LineEventKind::X,
+                start: 0.0,
+                end: 10.0,
+                easing: Easing::Linear,
+                start_beat: beat!(0),
+                end_beat: beat!(1),
+            },
+            LineEvent {
+                kind: LineEventKind::X,
+                start: 50.0,
+                end: 60.0,
+                easing: Easing::Linear,
+                start_beat: beat!(1),
+                end_beat: beat!(2),
+            },
+        ];
+
+        assert_eq!(fit_events(events.clone(), DEFAULT_FIT_TOLERANCE), events);
+    }
+}
@@ -5,7 +5,11 @@ use crate::bpm_list::BpmList;
 use crate::constants::{CANVAS_HEIGHT, CANVAS_WIDTH};
 use crate::easing::Easing;
 use crate::event::LineEventKind;
+use crate::fitting::{fit_events, DEFAULT_FIT_TOLERANCE};
+use crate::line::LinePath;
 use crate::primitive::{Format, PrimitiveChart};
+use crate::report::{ConversionReport, LossKind, NoteProperty};
+use crate::segmentation::{segment, DEFAULT_TOLERANCE};
 use crate::{beat, primitive};
 use anyhow::bail;
 use serde::{Deserialize, Serialize};
@@ -104,7 +108,30 @@ pub struct OfficialChart {
 }
 
 impl Format for OfficialChart {
-    fn into_primitive(self) -> anyhow::Result<PrimitiveChart> {
+    fn into_primitive_with_report(self) -> anyhow::Result<(PrimitiveChart, ConversionReport)> {
+        self.into_primitive_with_tolerance(DEFAULT_FIT_TOLERANCE)
+    }
+
+    fn from_primitive_with_report(
+        phichain: PrimitiveChart,
+    ) -> anyhow::Result<(Self, ConversionReport)>
+    where
+        Self: Sized,
+    {
+        Self::from_primitive_with_tolerance(phichain, DEFAULT_TOLERANCE)
+    }
+}
+
+impl OfficialChart {
+    /// Convert an official chart into a primitive chart
+    ///
+    /// Consecutive linear events are merged back into eased events when an easing fits them within `tolerance`,
+    /// see [`fit_events`]. Primitive charts can represent everything in an official chart, so the report is always
+    /// empty
+    pub fn into_primitive_with_tolerance(
+        self,
+        tolerance: f32,
+    ) -> anyhow::Result<(PrimitiveChart, ConversionReport)> {
         if self.lines.is_empty() {
             bail!("Expect at least one line");
         }
@@ -230,19 +257,36 @@ impl Format for OfficialChart {
                     });
 
             let mut line = primitive::line::Line {
+                name: None,
+                inherit: Default::default(),
                 notes: line
                     .notes_above
                     .iter()
                     .map(|x| create_note(true, x))
                     .chain(line.notes_below.iter().map(|x| create_note(false, x)))
                     .collect(),
-                events: move_event_iter
-                    .chain(rotate_event_iter)
-                    .chain(opacity_event_iter)
-                    .chain(speed_event_iter)
-                    .collect(),
+                events: vec![],
+                children: vec![],
+                extended: Default::default(),
             };
 
+            // official charts cut eased events into linear events, merge them back into eased events to keep the
+            // chart editable
+            let events = move_event_iter
+                .chain(rotate_event_iter)
+                .chain(opacity_event_iter)
+                .collect::<Vec<_>>();
+            for kind in [
+                LineEventKind::X,
+                LineEventKind::Y,
+                LineEventKind::Rotation,
+                LineEventKind::Opacity,
+            ] {
+                let events = events.iter().filter(|x| x.kind == kind).copied().collect();
+                line.events.append(&mut fit_events(events, tolerance));
+            }
+            line.events.extend(speed_event_iter);
+
             let mut speed_events = line
                 .events
                 .iter()
@@ -268,45 +312,49 @@ impl Format for OfficialChart {
             primitive.lines.push(line);
         }
 
-        Ok(primitive)
+        Ok((primitive, Default::default()))
     }
 
-    fn from_primitive(phichain: PrimitiveChart) -> anyhow::Result<Self>
-    where
-        Self: Sized,
-    {
-        fn cut_event(event: primitive::event::LineEvent) -> Vec<primitive::event::LineEvent> {
+    /// Convert a primitive chart into an official chart
+    ///
+    /// Eased events are approximated with linear events, `tolerance` is the maximum value error allowed, see [`segment`]
+    pub fn from_primitive_with_tolerance(
+        phichain: PrimitiveChart,
+        tolerance: f32,
+    ) -> anyhow::Result<(Self, ConversionReport)> {
+        fn cut_event(
+            event: primitive::event::LineEvent,
+            tolerance: f32,
+        ) -> Vec<primitive::event::LineEvent> {
             if matches!(event.easing, Easing::Linear) {
                 return vec![event];
             }
 
-            let mut events = vec![];
-
-            let minimum = beat!(1, 32);
-
-            let mut current_beat = event.start_beat;
-
-            while current_beat <= event.end_beat {
-                let start_value = crate::event::LineEvent::from(event)
-                    .evaluate(current_beat.value())
+            let evaluate = |beat: f32| {
+                [crate::event::LineEvent::from(event)
+                    .evaluate(beat)
                     .value()
-                    .unwrap();
-                let end_value = crate::event::LineEvent::from(event)
-                    .evaluate(current_beat.value() + minimum.value())
-                    .value()
-                    .unwrap();
-                events.push(primitive::event::LineEvent {
-                    kind: event.kind,
-                    start: start_value,
-                    end: end_value,
-                    easing: Easing::Linear,
-                    start_beat: current_beat,
-                    end_beat: current_beat + minimum,
-                });
-                current_beat += minimum;
-            }
+                    .unwrap()]
+            };
 
-            events
+            segment(
+                event.start_beat,
+                event.end_beat,
+                [event.start],
+                [event.end],
+                tolerance,
+                evaluate,
+            )
+            .into_iter()
+            .map(|x| primitive::event::LineEvent {
+                kind: event.kind,
+                start: x.start[0],
+                end: x.end[0],
+                easing: Easing::Linear,
+                start_beat: x.start_beat,
+                end_beat: x.end_beat,
+            })
+            .collect()
         }
 
         let bpm = phichain.bpm_list.0[0].bpm; // take first bpm as base bpm for all lines, normalize all beats using this bpm
@@ -320,7 +368,44 @@ impl Format for OfficialChart {
             lines: vec![],
         };
 
-        for line in phichain.lines {
+        let mut report = ConversionReport::default();
+
+        for (index, line) in phichain.lines.into_iter().enumerate() {
+            let path = LinePath(vec![index]);
+
+            if !line.children.is_empty() {
+                report.push(
+                    LossKind::ChildLines {
+                        count: line.children.len(),
+                    },
+                    path.clone(),
+                    None,
+                );
+            }
+            if !line.extended.is_empty() {
+                report.push(LossKind::ExtendedEvents, path.clone(), None);
+            }
+
+            for event in line.events.iter().filter(|x| x.start != x.end) {
+                let kind = match event.kind {
+                    // speed events only have a single value
+                    LineEventKind::Speed => LossKind::SteppedSpeed,
+                    _ if event.easing == Easing::Linear => continue,
+                    LineEventKind::X | LineEventKind::Y => LossKind::ApproximatedEasing {
+                        kind: event.kind,
+                        easing: event.easing,
+                    },
+                    // rotation and opacity events are connected with linear events
+                    LineEventKind::Rotation | LineEventKind::Opacity => {
+                        LossKind::UnsupportedEasing {
+                            kind: event.kind,
+                            easing: event.easing,
+                        }
+                    }
+                };
+                report.push(kind, path.clone(), Some(event.start_beat));
+            }
+
             let mut official_line = Line {
                 bpm,
                 move_events: vec![],
@@ -375,6 +460,7 @@ impl Format for OfficialChart {
 
             fn process_events<F, T>(
                 line: &primitive::line::Line,
+                tolerance: f32,
                 kind: LineEventKind,
                 mut transform: F,
                 target: &mut Vec<T>,
@@ -391,7 +477,7 @@ impl Format for OfficialChart {
                 );
 
                 for event in events {
-                    let events = cut_event(event);
+                    let events = cut_event(event, tolerance);
                     let mut transformed_events =
                         events.iter().map(&mut transform).collect::<Vec<_>>();
                     target.append(&mut transformed_events);
@@ -400,6 +486,7 @@ impl Format for OfficialChart {
 
             process_events(
                 &line,
+                tolerance,
                 LineEventKind::Rotation,
                 |e| NumericLineEvent {
                     start_time: time(e.start_beat),
@@ -412,6 +499,7 @@ impl Format for OfficialChart {
 
             process_events(
                 &line,
+                tolerance,
                 LineEventKind::Opacity,
                 |e| NumericLineEvent {
                     start_time: time(e.start_beat),
@@ -424,6 +512,7 @@ impl Format for OfficialChart {
 
             process_events(
                 &line,
+                tolerance,
                 LineEventKind::Speed,
                 |e| SpeedEvent {
                     start_time: time(e.start_beat),
@@ -442,11 +531,11 @@ impl Format for OfficialChart {
             for event in &line.events {
                 match event.kind {
                     LineEventKind::X => {
-                        let mut events = cut_event(*event);
+                        let mut events = cut_event(*event, tolerance);
                         x_events.append(&mut events);
                     }
                     LineEventKind::Y => {
-                        let mut events = cut_event(*event);
+                        let mut events = cut_event(*event, tolerance);
                         y_events.append(&mut events);
                     }
                     _ => {}
@@ -524,6 +613,21 @@ impl Format for OfficialChart {
             notes.sort_by_key(|n| n.beat);
 
             for note in notes {
+                if note.fake {
+                    report.push(LossKind::FakeNote, path.clone(), Some(note.beat));
+                    continue;
+                }
+                report.note(
+                    &note,
+                    &path,
+                    &[
+                        NoteProperty::Size,
+                        NoteProperty::Alpha,
+                        NoteProperty::YOffset,
+                        NoteProperty::VisibleTime,
+                    ],
+                );
+
                 let kind = match note.kind {
                     crate::note::NoteKind::Tap => NoteKind::Tap,
                     crate::note::NoteKind::Drag => NoteKind::Drag,
@@ -626,6 +730,79 @@ impl Format for OfficialChart {
             chart.lines.push(official_line);
         }
 
-        Ok(chart)
+        Ok((chart, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bpm_list::BpmList;
+    use crate::note::{Note as PhichainNote, NoteKind as PhichainNoteKind};
+
+    #[test]
+    fn test_report() {
+        let event = |kind, start, end, easing| primitive::event::LineEvent {
+            kind,
+            start_beat: beat!(0),
+            end_beat: beat!(1),
+            start,
+            end,
+            easing,
+        };
+        let note = PhichainNote::new(PhichainNoteKind::Tap, true, beat!(1), 0.0, 1.0);
+
+        let primitive = PrimitiveChart {
+            bpm_list: BpmList::single(120.0),
+            lines: vec![primitive::line::Line {
+                notes: vec![
+                    note,
+                    PhichainNote { size: 2.0, ..note },
+                    PhichainNote { fake: true, ..note },
+                ],
+                events: vec![
+                    event(LineEventKind::X, 0.0, 100.0, Easing::Linear),
+                    event(LineEventKind::Y, 0.0, 100.0, Easing::EaseInSine),
+                    event(
+                        LineEventKind::Rotation,
+                        0.0,
+                        90.0,
+                        Easing::Custom(0.1, 0.2, 0.3, 0.4),
+                    ),
+                    event(LineEventKind::Opacity, 255.0, 255.0, Easing::EaseInSine),
+                    event(LineEventKind::Speed, 10.0, 10.0, Easing::Linear),
+                ],
+                children: vec![Default::default()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let (chart, report) = OfficialChart::from_primitive_with_tolerance(primitive, 0.1).unwrap();
+        assert_eq!(chart.lines[0].notes_above.len(), 2);
+
+        let kinds = report
+            .losses
+            .into_iter()
+            .map(|x| x.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                LossKind::ChildLines { count: 1 },
+                LossKind::ApproximatedEasing {
+                    kind: LineEventKind::Y,
+                    easing: Easing::EaseInSine
+                },
+                LossKind::UnsupportedEasing {
+                    kind: LineEventKind::Rotation,
+                    easing: Easing::Custom(0.1, 0.2, 0.3, 0.4)
+                },
+                LossKind::NoteProperty {
+                    property: NoteProperty::Size
+                },
+                LossKind::FakeNote,
+            ]
+        );
     }
 }
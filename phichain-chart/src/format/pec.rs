@@ -0,0 +1,976 @@
+//! PhiEditor text format (`.pec`)
+//!
+//! A pec chart is a plain text file. The first line is the offset in milliseconds, followed by one command per line:
+//!
+//! - `bp <beat> <bpm>`: bpm changes
+//! - `n1`/`n3`/`n4 <line> <beat> <x> <side> <fake>`: tap, flick and drag notes
+//! - `n2 <line> <start> <end> <x> <side> <fake>`: hold notes
+//! - `# <speed>` and `& <size>`: speed and size of the previous note
+//! - `cv <line> <beat> <speed>`: speed changes
+//! - `cp <line> <beat> <x> <y>`, `cd <line> <beat> <degree>`, `ca <line> <beat> <alpha>`: set position, rotation and opacity
+//! - `cm <line> <start> <end> <x> <y> <easing>`, `cr <line> <start> <end> <degree> <easing>`, `cf <line> <start> <end> <alpha>`:
+//!   move, rotate and fade from the current value
+
+use crate::beat::Beat;
+use crate::bpm_list::{BpmList, BpmPoint};
+use crate::constants::{CANVAS_HEIGHT, CANVAS_WIDTH};
+use crate::easing::Easing;
+use crate::event::LineEventKind;
+use crate::format::rpe::RPE_EASING;
+use crate::line::LinePath;
+use crate::note::NoteKind;
+use crate::primitive::{Format, PrimitiveChart};
+use crate::report::{ConversionReport, LossKind, NoteProperty};
+use crate::{beat, primitive};
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+const PEC_WIDTH: f32 = 2048.0;
+const PEC_HEIGHT: f32 = 1400.0;
+
+/// Ratio between pec speed and phichain speed
+const SPEED_RATIO: f32 = 5.85 / 4.5;
+
+/// PhiEditor delays the music by 150ms
+const OFFSET_DELAY: f32 = 150.0;
+
+/// Lines are created for all indices up to the largest one, larger indices are rejected to keep malformed charts from
+/// allocating huge amounts of lines
+const MAX_LINE_INDEX: i64 = 9999;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PecNoteKind {
+    Tap,
+    Hold { end_beat: f32 },
+    Flick,
+    Drag,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PecNote {
+    kind: PecNoteKind,
+    line: usize,
+    beat: f32,
+    x: f32,
+    above: bool,
+    fake: bool,
+    speed: f32,
+    size: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PecCommand {
+    Speed {
+        line: usize,
+        beat: f32,
+        value: f32,
+    },
+    Position {
+        line: usize,
+        beat: f32,
+        x: f32,
+        y: f32,
+    },
+    Rotation {
+        line: usize,
+        beat: f32,
+        value: f32,
+    },
+    Alpha {
+        line: usize,
+        beat: f32,
+        value: f32,
+    },
+    Move {
+        line: usize,
+        start_beat: f32,
+        end_beat: f32,
+        x: f32,
+        y: f32,
+        easing: i32,
+    },
+    Rotate {
+        line: usize,
+        start_beat: f32,
+        end_beat: f32,
+        value: f32,
+        easing: i32,
+    },
+    Fade {
+        line: usize,
+        start_beat: f32,
+        end_beat: f32,
+        value: f32,
+    },
+}
+
+impl PecCommand {
+    fn line(&self) -> usize {
+        match self {
+            PecCommand::Speed { line, .. }
+            | PecCommand::Position { line, .. }
+            | PecCommand::Rotation { line, .. }
+            | PecCommand::Alpha { line, .. }
+            | PecCommand::Move { line, .. }
+            | PecCommand::Rotate { line, .. }
+            | PecCommand::Fade { line, .. } => *line,
+        }
+    }
+
+    fn start_beat(&self) -> f32 {
+        match self {
+            PecCommand::Speed { beat, .. }
+            | PecCommand::Position { beat, .. }
+            | PecCommand::Rotation { beat, .. }
+            | PecCommand::Alpha { beat, .. } => *beat,
+            PecCommand::Move { start_beat, .. }
+            | PecCommand::Rotate { start_beat, .. }
+            | PecCommand::Fade { start_beat, .. } => *start_beat,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PecChart {
+    offset: f32,
+    bpm_list: Vec<(f32, f32)>,
+    notes: Vec<PecNote>,
+    commands: Vec<PecCommand>,
+}
+
+impl FromStr for PecChart {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().filter(|(_, x)| !x.trim().is_empty());
+
+        let (_, offset) = lines.next().context("Missing offset")?;
+        let mut chart = PecChart {
+            offset: offset.trim().parse().context("Invalid offset")?,
+            ..Default::default()
+        };
+
+        for (index, line) in lines {
+            let tokens = line.split_whitespace().collect::<Vec<_>>();
+            let parse = |i: usize| -> anyhow::Result<f32> {
+                tokens
+                    .get(i)
+                    .with_context(|| format!("Line {}: missing argument {}", index + 1, i))?
+                    .parse::<f32>()
+                    .with_context(|| format!("Line {}: invalid argument {}", index + 1, i))
+            };
+            let line_index = |i: usize| -> anyhow::Result<usize> {
+                let value = tokens
+                    .get(i)
+                    .with_context(|| format!("Line {}: missing argument {}", index + 1, i))?
+                    .parse::<i64>()
+                    .with_context(|| format!("Line {}: invalid line index", index + 1))?;
+                if value < 0 {
+                    bail!("Line {}: negative line index {}", index + 1, value);
+                }
+                if value > MAX_LINE_INDEX {
+                    bail!(
+                        "Line {}: line index {} exceeds the maximum of {}",
+                        index + 1,
+                        value,
+                        MAX_LINE_INDEX
+                    );
+                }
+                Ok(value as usize)
+            };
+
+            match tokens[0] {
+                "bp" => chart.bpm_list.push((parse(1)?, parse(2)?)),
+                "n1" | "n2" | "n3" | "n4" => {
+                    let (kind, rest) = match tokens[0] {
+                        "n1" => (PecNoteKind::Tap, 3),
+                        "n2" => (
+                            PecNoteKind::Hold {
+                                end_beat: parse(3)?,
+                            },
+                            4,
+                        ),
+                        "n3" => (PecNoteKind::Flick, 3),
+                        _ => (PecNoteKind::Drag, 3),
+                    };
+                    chart.notes.push(PecNote {
+                        kind,
+                        line: line_index(1)?,
+                        beat: parse(2)?,
+                        x: parse(rest)?,
+                        above: parse(rest + 1)? == 1.0,
+                        fake: parse(rest + 2)? == 1.0,
+                        speed: 1.0,
+                        size: 1.0,
+                    });
+                }
+                "#" => {
+                    let speed = parse(1)?;
+                    chart
+                        .notes
+                        .last_mut()
+                        .with_context(|| format!("Line {}: `#` without a note", index + 1))?
+                        .speed = speed;
+                }
+                "&" => {
+                    let size = parse(1)?;
+                    chart
+                        .notes
+                        .last_mut()
+                        .with_context(|| format!("Line {}: `&` without a note", index + 1))?
+                        .size = size;
+                }
+                "cv" => chart.commands.push(PecCommand::Speed {
+                    line: line_index(1)?,
+                    beat: parse(2)?,
+                    value: parse(3)?,
+                }),
+                "cp" => chart.commands.push(PecCommand::Position {
+                    line: line_index(1)?,
+                    beat: parse(2)?,
+                    x: parse(3)?,
+                    y: parse(4)?,
+                }),
+                "cd" => chart.commands.push(PecCommand::Rotation {
+                    line: line_index(1)?,
+                    beat: parse(2)?,
+                    value: parse(3)?,
+                }),
+                "ca" => chart.commands.push(PecCommand::Alpha {
+                    line: line_index(1)?,
+                    beat: parse(2)?,
+                    value: parse(3)?,
+                }),
+                "cm" => chart.commands.push(PecCommand::Move {
+                    line: line_index(1)?,
+                    start_beat: parse(2)?,
+                    end_beat: parse(3)?,
+                    x: parse(4)?,
+                    y: parse(5)?,
+                    easing: parse(6)? as i32,
+                }),
+                "cr" => chart.commands.push(PecCommand::Rotate {
+                    line: line_index(1)?,
+                    start_beat: parse(2)?,
+                    end_beat: parse(3)?,
+                    value: parse(4)?,
+                    easing: parse(5)? as i32,
+                }),
+                "cf" => chart.commands.push(PecCommand::Fade {
+                    line: line_index(1)?,
+                    start_beat: parse(2)?,
+                    end_beat: parse(3)?,
+                    value: parse(4)?,
+                }),
+                command => bail!("Line {}: unknown command `{}`", index + 1, command),
+            }
+        }
+
+        Ok(chart)
+    }
+}
+
+impl Display for PecChart {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.offset.round() as i32)?;
+
+        for (beat, bpm) in &self.bpm_list {
+            writeln!(f, "bp {} {}", beat, bpm)?;
+        }
+
+        for note in &self.notes {
+            let side = if note.above { 1 } else { 2 };
+            let fake = if note.fake { 1 } else { 0 };
+            match note.kind {
+                PecNoteKind::Hold { end_beat } => writeln!(
+                    f,
+                    "n2 {} {} {} {} {} {}",
+                    note.line, note.beat, end_beat, note.x, side, fake
+                )?,
+                kind => {
+                    let command = match kind {
+                        PecNoteKind::Tap => "n1",
+                        PecNoteKind::Flick => "n3",
+                        _ => "n4",
+                    };
+                    writeln!(
+                        f,
+                        "{} {} {} {} {} {}",
+                        command, note.line, note.beat, note.x, side, fake
+                    )?
+                }
+            }
+            writeln!(f, "# {}", note.speed)?;
+            writeln!(f, "& {}", note.size)?;
+        }
+
+        for command in &self.commands {
+            match command {
+                PecCommand::Speed { line, beat, value } => {
+                    writeln!(f, "cv {} {} {}", line, beat, value)?
+                }
+                PecCommand::Position { line, beat, x, y } => {
+                    writeln!(f, "cp {} {} {} {}", line, beat, x, y)?
+                }
+                PecCommand::Rotation { line, beat, value } => {
+                    writeln!(f, "cd {} {} {}", line, beat, value)?
+                }
+                PecCommand::Alpha { line, beat, value } => {
+                    writeln!(f, "ca {} {} {}", line, beat, value)?
+                }
+                PecCommand::Move {
+                    line,
+                    start_beat,
+                    end_beat,
+                    x,
+                    y,
+                    easing,
+                } => writeln!(
+                    f,
+                    "cm {} {} {} {} {} {}",
+                    line, start_beat, end_beat, x, y, easing
+                )?,
+                PecCommand::Rotate {
+                    line,
+                    start_beat,
+                    end_beat,
+                    value,
+                    easing,
+                } => writeln!(
+                    f,
+                    "cr {} {} {} {} {}",
+                    line, start_beat, end_beat, value, easing
+                )?,
+                PecCommand::Fade {
+                    line,
+                    start_beat,
+                    end_beat,
+                    value,
+                } => writeln!(f, "cf {} {} {} {}", line, start_beat, end_beat, value)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Convert a float beat in pec to a [`Beat`], snapping to common beat divisions when possible
+fn beat(value: f32) -> Beat {
+    const DENOMINATORS: [u32; 12] = [1, 2, 3, 4, 6, 8, 12, 16, 24, 32, 48, 64];
+
+    for denom in DENOMINATORS {
+        let snapped = crate::beat::utils::attach(value, denom);
+        if (snapped.value() - value).abs() < 1e-3 {
+            return snapped;
+        }
+    }
+
+    Beat::from(value)
+}
+
+/// The easing of an easing id, unknown ids fall back to linear and are recorded into the report
+fn pec_easing(id: i32, line: usize, beat: f32, report: &mut ConversionReport) -> Easing {
+    RPE_EASING.get(id as usize).copied().unwrap_or_else(|| {
+        report.push(
+            LossKind::UnknownEasing { id },
+            LinePath(vec![line]),
+            Some(self::beat(beat)),
+        );
+        Easing::Linear
+    })
+}
+
+fn easing_id(easing: Easing) -> Option<i32> {
+    RPE_EASING
+        .iter()
+        .position(|x| *x == easing)
+        .map(|x| x as i32)
+}
+
+/// A keyframe of a single line property, either setting a value instantly or transiting to a value
+struct Keyframe {
+    start_beat: f32,
+    end_beat: f32,
+    value: f32,
+    easing: Option<Easing>,
+}
+
+/// Turn keyframes of a property into events, transitions start from the value before them
+fn keyframes_into_events(
+    kind: LineEventKind,
+    mut keyframes: Vec<Keyframe>,
+    initial: f32,
+) -> Vec<primitive::event::LineEvent> {
+    keyframes.sort_by(|a, b| a.start_beat.total_cmp(&b.start_beat));
+
+    let mut events = vec![];
+    let mut current = initial;
+    for (i, keyframe) in keyframes.iter().enumerate() {
+        match keyframe.easing {
+            Some(easing) => {
+                events.push(primitive::event::LineEvent {
+                    kind,
+                    start_beat: beat(keyframe.start_beat),
+                    end_beat: beat(keyframe.end_beat),
+                    start: current,
+                    end: keyframe.value,
+                    easing,
+                });
+            }
+            None => {
+                // hold the value until the next keyframe
+                let end_beat = keyframes
+                    .get(i + 1)
+                    .map_or(keyframe.start_beat + 1.0, |x| x.start_beat);
+                events.push(primitive::event::LineEvent {
+                    kind,
+                    start_beat: beat(keyframe.start_beat),
+                    end_beat: beat(end_beat),
+                    start: keyframe.value,
+                    end: keyframe.value,
+                    easing: Easing::Linear,
+                });
+            }
+        }
+        current = keyframe.value;
+    }
+
+    events
+}
+
+impl Format for PecChart {
+    fn into_primitive_with_report(self) -> anyhow::Result<(PrimitiveChart, ConversionReport)> {
+        if self.bpm_list.is_empty() {
+            bail!("Expect at least one bpm point");
+        }
+
+        let line_count = self
+            .notes
+            .iter()
+            .map(|x| x.line)
+            .chain(self.commands.iter().map(|x| x.line()))
+            .max()
+            .map_or(0, |x| x + 1);
+        if line_count as i64 > MAX_LINE_INDEX + 1 {
+            bail!("Line index exceeds the maximum of {}", MAX_LINE_INDEX);
+        }
+
+        let mut primitive = PrimitiveChart {
+            offset: self.offset - OFFSET_DELAY,
+            bpm_list: BpmList::new(
+                self.bpm_list
+                    .iter()
+                    .map(|(b, bpm)| BpmPoint::new(beat(*b), *bpm))
+                    .collect(),
+            ),
+            lines: vec![Default::default(); line_count],
+            ..Default::default()
+        };
+        let mut report = ConversionReport::default();
+
+        for note in &self.notes {
+            let kind = match note.kind {
+                PecNoteKind::Tap => NoteKind::Tap,
+                PecNoteKind::Drag => NoteKind::Drag,
+                PecNoteKind::Flick => NoteKind::Flick,
+                PecNoteKind::Hold { end_beat } => NoteKind::Hold {
+                    hold_beat: beat(end_beat) - beat(note.beat),
+                },
+            };
+            primitive.lines[note.line].notes.push(crate::note::Note {
+                fake: note.fake,
+                size: note.size,
+                ..crate::note::Note::new(
+                    kind,
+                    note.above,
+                    beat(note.beat),
+                    note.x / PEC_WIDTH * CANVAS_WIDTH,
+                    note.speed,
+                )
+            });
+        }
+
+        let x = |x: f32| (x / PEC_WIDTH - 0.5) * CANVAS_WIDTH;
+        let y = |y: f32| (y / PEC_HEIGHT - 0.5) * CANVAS_HEIGHT;
+
+        for (index, line) in primitive.lines.iter_mut().enumerate() {
+            let commands = self.commands.iter().filter(|x| x.line() == index);
+
+            let mut x_keyframes = vec![];
+            let mut y_keyframes = vec![];
+            let mut rotation_keyframes = vec![];
+            let mut opacity_keyframes = vec![];
+            let mut speed_keyframes = vec![];
+
+            for command in commands {
+                let instant = |beat: f32, value: f32| Keyframe {
+                    start_beat: beat,
+                    end_beat: beat,
+                    value,
+                    easing: None,
+                };
+                let transition =
+                    |start_beat: f32, end_beat: f32, value: f32, easing: Easing| Keyframe {
+                        start_beat,
+                        end_beat,
+                        value,
+                        easing: Some(easing),
+                    };
+
+                match *command {
+                    PecCommand::Speed { beat, value, .. } => {
+                        speed_keyframes.push(instant(beat, value / SPEED_RATIO))
+                    }
+                    PecCommand::Position {
+                        beat, x: px, y: py, ..
+                    } => {
+                        x_keyframes.push(instant(beat, x(px)));
+                        y_keyframes.push(instant(beat, y(py)));
+                    }
+                    // negate value for rotation
+                    PecCommand::Rotation { beat, value, .. } => {
+                        rotation_keyframes.push(instant(beat, -value))
+                    }
+                    PecCommand::Alpha { beat, value, .. } => {
+                        opacity_keyframes.push(instant(beat, value))
+                    }
+                    PecCommand::Move {
+                        start_beat,
+                        end_beat,
+                        x: px,
+                        y: py,
+                        easing,
+                        ..
+                    } => {
+                        let easing = pec_easing(easing, index, start_beat, &mut report);
+                        x_keyframes.push(transition(start_beat, end_beat, x(px), easing));
+                        y_keyframes.push(transition(start_beat, end_beat, y(py), easing));
+                    }
+                    PecCommand::Rotate {
+                        start_beat,
+                        end_beat,
+                        value,
+                        easing,
+                        ..
+                    } => rotation_keyframes.push(transition(
+                        start_beat,
+                        end_beat,
+                        -value,
+                        pec_easing(easing, index, start_beat, &mut report),
+                    )),
+                    PecCommand::Fade {
+                        start_beat,
+                        end_beat,
+                        value,
+                        ..
+                    } => opacity_keyframes.push(transition(
+                        start_beat,
+                        end_beat,
+                        value,
+                        Easing::Linear,
+                    )),
+                }
+            }
+
+            line.events = [
+                keyframes_into_events(LineEventKind::X, x_keyframes, 0.0),
+                keyframes_into_events(LineEventKind::Y, y_keyframes, 0.0),
+                keyframes_into_events(LineEventKind::Rotation, rotation_keyframes, 0.0),
+                keyframes_into_events(LineEventKind::Opacity, opacity_keyframes, 0.0),
+                keyframes_into_events(LineEventKind::Speed, speed_keyframes, 10.0),
+            ]
+            .concat();
+        }
+
+        Ok((primitive, report))
+    }
+
+    fn from_primitive_with_report(
+        primitive: PrimitiveChart,
+    ) -> anyhow::Result<(Self, ConversionReport)>
+    where
+        Self: Sized,
+    {
+        let mut pec = PecChart {
+            offset: primitive.offset + OFFSET_DELAY,
+            bpm_list: primitive
+                .bpm_list
+                .0
+                .iter()
+                .map(|x| (x.beat.value(), x.bpm))
+                .collect(),
+            ..Default::default()
+        };
+
+        let minimum = beat!(1, 32);
+
+        /// Cut an event into linear pieces
+        fn cut(
+            event: &primitive::event::LineEvent,
+            minimum: Beat,
+        ) -> Vec<primitive::event::LineEvent> {
+            let evaluated = crate::event::LineEvent::from(*event);
+            let mut pieces = vec![];
+            let mut current = event.start_beat;
+            while current < event.end_beat {
+                let next = (current + minimum).min(event.end_beat);
+                pieces.push(primitive::event::LineEvent {
+                    start_beat: current,
+                    end_beat: next,
+                    start: evaluated.evaluate(current.value()).value().unwrap(),
+                    end: evaluated.evaluate(next.value()).value().unwrap(),
+                    easing: Easing::Linear,
+                    ..*event
+                });
+                current = next;
+            }
+            pieces
+        }
+
+        /// Sort events of a kind, cutting events with easings not supported by `supported`
+        fn events_of(
+            line: &primitive::line::Line,
+            kind: LineEventKind,
+            supported: impl Fn(Easing) -> bool,
+            minimum: Beat,
+        ) -> Vec<primitive::event::LineEvent> {
+            let mut events = line
+                .events
+                .iter()
+                .filter(|x| x.kind == kind)
+                .flat_map(|x| {
+                    if supported(x.easing) || x.start == x.end {
+                        vec![*x]
+                    } else {
+                        cut(x, minimum)
+                    }
+                })
+                .collect::<Vec<_>>();
+            events.sort_by_key(|x| x.start_beat);
+            events
+        }
+
+        let px = |x: f32| (x / CANVAS_WIDTH + 0.5) * PEC_WIDTH;
+        let py = |y: f32| (y / CANVAS_HEIGHT + 0.5) * PEC_HEIGHT;
+
+        let mut report = ConversionReport::default();
+
+        for (index, line) in primitive.lines.iter().enumerate() {
+            let path = LinePath(vec![index]);
+
+            if !line.children.is_empty() {
+                report.push(
+                    LossKind::ChildLines {
+                        count: line.children.len(),
+                    },
+                    path.clone(),
+                    None,
+                );
+            }
+            if !line.extended.is_empty() {
+                report.push(LossKind::ExtendedEvents, path.clone(), None);
+            }
+
+            for event in line.events.iter().filter(|x| x.start != x.end) {
+                let kind = match event.kind {
+                    LineEventKind::Speed => LossKind::SteppedSpeed,
+                    // pec rotation supports the same easings as RPE, fading is always linear
+                    LineEventKind::Rotation if easing_id(event.easing).is_some() => continue,
+                    LineEventKind::Opacity if event.easing == Easing::Linear => continue,
+                    // moving is cut into linear events
+                    _ if event.easing == Easing::Linear => continue,
+                    _ => LossKind::ApproximatedEasing {
+                        kind: event.kind,
+                        easing: event.easing,
+                    },
+                };
+                report.push(kind, path.clone(), Some(event.start_beat));
+            }
+
+            for note in &line.notes {
+                report.note(
+                    note,
+                    &path,
+                    &[
+                        NoteProperty::Alpha,
+                        NoteProperty::YOffset,
+                        NoteProperty::VisibleTime,
+                    ],
+                );
+
+                pec.notes.push(PecNote {
+                    kind: match note.kind {
+                        NoteKind::Tap => PecNoteKind::Tap,
+                        NoteKind::Drag => PecNoteKind::Drag,
+                        NoteKind::Flick => PecNoteKind::Flick,
+                        NoteKind::Hold { hold_beat } => PecNoteKind::Hold {
+                            end_beat: (note.beat + hold_beat).value(),
+                        },
+                    },
+                    line: index,
+                    beat: note.beat.value(),
+                    x: note.x / CANVAS_WIDTH * PEC_WIDTH,
+                    above: note.above,
+                    fake: note.fake,
+                    speed: note.speed,
+                    size: note.size,
+                });
+            }
+
+            // -------- Position --------
+
+            let x_events = events_of(line, LineEventKind::X, |_| false, minimum);
+            let y_events = events_of(line, LineEventKind::Y, |_| false, minimum);
+
+            let evaluate = |events: &[primitive::event::LineEvent], beat: Beat| {
+                let mut value = 0.0;
+                for event in events {
+                    if let Some(x) = crate::event::LineEvent::from(*event)
+                        .evaluate(beat.value())
+                        .value()
+                    {
+                        value = x;
+                    }
+                }
+                value
+            };
+
+            let mut splits = x_events
+                .iter()
+                .chain(y_events.iter())
+                .flat_map(|x| [x.start_beat, x.end_beat])
+                .collect::<Vec<_>>();
+            splits.sort();
+            splits.dedup();
+
+            if let Some(first) = splits.first() {
+                pec.commands.push(PecCommand::Position {
+                    line: index,
+                    beat: first.value(),
+                    x: px(evaluate(&x_events, *first)),
+                    y: py(evaluate(&y_events, *first)),
+                });
+            }
+            for window in splits.windows(2) {
+                let (start, end) = (window[0], window[1]);
+                let (start_x, end_x) = (evaluate(&x_events, start), evaluate(&x_events, end));
+                let (start_y, end_y) = (evaluate(&y_events, start), evaluate(&y_events, end));
+                if start_x == end_x && start_y == end_y {
+                    continue;
+                }
+                pec.commands.push(PecCommand::Move {
+                    line: index,
+                    start_beat: start.value(),
+                    end_beat: end.value(),
+                    x: px(end_x),
+                    y: py(end_y),
+                    easing: 1,
+                });
+            }
+
+            // -------- Rotation, opacity and speed --------
+
+            for event in events_of(
+                line,
+                LineEventKind::Rotation,
+                |x| easing_id(x).is_some(),
+                minimum,
+            ) {
+                // negate value for rotation
+                pec.commands.push(PecCommand::Rotation {
+                    line: index,
+                    beat: event.start_beat.value(),
+                    value: -event.start,
+                });
+                if event.start != event.end {
+                    pec.commands.push(PecCommand::Rotate {
+                        line: index,
+                        start_beat: event.start_beat.value(),
+                        end_beat: event.end_beat.value(),
+                        value: -event.end,
+                        // unwrap: unsupported easings are cut into linear events
+                        easing: easing_id(event.easing).unwrap(),
+                    });
+                }
+            }
+
+            for event in events_of(
+                line,
+                LineEventKind::Opacity,
+                |x| x == Easing::Linear,
+                minimum,
+            ) {
+                pec.commands.push(PecCommand::Alpha {
+                    line: index,
+                    beat: event.start_beat.value(),
+                    value: event.start,
+                });
+                if event.start != event.end {
+                    pec.commands.push(PecCommand::Fade {
+                        line: index,
+                        start_beat: event.start_beat.value(),
+                        end_beat: event.end_beat.value(),
+                        value: event.end,
+                    });
+                }
+            }
+
+            // pec only supports constant speed, cut speed transitions into steps
+            for event in events_of(line, LineEventKind::Speed, |_| false, minimum) {
+                pec.commands.push(PecCommand::Speed {
+                    line: index,
+                    beat: event.start_beat.value(),
+                    value: (event.start + event.end) / 2.0 * SPEED_RATIO,
+                });
+            }
+        }
+
+        pec.commands
+            .sort_by(|a, b| a.start_beat().total_cmp(&b.start_beat()));
+
+        Ok((pec, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHART: &str = "150
+bp 0.000 120.00
+n1 0 1.000 512.00 1 0
+# 1.00
+& 1.00
+n2 0 2.000 4.000 -512.00 2 0
+# 2.00
+& 1.00
+cv 0 0.000 11.70
+cp 0 0.000 1024.00 700.00
+ca 0 0.000 255
+cm 0 1.000 2.000 2048.00 1400.00 1
+cd 0 0.000 0.00
+cr 0 2.000 3.000 90.00 2
+cf 1 0.000 4.000 255
+";
+
+    fn evaluate(events: &[primitive::event::LineEvent], kind: LineEventKind, beat: f32) -> f32 {
+        let mut value = 0.0;
+        for event in events.iter().filter(|x| x.kind == kind) {
+            if let Some(x) = crate::event::LineEvent::from(*event).evaluate(beat).value() {
+                value = x;
+            }
+        }
+        value
+    }
+
+    #[test]
+    fn test_parse() {
+        let chart: PecChart = CHART.parse().unwrap();
+        assert_eq!(chart.offset, 150.0);
+        assert_eq!(chart.bpm_list, vec![(0.0, 120.0)]);
+        assert_eq!(chart.notes.len(), 2);
+        assert_eq!(chart.notes[1].speed, 2.0);
+        assert_eq!(chart.commands.len(), 7);
+    }
+
+    #[test]
+    fn test_into_primitive() {
+        let chart: PecChart = CHART.parse().unwrap();
+        let primitive = chart.into_primitive().unwrap();
+
+        assert_eq!(primitive.offset, 0.0);
+        assert_eq!(primitive.lines.len(), 2);
+
+        let line = &primitive.lines[0];
+        assert_eq!(line.notes[0].beat, beat!(1));
+        assert_eq!(line.notes[0].x, CANVAS_WIDTH / 4.0);
+        assert_eq!(
+            line.notes[1].kind,
+            NoteKind::Hold {
+                hold_beat: beat!(2)
+            }
+        );
+        assert!(!line.notes[1].above);
+
+        let speed = line
+            .events
+            .iter()
+            .find(|x| x.kind == LineEventKind::Speed)
+            .unwrap();
+        assert_eq!(speed.start, 9.0);
+
+        let x = line
+            .events
+            .iter()
+            .filter(|x| x.kind == LineEventKind::X)
+            .collect::<Vec<_>>();
+        assert_eq!(x.len(), 2);
+        assert_eq!(x[1].start, 0.0);
+        assert_eq!(x[1].end, CANVAS_WIDTH / 2.0);
+        assert_eq!(x[1].start_beat, beat!(1));
+        assert_eq!(x[1].end_beat, beat!(2));
+
+        let rotation = line
+            .events
+            .iter()
+            .filter(|x| x.kind == LineEventKind::Rotation)
+            .collect::<Vec<_>>();
+        assert_eq!(rotation[1].end, -90.0);
+        assert_eq!(rotation[1].easing, Easing::EaseOutSine);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let chart: PecChart = CHART.parse().unwrap();
+        let primitive = chart.into_primitive().unwrap();
+        let pec = PecChart::from_primitive(primitive.clone()).unwrap();
+        let reparsed: PecChart = pec.to_string().parse().unwrap();
+        let round_trip = reparsed.into_primitive().unwrap();
+
+        assert_eq!(round_trip.offset, primitive.offset);
+        assert_eq!(round_trip.lines.len(), primitive.lines.len());
+        assert_eq!(round_trip.lines[0].notes, primitive.lines[0].notes);
+
+        for (line, expected) in round_trip.lines.iter().zip(&primitive.lines) {
+            for kind in [
+                LineEventKind::X,
+                LineEventKind::Y,
+                LineEventKind::Rotation,
+                LineEventKind::Opacity,
+                LineEventKind::Speed,
+            ] {
+                for step in 0..=10 {
+                    let beat = step as f32 / 2.0;
+                    let actual = evaluate(&line.events, kind, beat);
+                    let expected = evaluate(&expected.events, kind, beat);
+                    assert!(
+                        (actual - expected).abs() < 0.01,
+                        "{:?} mismatch at beat {}: {} != {}",
+                        kind,
+                        beat,
+                        actual,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_line_index() {
+        assert!("0\nbp 0 120\nn1 -1 1 0 1 0".parse::<PecChart>().is_err());
+        assert!("0\nbp 0 120\ncv -1 0 10".parse::<PecChart>().is_err());
+        assert!("0\nbp 0 120\nn1 100000000 1 0 1 0"
+            .parse::<PecChart>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        assert!("0\nbp 0 120\nxx 0 0".parse::<PecChart>().is_err());
+    }
+}
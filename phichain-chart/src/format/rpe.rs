@@ -1,10 +1,15 @@
 //! Re:PhiEdit json format
 
+use crate::beat;
 use crate::bpm_list::BpmList;
-use crate::easing::Easing;
+use crate::easing::{Easing, Tween};
+use crate::extended::{ExtendedEvent, ExtendedEvents, LineColor};
+use crate::line::{Inheritance, LinePath};
 use crate::primitive;
 use crate::primitive::{Format, PrimitiveChart};
-use num::{Num, Rational32};
+use crate::project::ProjectMeta;
+use crate::report::{ConversionReport, LossKind};
+use num::Rational32;
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use tracing::warn;
@@ -20,7 +25,6 @@ enum NoteKind {
 }
 
 // generated by https://transform.tools/json-to-rust-serde
-// TODO: event layer, parent support
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct Beat(i32, i32, i32);
 
@@ -66,14 +70,244 @@ struct Meta {
     name: String,
     offset: i32,
     song: String,
+    #[serde(default)]
+    illustration: String,
+}
+
+impl From<&Meta> for ProjectMeta {
+    fn from(meta: &Meta) -> Self {
+        Self {
+            composer: meta.composer.clone(),
+            charter: meta.charter.clone(),
+            illustrator: meta.illustration.clone(),
+            name: meta.name.clone(),
+            level: meta.level.clone(),
+        }
+    }
+}
+
+impl From<ProjectMeta> for Meta {
+    fn from(meta: ProjectMeta) -> Self {
+        Self {
+            charter: meta.charter,
+            composer: meta.composer,
+            level: meta.level,
+            name: meta.name,
+            illustration: meta.illustrator,
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct JudgeLine {
+    #[serde(default, rename = "Name")]
+    name: String,
     event_layers: Vec<EventLayer>,
     #[serde(default)]
     notes: Vec<Note>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extended: Option<Extended>,
+    /// Index of the parent line, `-1` for lines without parent
+    #[serde(default = "no_father")]
+    father: i32,
+    /// Whether the rotation of the parent line is added to this line
+    #[serde(default = "rotate_with_father")]
+    rotate_with_father: bool,
+}
+
+impl JudgeLine {
+    /// Unknown easing ids of all events of the line, along with the start beat of the event
+    fn unknown_easings(&self) -> Vec<(i32, crate::beat::Beat)> {
+        fn check<T>(events: &[CommonEvent<T>], unknown: &mut Vec<(i32, crate::beat::Beat)>) {
+            for event in events {
+                if let Some(id) = event.unknown_easing() {
+                    unknown.push((id, event.start_time.clone().into()));
+                }
+            }
+        }
+
+        let mut unknown = vec![];
+        for layer in &self.event_layers {
+            check(&layer.move_xevents, &mut unknown);
+            check(&layer.move_yevents, &mut unknown);
+            check(&layer.rotate_events, &mut unknown);
+            check(&layer.alpha_events, &mut unknown);
+        }
+        if let Some(extended) = &self.extended {
+            check(&extended.scale_xevents, &mut unknown);
+            check(&extended.scale_yevents, &mut unknown);
+            check(&extended.color_events, &mut unknown);
+            check(&extended.text_events, &mut unknown);
+            check(&extended.paint_events, &mut unknown);
+            check(&extended.incline_events, &mut unknown);
+        }
+        unknown
+    }
+}
+
+fn no_father() -> i32 {
+    -1
+}
+
+fn rotate_with_father() -> bool {
+    true
+}
+
+/// Sum events of the same kind from multiple event layers into a single event sequence
+///
+/// RPE adds up the values of all layers. Events are split at every event boundary of every layer, so each layer is
+/// either inside a single event or holding a value within each segment. A segment keeps the original easing if only
+/// one layer is changing over the whole event, stays linear if all changing layers are linear, and is sampled with
+/// linear events otherwise, which is recorded into the report
+fn sum_layers(
+    layers: Vec<Vec<primitive::event::LineEvent>>,
+    path: &LinePath,
+    report: &mut ConversionReport,
+) -> Vec<primitive::event::LineEvent> {
+    let mut layers = layers
+        .into_iter()
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    if layers.len() <= 1 {
+        return layers.pop().unwrap_or_default();
+    }
+
+    for layer in &mut layers {
+        layer.sort_by_key(|x| x.start_beat);
+    }
+    let kind = layers[0][0].kind;
+
+    let mut splits = layers
+        .iter()
+        .flatten()
+        .flat_map(|x| [x.start_beat, x.end_beat])
+        .collect::<Vec<_>>();
+    splits.sort();
+    splits.dedup();
+
+    let value_at = |event: &primitive::event::LineEvent, beat: crate::beat::Beat| {
+        let percent =
+            (beat - event.start_beat).value() / (event.end_beat - event.start_beat).value();
+        event.start.ease_to(event.end, percent, event.easing)
+    };
+
+    let minimum = beat!(1, 32);
+    let mut events = vec![];
+
+    for segment in splits.windows(2) {
+        let (start_beat, end_beat) = (segment[0], segment[1]);
+
+        // the value held by layers not changing within this segment, and the events of the changing layers
+        let mut holding = 0.0;
+        let mut changing = vec![];
+        for layer in &layers {
+            match layer
+                .iter()
+                .find(|x| x.start_beat <= start_beat && x.end_beat >= end_beat)
+            {
+                Some(event) => changing.push(event),
+                None => {
+                    holding += layer
+                        .iter()
+                        .rev()
+                        .find(|x| x.end_beat <= start_beat)
+                        .map_or(0.0, |x| x.end)
+                }
+            }
+        }
+
+        let sum = |beat: crate::beat::Beat| {
+            holding + changing.iter().map(|x| value_at(x, beat)).sum::<f32>()
+        };
+        let event = |start_beat: crate::beat::Beat,
+                     end_beat: crate::beat::Beat,
+                     start: f32,
+                     end: f32,
+                     easing: Easing| {
+            primitive::event::LineEvent {
+                kind,
+                start_beat,
+                end_beat,
+                start,
+                end,
+                easing,
+            }
+        };
+
+        match changing.as_slice() {
+            [single] if single.start_beat == start_beat && single.end_beat == end_beat => {
+                events.push(event(
+                    start_beat,
+                    end_beat,
+                    single.start + holding,
+                    single.end + holding,
+                    single.easing,
+                ));
+            }
+            _ if changing.iter().all(|x| x.easing == Easing::Linear) => {
+                events.push(event(
+                    start_beat,
+                    end_beat,
+                    sum(start_beat),
+                    sum(end_beat),
+                    Easing::Linear,
+                ));
+            }
+            _ => {
+                report.push(
+                    LossKind::SampledLayers { kind },
+                    path.clone(),
+                    Some(start_beat),
+                );
+                let mut current = start_beat;
+                while current < end_beat {
+                    let next = (current + minimum).min(end_beat);
+                    events.push(event(
+                        current,
+                        next,
+                        sum(current),
+                        sum(next),
+                        Easing::Linear,
+                    ));
+                    current = next;
+                }
+            }
+        }
+    }
+
+    events
+}
+
+/// Resolve the parent index of each line, lines with invalid parent or inside a parent cycle are treated as root lines
+fn resolve_fathers(lines: &[JudgeLine], report: &mut ConversionReport) -> Vec<Option<usize>> {
+    let mut fathers = lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| {
+            usize::try_from(line.father)
+                .ok()
+                .filter(|x| *x < lines.len() && *x != index)
+        })
+        .collect::<Vec<_>>();
+
+    for index in 0..fathers.len() {
+        let mut current = fathers[index];
+        for _ in 0..fathers.len() {
+            match current {
+                Some(father) if father == index => {
+                    report.push(LossKind::ParentCycle, LinePath(vec![index]), None);
+                    fathers[index] = None;
+                    break;
+                }
+                Some(father) => current = fathers[father],
+                None => break,
+            }
+        }
+    }
+
+    fathers
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -93,7 +327,7 @@ struct EventLayer {
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct CommonEvent<T: Num> {
+struct CommonEvent<T> {
     bezier: i32,
     #[serde(rename = "bezierPoints")]
     bezier_points: [f32; 4],
@@ -104,6 +338,163 @@ struct CommonEvent<T: Num> {
     start_time: Beat,
 }
 
+impl<T> CommonEvent<T> {
+    fn new(
+        start_time: crate::beat::Beat,
+        end_time: crate::beat::Beat,
+        start: T,
+        end: T,
+        easing: Easing,
+    ) -> Self {
+        let (bezier, bezier_points, easing_type) = match easing {
+            Easing::Custom(a, b, c, d) => (1, [a, b, c, d], 1),
+            _ => (
+                0,
+                [0.0, 0.0, 0.0, 0.0],
+                RPE_EASING
+                    .iter()
+                    .position(|x| *x == easing)
+                    .unwrap_or_else(|| {
+                        warn!("Unknown easing type: {}", easing);
+                        1
+                    }) as i32,
+            ),
+        };
+
+        Self {
+            bezier,
+            bezier_points,
+            easing_type,
+            end,
+            end_time: end_time.into(),
+            start,
+            start_time: start_time.into(),
+        }
+    }
+
+    /// The easing of the event, unknown easing types fall back to linear
+    fn easing(&self) -> Easing {
+        if self.bezier == 1 {
+            let [x1, y1, x2, y2] = self.bezier_points;
+            return Easing::Custom(x1, y1, x2, y2);
+        }
+        RPE_EASING
+            .get(self.easing_type as usize)
+            .copied()
+            .unwrap_or(Easing::Linear)
+    }
+
+    /// The easing type of the event if it is unknown
+    fn unknown_easing(&self) -> Option<i32> {
+        (self.bezier != 1 && RPE_EASING.get(self.easing_type as usize).is_none())
+            .then_some(self.easing_type)
+    }
+}
+
+impl<T> From<CommonEvent<T>> for ExtendedEvent<T> {
+    fn from(event: CommonEvent<T>) -> Self {
+        Self {
+            easing: event.easing(),
+            start_beat: event.start_time.into(),
+            end_beat: event.end_time.into(),
+            start: event.start,
+            end: event.end,
+        }
+    }
+}
+
+impl<T> From<ExtendedEvent<T>> for CommonEvent<T> {
+    fn from(event: ExtendedEvent<T>) -> Self {
+        Self::new(
+            event.start_beat,
+            event.end_beat,
+            event.start,
+            event.end,
+            event.easing,
+        )
+    }
+}
+
+/// Extended events of a line, colors are in RGB
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Extended {
+    #[serde(default, rename = "scaleXEvents")]
+    scale_xevents: Vec<CommonEvent<f32>>,
+    #[serde(default, rename = "scaleYEvents")]
+    scale_yevents: Vec<CommonEvent<f32>>,
+    #[serde(default)]
+    color_events: Vec<CommonEvent<[u8; 3]>>,
+    #[serde(default)]
+    text_events: Vec<CommonEvent<String>>,
+    #[serde(default)]
+    paint_events: Vec<CommonEvent<f32>>,
+    #[serde(default)]
+    incline_events: Vec<CommonEvent<f32>>,
+}
+
+impl From<Extended> for ExtendedEvents {
+    fn from(extended: Extended) -> Self {
+        fn convert<T>(events: Vec<CommonEvent<T>>) -> Vec<ExtendedEvent<T>> {
+            events.into_iter().map(Into::into).collect()
+        }
+
+        Self {
+            scale_x: convert(extended.scale_xevents),
+            scale_y: convert(extended.scale_yevents),
+            color: extended
+                .color_events
+                .into_iter()
+                .map(|event| {
+                    let [r, g, b] = event.start;
+                    let start = LineColor(r, g, b);
+                    let [r, g, b] = event.end;
+                    let end = LineColor(r, g, b);
+                    ExtendedEvent {
+                        easing: event.easing(),
+                        start_beat: event.start_time.into(),
+                        end_beat: event.end_time.into(),
+                        start,
+                        end,
+                    }
+                })
+                .collect(),
+            text: convert(extended.text_events),
+            paint: convert(extended.paint_events),
+            incline: convert(extended.incline_events),
+        }
+    }
+}
+
+impl From<ExtendedEvents> for Extended {
+    fn from(events: ExtendedEvents) -> Self {
+        fn convert<T>(events: Vec<ExtendedEvent<T>>) -> Vec<CommonEvent<T>> {
+            events.into_iter().map(Into::into).collect()
+        }
+
+        Self {
+            scale_xevents: convert(events.scale_x),
+            scale_yevents: convert(events.scale_y),
+            color_events: events
+                .color
+                .into_iter()
+                .map(|event| {
+                    CommonEvent::new(
+                        event.start_beat,
+                        event.end_beat,
+                        [event.start.0, event.start.1, event.start.2],
+                        [event.end.0, event.end.1, event.end.2],
+                        event.easing,
+                    )
+                })
+                .collect(),
+            text_events: convert(events.text),
+            paint_events: convert(events.paint),
+            incline_events: convert(events.incline),
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct SpeedEvent {
@@ -121,13 +512,36 @@ struct Note {
     position_x: f32,
     speed: f32,
     start_time: Beat,
-    size: f32,         // ignored
-    visible_time: f32, // ignored
+    #[serde(default = "default_size")]
+    size: f32,
+    #[serde(default = "default_visible_time")]
+    visible_time: f32,
+    #[serde(default)]
+    is_fake: i32,
+    #[serde(default = "default_alpha")]
+    alpha: i32,
+    #[serde(default)]
+    y_offset: f32,
     #[serde(rename = "type")]
     kind: NoteKind,
 }
 
-static RPE_EASING: [Easing; 30] = [
+/// RPE uses a large visible time for notes which are always visible
+const ALWAYS_VISIBLE: f32 = 999999.0;
+
+fn default_size() -> f32 {
+    1.0
+}
+
+fn default_visible_time() -> f32 {
+    ALWAYS_VISIBLE
+}
+
+fn default_alpha() -> i32 {
+    255
+}
+
+pub(crate) static RPE_EASING: [Easing; 30] = [
     Easing::Linear,
     Easing::Linear,
     Easing::EaseOutSine,
@@ -161,7 +575,9 @@ static RPE_EASING: [Easing; 30] = [
 ];
 
 impl Format for RpeChart {
-    fn into_primitive(self) -> anyhow::Result<PrimitiveChart> {
+    fn into_primitive_with_report(self) -> anyhow::Result<(PrimitiveChart, ConversionReport)> {
+        let mut report = ConversionReport::default();
+
         let mut primitive = PrimitiveChart {
             offset: self.meta.offset as f32,
             bpm_list: BpmList::new(
@@ -171,80 +587,139 @@ impl Format for RpeChart {
                     .collect(),
             ),
             lines: vec![],
+            meta: Some((&self.meta).into()),
             ..Default::default()
         };
 
-        let e = |id: i32| {
-            RPE_EASING.get(id as usize).copied().unwrap_or_else(|| {
-                warn!("Unknown easing type: {}", id);
-                Easing::Linear
-            })
-        };
+        let fathers = resolve_fathers(&self.judge_line_list, &mut report);
 
-        for line in self.judge_line_list {
-            let x_event_iter = line
-                .event_layers
-                .iter()
-                .flat_map(|layer| layer.move_xevents.clone())
-                .map(|event| primitive::event::LineEvent {
-                    kind: crate::event::LineEventKind::X,
-                    start_beat: event.start_time.into(),
-                    end_beat: event.end_time.into(),
-                    start: event.start,
-                    end: event.end,
-                    easing: e(event.easing_type),
-                });
-            let y_event_iter = line
-                .event_layers
-                .iter()
-                .flat_map(|layer| layer.move_yevents.clone())
-                .map(|event| primitive::event::LineEvent {
-                    kind: crate::event::LineEventKind::Y,
-                    start_beat: event.start_time.into(),
-                    end_beat: event.end_time.into(),
-                    start: event.start,
-                    end: event.end,
-                    easing: e(event.easing_type),
-                });
-            let rotate_event_iter = line
-                .event_layers
-                .iter()
-                .flat_map(|layer| layer.rotate_events.clone())
-                .map(|event| primitive::event::LineEvent {
-                    kind: crate::event::LineEventKind::Rotation,
-                    start_beat: event.start_time.into(),
-                    end_beat: event.end_time.into(),
-                    // negate value for rotation
-                    start: -event.start,
-                    end: -event.end,
-                    easing: e(event.easing_type),
-                });
-            let alpha_event_iter = line
-                .event_layers
-                .iter()
-                .flat_map(|layer| layer.alpha_events.clone())
-                .map(|event| primitive::event::LineEvent {
-                    kind: crate::event::LineEventKind::Opacity,
-                    start_beat: event.start_time.into(),
-                    end_beat: event.end_time.into(),
-                    start: event.start as f32,
-                    end: event.end as f32,
-                    easing: e(event.easing_type),
-                });
-            let speed_event_iter = line
-                .event_layers
-                .iter()
-                .flat_map(|layer| layer.speed_events.clone())
-                .map(|event| primitive::event::LineEvent {
-                    kind: crate::event::LineEventKind::Speed,
-                    start_beat: event.start_time.into(),
-                    end_beat: event.end_time.into(),
-                    start: event.start,
-                    end: event.end,
-                    easing: Easing::Linear, // speed events' easing are fixed to be Linear
-                });
+        let mut lines = vec![];
+
+        for (index, line) in self.judge_line_list.into_iter().enumerate() {
+            let path = LinePath(vec![index]);
+            for (id, beat) in line.unknown_easings() {
+                report.push(LossKind::UnknownEasing { id }, path.clone(), Some(beat));
+            }
 
-            primitive.lines.push(primitive::line::Line {
+            let x_events = sum_layers(
+                line.event_layers
+                    .iter()
+                    .map(|layer| {
+                        layer
+                            .move_xevents
+                            .iter()
+                            .cloned()
+                            .map(|event| primitive::event::LineEvent {
+                                kind: crate::event::LineEventKind::X,
+                                easing: event.easing(),
+                                start_beat: event.start_time.into(),
+                                end_beat: event.end_time.into(),
+                                start: event.start,
+                                end: event.end,
+                            })
+                            .collect()
+                    })
+                    .collect(),
+                &path,
+                &mut report,
+            );
+            let y_events = sum_layers(
+                line.event_layers
+                    .iter()
+                    .map(|layer| {
+                        layer
+                            .move_yevents
+                            .iter()
+                            .cloned()
+                            .map(|event| primitive::event::LineEvent {
+                                kind: crate::event::LineEventKind::Y,
+                                easing: event.easing(),
+                                start_beat: event.start_time.into(),
+                                end_beat: event.end_time.into(),
+                                start: event.start,
+                                end: event.end,
+                            })
+                            .collect()
+                    })
+                    .collect(),
+                &path,
+                &mut report,
+            );
+            let rotate_events = sum_layers(
+                line.event_layers
+                    .iter()
+                    .map(|layer| {
+                        layer
+                            .rotate_events
+                            .iter()
+                            .cloned()
+                            .map(|event| primitive::event::LineEvent {
+                                kind: crate::event::LineEventKind::Rotation,
+                                easing: event.easing(),
+                                start_beat: event.start_time.into(),
+                                end_beat: event.end_time.into(),
+                                // negate value for rotation
+                                start: -event.start,
+                                end: -event.end,
+                            })
+                            .collect()
+                    })
+                    .collect(),
+                &path,
+                &mut report,
+            );
+            let alpha_events = sum_layers(
+                line.event_layers
+                    .iter()
+                    .map(|layer| {
+                        layer
+                            .alpha_events
+                            .iter()
+                            .cloned()
+                            .map(|event| primitive::event::LineEvent {
+                                kind: crate::event::LineEventKind::Opacity,
+                                easing: event.easing(),
+                                start_beat: event.start_time.into(),
+                                end_beat: event.end_time.into(),
+                                start: event.start as f32,
+                                end: event.end as f32,
+                            })
+                            .collect()
+                    })
+                    .collect(),
+                &path,
+                &mut report,
+            );
+            let speed_events = sum_layers(
+                line.event_layers
+                    .iter()
+                    .map(|layer| {
+                        layer
+                            .speed_events
+                            .iter()
+                            .cloned()
+                            .map(|event| primitive::event::LineEvent {
+                                kind: crate::event::LineEventKind::Speed,
+                                start_beat: event.start_time.into(),
+                                end_beat: event.end_time.into(),
+                                start: event.start,
+                                end: event.end,
+                                easing: Easing::Linear, // speed events' easing are fixed to be Linear
+                            })
+                            .collect()
+                    })
+                    .collect(),
+                &path,
+                &mut report,
+            );
+
+            lines.push(Some(primitive::line::Line {
+                name: (!line.name.is_empty()).then(|| line.name.clone()),
+                // RPE lines never inherit the opacity of their parent
+                inherit: Inheritance {
+                    opacity: false,
+                    rotation: line.rotate_with_father,
+                },
                 notes: line
                     .notes
                     .iter()
@@ -260,28 +735,69 @@ impl Format for RpeChart {
                             NoteKind::Flick => crate::note::NoteKind::Flick,
                         };
 
-                        crate::note::Note::new(
-                            kind,
-                            note.above == 1,
-                            start_beat,
-                            note.position_x,
-                            note.speed,
-                        )
+                        crate::note::Note {
+                            fake: note.is_fake == 1,
+                            alpha: note.alpha as f32,
+                            size: note.size,
+                            y_offset: note.y_offset,
+                            visible_time: (note.visible_time < ALWAYS_VISIBLE)
+                                .then_some(note.visible_time),
+                            ..crate::note::Note::new(
+                                kind,
+                                note.above == 1,
+                                start_beat,
+                                note.position_x,
+                                note.speed,
+                            )
+                        }
                     })
                     .collect(),
-                events: x_event_iter
-                    .chain(y_event_iter)
-                    .chain(rotate_event_iter)
-                    .chain(alpha_event_iter)
-                    .chain(speed_event_iter)
-                    .collect(),
-            });
+                events: [
+                    x_events,
+                    y_events,
+                    rotate_events,
+                    alpha_events,
+                    speed_events,
+                ]
+                .concat(),
+                children: vec![],
+                extended: line.extended.map(Into::into).unwrap_or_default(),
+            }));
+        }
+
+        let mut children = vec![vec![]; lines.len()];
+        for (index, father) in fathers.iter().enumerate() {
+            if let Some(father) = father {
+                children[*father].push(index);
+            }
+        }
+
+        fn build(
+            index: usize,
+            lines: &mut [Option<primitive::line::Line>],
+            children: &[Vec<usize>],
+        ) -> primitive::line::Line {
+            let mut line = lines[index].take().expect("Line is built twice");
+            line.children = children[index]
+                .iter()
+                .map(|child| build(*child, lines, children))
+                .collect();
+            line
+        }
+
+        for (index, father) in fathers.iter().enumerate() {
+            if father.is_none() {
+                let line = build(index, &mut lines, &children);
+                primitive.lines.push(line);
+            }
         }
 
-        Ok(primitive)
+        Ok((primitive, report))
     }
 
-    fn from_primitive(primitive: PrimitiveChart) -> anyhow::Result<Self>
+    fn from_primitive_with_report(
+        primitive: PrimitiveChart,
+    ) -> anyhow::Result<(Self, ConversionReport)>
     where
         Self: Sized,
     {
@@ -297,23 +813,58 @@ impl Format for RpeChart {
                 .collect(),
             meta: Meta {
                 offset: primitive.offset as i32,
-                ..Default::default()
+                ..primitive.meta.map(Meta::from).unwrap_or_default()
             },
             judge_line_list: vec![],
         };
 
-        let e = |easing: Easing| {
-            RPE_EASING
-                .iter()
-                .position(|x| *x == easing)
-                .unwrap_or_else(|| {
-                    warn!("Unknown easing type: {}", easing);
-                    1
-                })
-        };
+        /// Flatten the line tree in depth-first order, along with the index of each line's parent and its path
+        fn flatten(
+            mut line: primitive::line::Line,
+            father: i32,
+            path: LinePath,
+            lines: &mut Vec<(i32, LinePath, primitive::line::Line)>,
+        ) {
+            let index = lines.len() as i32;
+            let children = std::mem::take(&mut line.children);
+            lines.push((father, path.clone(), line));
+            for (i, child) in children.into_iter().enumerate() {
+                flatten(child, index, path.child(i), lines);
+            }
+        }
+
+        let mut lines = vec![];
+        for (index, line) in primitive.lines.into_iter().enumerate() {
+            flatten(line, -1, LinePath(vec![index]), &mut lines);
+        }
+
+        let mut report = ConversionReport::default();
+
+        for (
+            father,
+            path,
+            primitive::line::Line {
+                name,
+                inherit,
+                notes,
+                events,
+                extended,
+                ..
+            },
+        ) in lines
+        {
+            // RPE lines never inherit the opacity of their parent
+            if father >= 0 && inherit.opacity {
+                report.push(LossKind::OpacityInheritance, path.clone(), None);
+            }
 
-        for primitive::line::Line { notes, events } in primitive.lines {
-            let mut line = JudgeLine::default();
+            let mut line = JudgeLine {
+                name: name.unwrap_or_default(),
+                father,
+                rotate_with_father: inherit.rotation,
+                extended: (!extended.is_empty()).then(|| extended.into()),
+                ..Default::default()
+            };
             for note in notes {
                 let kind = match note.kind {
                     crate::note::NoteKind::Tap => NoteKind::Tap,
@@ -331,31 +882,42 @@ impl Format for RpeChart {
                     position_x: note.x,
                     speed: note.speed,
                     start_time: note.beat.into(),
-                    size: 1.0,
-                    visible_time: 999999.0,
+                    size: note.size,
+                    visible_time: note.visible_time.unwrap_or(ALWAYS_VISIBLE),
+                    is_fake: note.fake as i32,
+                    alpha: note.alpha.round() as i32,
+                    y_offset: note.y_offset,
                     kind,
                 });
             }
             let mut event_layer = EventLayer::default();
             for event in events {
-                let mut rpe_event = CommonEvent {
-                    bezier: 0,
-                    bezier_points: [0.0, 0.0, 0.0, 0.0],
-                    easing_type: 0,
-                    end: event.end,
-                    end_time: event.end_beat.into(),
-                    start: event.start,
-                    start_time: event.start_beat.into(),
+                let supported = match event.kind {
+                    crate::event::LineEventKind::Speed => event.easing == Easing::Linear,
+                    _ => {
+                        matches!(event.easing, Easing::Custom(..))
+                            || RPE_EASING.contains(&event.easing)
+                    }
                 };
-
-                if let Easing::Custom(a, b, c, d) = event.easing {
-                    rpe_event.bezier_points = [a, b, c, d];
-                    rpe_event.bezier = 1;
-                    rpe_event.easing_type = 1;
-                } else {
-                    rpe_event.easing_type = e(event.easing) as i32;
+                if !supported && event.start != event.end {
+                    report.push(
+                        LossKind::UnsupportedEasing {
+                            kind: event.kind,
+                            easing: event.easing,
+                        },
+                        path.clone(),
+                        Some(event.start_beat),
+                    );
                 }
 
+                let rpe_event = CommonEvent::new(
+                    event.start_beat,
+                    event.end_beat,
+                    event.start,
+                    event.end,
+                    event.easing,
+                );
+
                 match event.kind {
                     crate::event::LineEventKind::X => {
                         event_layer.move_xevents.push(rpe_event);
@@ -401,6 +963,328 @@ impl Format for RpeChart {
             rpe.judge_line_list.push(line);
         }
 
-        Ok(rpe)
+        Ok((rpe, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat;
+    use crate::note::{Note as PhichainNote, NoteKind as PhichainNoteKind};
+
+    fn line(x: f32) -> primitive::line::Line {
+        primitive::line::Line {
+            notes: vec![PhichainNote::new(
+                PhichainNoteKind::Tap,
+                true,
+                beat!(1),
+                x,
+                1.0,
+            )],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_father_round_trip() {
+        let mut parent = line(0.0);
+        let mut child = line(1.0);
+        child.children.push(line(2.0));
+        parent.children.push(child);
+        parent.children.push(line(3.0));
+
+        let primitive = PrimitiveChart {
+            lines: vec![line(-1.0), parent],
+            ..Default::default()
+        };
+
+        let rpe = RpeChart::from_primitive(primitive.clone()).unwrap();
+        assert_eq!(
+            rpe.judge_line_list
+                .iter()
+                .map(|x| x.father)
+                .collect::<Vec<_>>(),
+            vec![-1, -1, 1, 2, 1]
+        );
+
+        let round_trip = rpe.into_primitive().unwrap();
+        assert_eq!(round_trip.lines.len(), 2);
+        assert_eq!(round_trip.lines[1].children.len(), 2);
+        assert_eq!(round_trip.lines[1].children[0].children[0].notes[0].x, 2.0);
+        assert_eq!(round_trip.lines[1].children[1].notes[0].x, 3.0);
+    }
+
+    #[test]
+    fn test_rotate_with_father() {
+        let mut child = line(1.0);
+        child.inherit.rotation = false;
+        let primitive = PrimitiveChart {
+            lines: vec![primitive::line::Line {
+                children: vec![child],
+                ..line(0.0)
+            }],
+            ..Default::default()
+        };
+
+        let rpe = RpeChart::from_primitive(primitive).unwrap();
+        assert!(rpe.judge_line_list[0].rotate_with_father);
+        assert!(!rpe.judge_line_list[1].rotate_with_father);
+
+        let round_trip = rpe.into_primitive().unwrap();
+        assert!(round_trip.lines[0].inherit.rotation);
+        assert!(!round_trip.lines[0].children[0].inherit.rotation);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut child = line(1.0);
+        child.inherit.opacity = true;
+        child.events = vec![
+            primitive::event::LineEvent {
+                easing: Easing::Custom(0.1, 0.2, 0.3, 0.4),
+                ..event(beat!(0), beat!(1), 0.0, 10.0, Easing::Linear)
+            },
+            event(beat!(1), beat!(2), 10.0, 0.0, Easing::EaseInOutExpo),
+        ];
+        let primitive = PrimitiveChart {
+            lines: vec![primitive::line::Line {
+                children: vec![child],
+                ..line(0.0)
+            }],
+            ..Default::default()
+        };
+
+        let (mut rpe, report) = RpeChart::from_primitive_with_report(primitive).unwrap();
+        assert_eq!(
+            report
+                .losses
+                .iter()
+                .map(|x| (x.kind.clone(), x.line.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (LossKind::OpacityInheritance, LinePath(vec![0, 0])),
+                (
+                    LossKind::UnsupportedEasing {
+                        kind: crate::event::LineEventKind::X,
+                        easing: Easing::EaseInOutExpo
+                    },
+                    LinePath(vec![0, 0])
+                ),
+            ]
+        );
+
+        rpe.judge_line_list[0].event_layers[0]
+            .move_xevents
+            .push(CommonEvent {
+                easing_type: 100,
+                start_time: Beat(0, 0, 1),
+                end_time: Beat(1, 0, 1),
+                ..Default::default()
+            });
+        let (primitive, report) = rpe.into_primitive_with_report().unwrap();
+        // bezier easings survive the round trip
+        assert_eq!(
+            primitive.lines[0].children[0].events[0].easing,
+            Easing::Custom(0.1, 0.2, 0.3, 0.4)
+        );
+        assert_eq!(report.losses.len(), 1);
+        assert_eq!(report.losses[0].kind, LossKind::UnknownEasing { id: 100 });
+    }
+
+    #[test]
+    fn test_father_cycle() {
+        let line = |father: i32| JudgeLine {
+            father,
+            ..Default::default()
+        };
+        let rpe = RpeChart {
+            judge_line_list: vec![line(1), line(0), line(5), line(1)],
+            ..Default::default()
+        };
+
+        let mut report = ConversionReport::default();
+        let fathers = resolve_fathers(&rpe.judge_line_list, &mut report);
+        assert_eq!(fathers, vec![None, Some(0), None, Some(1)]);
+        assert_eq!(report.losses.len(), 1);
+        assert_eq!(report.losses[0].kind, LossKind::ParentCycle);
+        assert_eq!(report.losses[0].line, LinePath(vec![0]));
+
+        let primitive = rpe.into_primitive().unwrap();
+        assert_eq!(primitive.lines.len(), 2);
+        assert_eq!(primitive.lines[0].children[0].children.len(), 1);
+    }
+
+    fn event(
+        start_beat: crate::beat::Beat,
+        end_beat: crate::beat::Beat,
+        start: f32,
+        end: f32,
+        easing: Easing,
+    ) -> primitive::event::LineEvent {
+        primitive::event::LineEvent {
+            kind: crate::event::LineEventKind::X,
+            start_beat,
+            end_beat,
+            start,
+            end,
+            easing,
+        }
+    }
+
+    fn evaluate(events: &[primitive::event::LineEvent], beat: f32) -> f32 {
+        let mut value = 0.0;
+        for event in events {
+            if let Some(x) = crate::event::LineEvent::from(*event).evaluate(beat).value() {
+                value = x;
+            }
+        }
+        value
+    }
+
+    #[test]
+    fn test_sum_layers() {
+        let layers = vec![
+            vec![
+                event(beat!(0), beat!(2), 0.0, 100.0, Easing::Linear),
+                event(beat!(3), beat!(4), 100.0, 0.0, Easing::EaseInSine),
+            ],
+            vec![event(beat!(1), beat!(4), 10.0, 40.0, Easing::Linear)],
+            vec![],
+        ];
+
+        let mut report = ConversionReport::default();
+        let summed = sum_layers(layers.clone(), &LinePath(vec![0]), &mut report);
+
+        for step in 0..=50 {
+            let beat = step as f32 / 10.0;
+            let expected = layers.iter().map(|x| evaluate(x, beat)).sum::<f32>();
+            assert!(
+                (evaluate(&summed, beat) - expected).abs() < 1.0,
+                "mismatch at beat {}",
+                beat
+            );
+        }
+
+        // the sine segment is the only non-linear part and gets sampled
+        assert_eq!(
+            summed
+                .iter()
+                .filter(|x| x.start_beat >= beat!(3) && x.end_beat <= beat!(4))
+                .count(),
+            32
+        );
+        assert_eq!(
+            report.losses.iter().map(|x| x.beat).collect::<Vec<_>>(),
+            vec![Some(beat!(3))]
+        );
+    }
+
+    #[test]
+    fn test_sum_layers_keeps_easing() {
+        let layers = vec![
+            vec![event(beat!(0), beat!(1), 0.0, 10.0, Easing::Linear)],
+            vec![event(beat!(1), beat!(2), 0.0, 100.0, Easing::EaseOutQuad)],
+        ];
+
+        let mut report = ConversionReport::default();
+        let summed = sum_layers(layers, &LinePath(vec![0]), &mut report);
+        assert!(report.is_lossless());
+        assert_eq!(
+            summed,
+            vec![
+                event(beat!(0), beat!(1), 0.0, 10.0, Easing::Linear),
+                event(beat!(1), beat!(2), 10.0, 110.0, Easing::EaseOutQuad),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extended_round_trip() {
+        let extended = ExtendedEvents {
+            scale_x: vec![ExtendedEvent {
+                start_beat: beat!(0),
+                end_beat: beat!(1),
+                start: 1.0,
+                end: 2.0,
+                easing: Easing::EaseInQuad,
+            }],
+            color: vec![ExtendedEvent {
+                start_beat: beat!(1),
+                end_beat: beat!(2),
+                start: LineColor(255, 0, 0),
+                end: LineColor(0, 0, 255),
+                easing: Easing::Linear,
+            }],
+            text: vec![ExtendedEvent {
+                start_beat: beat!(2),
+                end_beat: beat!(3),
+                start: "phi".to_owned(),
+                end: "chain".to_owned(),
+                easing: Easing::Linear,
+            }],
+            ..Default::default()
+        };
+
+        let primitive = PrimitiveChart {
+            lines: vec![primitive::line::Line {
+                extended: extended.clone(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let json = serde_json::to_value(RpeChart::from_primitive(primitive).unwrap()).unwrap();
+        let line = &json["judgeLineList"][0];
+        assert_eq!(
+            line["extended"]["colorEvents"][0]["start"],
+            serde_json::json!([255, 0, 0])
+        );
+        assert_eq!(line["extended"]["textEvents"][0]["end"], "chain");
+
+        let rpe: RpeChart = serde_json::from_value(json).unwrap();
+        assert_eq!(rpe.into_primitive().unwrap().lines[0].extended, extended);
+    }
+
+    #[test]
+    fn test_meta_and_names() {
+        let meta = ProjectMeta {
+            composer: "composer".to_owned(),
+            charter: "charter".to_owned(),
+            illustrator: "illustrator".to_owned(),
+            name: "name".to_owned(),
+            level: "IN Lv.15".to_owned(),
+        };
+        let primitive = PrimitiveChart {
+            lines: vec![primitive::line::Line {
+                name: Some("main".to_owned()),
+                ..Default::default()
+            }],
+            meta: Some(meta.clone()),
+            ..Default::default()
+        };
+
+        let rpe = RpeChart::from_primitive(primitive).unwrap();
+        assert_eq!(rpe.meta.charter, "charter");
+        assert_eq!(rpe.judge_line_list[0].name, "main");
+
+        let phichain =
+            crate::serialization::PhichainChart::from_primitive(rpe.into_primitive().unwrap())
+                .unwrap();
+        assert_eq!(phichain.lines[0].line.name, "main");
+
+        let primitive = phichain.into_primitive().unwrap();
+        assert_eq!(primitive.lines[0].name.as_deref(), Some("main"));
+        assert_eq!(
+            RpeChart::from_primitive(PrimitiveChart {
+                meta: Some(meta.clone()),
+                ..primitive
+            })
+            .unwrap()
+            .into_primitive()
+            .unwrap()
+            .meta,
+            Some(meta)
+        );
     }
 }
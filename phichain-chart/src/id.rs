@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+/// A persistent identifier of a line, a note or an event
+///
+/// Ids are unique across all lines, notes and events of a chart, and are kept when the chart is saved and loaded again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Id(pub u64);
+
+impl Display for Id {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Give every missing id a new one, also for ids already used earlier in the sequence
+///
+/// New ids count up from the largest existing id, existing unique ids are never changed
+pub fn assign_ids<'a>(ids: impl IntoIterator<Item = &'a mut Option<Id>>) {
+    let ids = ids.into_iter().collect::<Vec<_>>();
+
+    let mut next = ids
+        .iter()
+        .filter_map(|x| x.map(|id| id.0 + 1))
+        .max()
+        .unwrap_or(0);
+    let mut used = HashSet::new();
+
+    for id in ids {
+        if !id.is_some_and(|x| used.insert(x)) {
+            *id = Some(Id(next));
+            next += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign_ids() {
+        let mut ids = vec![None, Some(Id(3)), None, Some(Id(1)), Some(Id(3))];
+        assign_ids(ids.iter_mut());
+        assert_eq!(
+            ids,
+            vec![
+                Some(Id(4)),
+                Some(Id(3)),
+                Some(Id(5)),
+                Some(Id(1)),
+                Some(Id(6))
+            ]
+        );
+
+        let mut ids = vec![None, None];
+        assign_ids(ids.iter_mut());
+        assert_eq!(ids, vec![Some(Id(0)), Some(Id(1))]);
+    }
+}
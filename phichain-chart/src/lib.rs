@@ -1,13 +1,24 @@
 pub mod beat;
+pub mod binary;
 pub mod bpm_list;
+pub mod canonical;
 pub mod constants;
+pub mod diff;
 pub mod easing;
 pub mod event;
+pub mod extended;
+pub mod fitting;
 pub mod format;
+pub mod id;
 pub mod line;
 pub mod migration;
 pub mod note;
 pub mod offset;
+pub mod pez;
 pub mod primitive;
 pub mod project;
+pub mod report;
+pub mod segmentation;
 pub mod serialization;
+pub mod statistics;
+pub mod validation;
@@ -1,19 +1,77 @@
+use crate::id::Id;
 use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "bevy", derive(bevy::prelude::Component))]
 pub struct Line {
+    /// The persistent id of the line, [`None`] if not assigned yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Id>,
     pub name: String,
+    /// What this line inherits from its parent line, besides the position
+    #[serde(default)]
+    pub inherit: Inheritance,
 }
 
 impl Default for Line {
     fn default() -> Self {
         Self {
+            id: None,
             name: "Unnamed Line".to_owned(),
+            inherit: Default::default(),
         }
     }
 }
 
+/// What a child line inherits from its parent line
+///
+/// The position of a child line is always relative to its parent, including the parent's rotation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Inheritance {
+    /// The opacity of the parent is multiplied into the opacity of the line, with `255` being fully opaque
+    pub opacity: bool,
+    /// The rotation of the parent is added to the rotation of the line, like `rotateWithFather` in RPE
+    pub rotation: bool,
+}
+
+impl Default for Inheritance {
+    fn default() -> Self {
+        Self {
+            opacity: false,
+            rotation: true,
+        }
+    }
+}
+
+/// The location of a line in the line hierarchy of a chart
+///
+/// Each element is the index of a line within its parent, starting from the root lines
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct LinePath(pub Vec<usize>);
+
+impl LinePath {
+    /// Get the path of the `index`-th child of this line
+    pub fn child(&self, index: usize) -> Self {
+        let mut path = self.0.clone();
+        path.push(index);
+        Self(path)
+    }
+
+    /// Get the depth of this line, root lines have a depth of `0`
+    pub fn depth(&self) -> usize {
+        self.0.len().saturating_sub(1)
+    }
+}
+
+impl Display for LinePath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let segments = self.0.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        write!(f, "{}", segments.join("/"))
+    }
+}
+
 #[cfg(feature = "bevy")]
 #[derive(bevy::prelude::Component, Debug, Default)]
 pub struct LinePosition(pub bevy::prelude::Vec2);
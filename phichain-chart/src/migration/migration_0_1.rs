@@ -36,6 +36,25 @@ impl Migration for Migration0To1 {
 
         Ok(chart)
     }
+
+    fn downgrade(new: &Value) -> anyhow::Result<Value> {
+        let mut chart = new.clone();
+        for line in chart
+            .get_mut("lines")
+            .context("Failed to get lines")?
+            .as_array_mut()
+            .context("`lines` is not an array")?
+        {
+            *line = json!([line["notes"], line["events"]]);
+        }
+
+        chart
+            .as_object_mut()
+            .context("chart is not an object")?
+            .remove("format");
+
+        Ok(chart)
+    }
 }
 
 #[cfg(test)]
@@ -159,5 +178,6 @@ mod tests {
         });
 
         assert_eq!(Migration0To1::migrate(&old).unwrap(), new);
+        assert_eq!(Migration0To1::downgrade(&new).unwrap(), old);
     }
 }
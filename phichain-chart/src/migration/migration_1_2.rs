@@ -1,4 +1,4 @@
-use crate::migration::Migration;
+use crate::migration::{Losses, Migration};
 use anyhow::Context;
 use serde_json::{json, Value};
 
@@ -29,6 +29,31 @@ impl Migration for Migration1To2 {
 
         Ok(chart)
     }
+
+    fn downgrade(new: &Value) -> anyhow::Result<Value> {
+        let mut chart = new.clone();
+        let mut renamed = 0;
+        for line in chart
+            .get_mut("lines")
+            .context("Failed to get lines")?
+            .as_array_mut()
+            .context("`lines` is not an array")?
+        {
+            let line = line.as_object_mut().context("line is not an object")?;
+            // upgrading names all lines `Unnamed Line`, so other names can not be kept
+            if line.remove("name").is_some_and(|x| x != "Unnamed Line") {
+                renamed += 1;
+            }
+        }
+
+        let mut losses = Losses::default();
+        losses.add("line name(s)", renamed);
+        losses.check(2)?;
+
+        chart["format"] = json!(1);
+
+        Ok(chart)
+    }
 }
 
 #[cfg(test)]
@@ -154,5 +179,23 @@ mod tests {
         });
 
         assert_eq!(Migration1To2::migrate(&old).unwrap(), new);
+        assert_eq!(Migration1To2::downgrade(&new).unwrap(), old);
+    }
+
+    #[test]
+    fn test_downgrade_named_lines() {
+        let new = json!({
+          "format": 2,
+          "lines": [
+            { "name": "Unnamed Line", "notes": [], "events": [] },
+            { "name": "Main", "notes": [], "events": [] }
+          ]
+        });
+
+        let err = Migration1To2::downgrade(&new).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Downgrading the chart from format 2 to 1 would lose:\n  - 1 line name(s)"
+        );
     }
 }
@@ -97,6 +97,101 @@ impl Migration for Migration2To3 {
 
         Ok(chart)
     }
+
+    fn downgrade(new: &Value) -> anyhow::Result<Value> {
+        let mut chart = new.clone();
+        for line in chart
+            .get_mut("lines")
+            .context("Failed to get lines")?
+            .as_array_mut()
+            .context("`lines` is not an array")?
+        {
+            for event in line["events"]
+                .as_array_mut()
+                .context("`line.events` is not an array")?
+            {
+                event["kind"] = json!(event["kind"]
+                    .as_str()
+                    .context("event kind is not string")?
+                    .from_case(Case::Snake)
+                    .to_case(Case::Pascal));
+
+                // constant events are linear transitions from the value to itself
+                let (start, end, easing) = match event["value"] {
+                    Value::Object(ref value) if value.contains_key("transition") => {
+                        let transition = &value["transition"];
+                        let easing = match transition["easing"] {
+                            Value::String(ref s) => {
+                                json!(s.from_case(Case::Snake).to_case(Case::Pascal))
+                            }
+                            Value::Object(_) => {
+                                json!({
+                                    "Custom": transition["easing"]["custom"],
+                                })
+                            }
+                            ref other => {
+                                bail!("expected an object or a string as easing, got: {:?}", other)
+                            }
+                        };
+                        (
+                            transition["start"].clone(),
+                            transition["end"].clone(),
+                            easing,
+                        )
+                    }
+                    Value::Object(ref value) if value.contains_key("constant") => (
+                        value["constant"].clone(),
+                        value["constant"].clone(),
+                        json!("Linear"),
+                    ),
+                    ref other => {
+                        bail!(
+                            "expected a transition or a constant value, got: {:?}",
+                            other
+                        )
+                    }
+                };
+
+                let event = event.as_object_mut().context("event is not an object")?;
+                event.remove("value");
+                event.insert("start".to_owned(), start);
+                event.insert("end".to_owned(), end);
+                event.insert("easing".to_owned(), easing);
+            }
+
+            for note in line["notes"]
+                .as_array_mut()
+                .context("`line.notes` is not an array")?
+            {
+                let old_kind = match note["kind"] {
+                    Value::String(ref s) => {
+                        json!(s.from_case(Case::Snake).to_case(Case::Pascal))
+                    }
+                    Value::Object(_) => {
+                        let hold_beat = &note["kind"]["hold"]["hold_beat"];
+
+                        json!({
+                            "Hold": {
+                                "hold_beat": hold_beat,
+                            },
+                        })
+                    }
+                    ref other => {
+                        bail!(
+                            "expected an object or a string as note kind, got: {:?}",
+                            other
+                        )
+                    }
+                };
+
+                note["kind"] = old_kind;
+            }
+        }
+
+        chart["format"] = json!(2);
+
+        Ok(chart)
+    }
 }
 
 #[cfg(test)]
@@ -253,5 +348,47 @@ mod tests {
         });
 
         assert_eq!(Migration2To3::migrate(&old).unwrap(), new);
+        assert_eq!(Migration2To3::downgrade(&new).unwrap(), old);
+    }
+
+    #[test]
+    fn test_downgrade_constant_event() {
+        let new = json!({
+          "format": 3,
+          "lines": [
+            {
+              "notes": [],
+              "events": [
+                {
+                  "kind": "opacity",
+                  "start_beat": [0, 0, 1],
+                  "end_beat": [1, 0, 1],
+                  "value": { "constant": 255.0 },
+                }
+              ]
+            }
+          ]
+        });
+
+        let old = json!({
+          "format": 2,
+          "lines": [
+            {
+              "notes": [],
+              "events": [
+                {
+                  "kind": "Opacity",
+                  "start": 255.0,
+                  "end": 255.0,
+                  "start_beat": [0, 0, 1],
+                  "end_beat": [1, 0, 1],
+                  "easing": "Linear",
+                }
+              ]
+            }
+          ]
+        });
+
+        assert_eq!(Migration2To3::downgrade(&new).unwrap(), old);
     }
 }
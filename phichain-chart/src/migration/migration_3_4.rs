@@ -1,4 +1,4 @@
-use crate::migration::Migration;
+use crate::migration::{Losses, Migration};
 use anyhow::Context;
 use serde_json::{json, Value};
 
@@ -11,8 +11,25 @@ use serde_json::{json, Value};
 /// # Modifications
 ///
 /// - Added a empty `children` array to all lines
+///
+/// Note properties (`fake`, `alpha`, `size`, `y_offset` and `visible_time`), extended events and inheritance flags
+/// of lines were added later without a format bump, downgrading removes them as well
 pub struct Migration3To4;
 
+/// Note properties unknown to format `3`
+const NOTE_PROPERTIES: [&str; 5] = ["fake", "alpha", "size", "y_offset", "visible_time"];
+
+/// Whether a note property has its default value, missing properties are default
+fn is_default_property(key: &str, value: &Value) -> bool {
+    match key {
+        "fake" => value == &Value::Bool(false),
+        "alpha" => value.as_f64() == Some(255.0),
+        "size" => value.as_f64() == Some(1.0),
+        "y_offset" => value.as_f64() == Some(0.0),
+        _ => value.is_null(),
+    }
+}
+
 impl Migration for Migration3To4 {
     fn migrate(old: &Value) -> anyhow::Result<Value> {
         let mut chart = old.clone();
@@ -29,6 +46,59 @@ impl Migration for Migration3To4 {
 
         Ok(chart)
     }
+
+    fn downgrade(new: &Value) -> anyhow::Result<Value> {
+        let mut chart = new.clone();
+        let mut children = 0;
+        let mut notes = 0;
+        let mut extended = 0;
+        for line in chart
+            .get_mut("lines")
+            .context("Failed to get lines")?
+            .as_array_mut()
+            .context("`lines` is not an array")?
+        {
+            let line = line.as_object_mut().context("line is not an object")?;
+            if let Some(Value::Array(x)) = line.remove("children") {
+                children += x.len();
+            }
+            // the inheritance flags only apply to child lines, which are counted above
+            line.remove("inherit");
+            if line.remove("extended").is_some_and(|x| x != json!({})) {
+                extended += 1;
+            }
+
+            for note in line
+                .get_mut("notes")
+                .and_then(|x| x.as_array_mut())
+                .context("`line.notes` is not an array")?
+            {
+                let note = note.as_object_mut().context("note is not an object")?;
+                let mut lossy = false;
+                for key in NOTE_PROPERTIES {
+                    if let Some(value) = note.remove(key) {
+                        lossy |= !is_default_property(key, &value);
+                    }
+                }
+                if lossy {
+                    notes += 1;
+                }
+            }
+        }
+
+        let mut losses = Losses::default();
+        losses.add("child line(s)", children);
+        losses.add(
+            "note(s) with fake, alpha, size, y offset or visible time",
+            notes,
+        );
+        losses.add("line(s) with extended events", extended);
+        losses.check(4)?;
+
+        chart["format"] = json!(3);
+
+        Ok(chart)
+    }
 }
 
 #[cfg(test)]
@@ -194,5 +264,62 @@ mod tests {
         });
 
         assert_eq!(Migration3To4::migrate(&old).unwrap(), new);
+        assert_eq!(Migration3To4::downgrade(&new).unwrap(), old);
+    }
+
+    #[test]
+    fn test_downgrade_child_lines() {
+        let new = json!({
+          "format": 4,
+          "lines": [
+            {
+              "name": "Parent",
+              "notes": [],
+              "events": [],
+              "children": [
+                { "name": "Child", "notes": [], "events": [], "children": [] },
+                { "name": "Child", "notes": [], "events": [], "children": [] }
+              ]
+            }
+          ]
+        });
+
+        let err = Migration3To4::downgrade(&new).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Downgrading the chart from format 4 to 3 would lose:\n  - 2 child line(s)"
+        );
+    }
+
+    #[test]
+    fn test_downgrade_note_properties() {
+        use crate::beat;
+        use crate::migration::migrate_to;
+        use crate::note::{Note, NoteKind};
+        use crate::serialization::{LineWrapper, PhichainChart};
+
+        let chart = |fake: bool| {
+            let note = Note {
+                fake,
+                ..Note::new(NoteKind::Tap, true, beat!(1), 0.0, 1.0)
+            };
+            let line = LineWrapper {
+                notes: vec![note],
+                ..Default::default()
+            };
+            serde_json::to_value(PhichainChart::new(0.0, Default::default(), vec![line])).unwrap()
+        };
+
+        let downgraded = migrate_to(&chart(false), 3).unwrap();
+        assert_eq!(
+            downgraded["lines"][0]["notes"][0],
+            json!({ "kind": "tap", "above": true, "beat": [1, 0, 1], "x": 0.0, "speed": 1.0 })
+        );
+
+        let err = migrate_to(&chart(true), 3).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Downgrading the chart from format 4 to 3 would lose:\n  - 1 note(s) with fake, alpha, size, y offset or visible time"
+        );
     }
 }
@@ -0,0 +1,247 @@
+use crate::migration::Migration;
+use anyhow::Context;
+use serde_json::{json, Value};
+
+/// Migration from format `4` to `5`
+///
+/// # Changes
+///
+/// - Added optional persistent ids for lines, notes and events
+///
+/// # Modifications
+///
+/// - Assigned ids counting up from `0` to all lines, notes and events, a line comes before its notes, events and children
+pub struct Migration4To5;
+
+fn assign_line(line: &mut Value, next: &mut u64) -> anyhow::Result<()> {
+    let mut assign = |x: &mut Value| {
+        x["id"] = json!(*next);
+        *next += 1;
+    };
+
+    assign(line);
+    for note in line["notes"]
+        .as_array_mut()
+        .context("`line.notes` is not an array")?
+    {
+        assign(note);
+    }
+    for event in line["events"]
+        .as_array_mut()
+        .context("`line.events` is not an array")?
+    {
+        assign(event);
+    }
+
+    for child in line["children"]
+        .as_array_mut()
+        .context("`line.children` is not an array")?
+    {
+        assign_line(child, next)?;
+    }
+
+    Ok(())
+}
+
+fn remove_line_ids(line: &mut Value) -> anyhow::Result<()> {
+    let remove = |x: &mut Value| -> anyhow::Result<()> {
+        x.as_object_mut()
+            .context("expected an object for line, note or event")?
+            .remove("id");
+        Ok(())
+    };
+
+    remove(line)?;
+    for note in line["notes"]
+        .as_array_mut()
+        .context("`line.notes` is not an array")?
+    {
+        remove(note)?;
+    }
+    for event in line["events"]
+        .as_array_mut()
+        .context("`line.events` is not an array")?
+    {
+        remove(event)?;
+    }
+
+    for child in line["children"]
+        .as_array_mut()
+        .context("`line.children` is not an array")?
+    {
+        remove_line_ids(child)?;
+    }
+
+    Ok(())
+}
+
+impl Migration for Migration4To5 {
+    fn migrate(old: &Value) -> anyhow::Result<Value> {
+        let mut chart = old.clone();
+        let mut next = 0;
+        for line in chart
+            .get_mut("lines")
+            .context("Failed to get lines")?
+            .as_array_mut()
+            .context("`lines` is not an array")?
+        {
+            assign_line(line, &mut next)?;
+        }
+
+        chart["format"] = json!(5);
+
+        Ok(chart)
+    }
+
+    fn downgrade(new: &Value) -> anyhow::Result<Value> {
+        let mut chart = new.clone();
+        // ids are optional, dropping them does not change how the chart plays
+        // note properties, extended events and inheritance flags are kept, format 4 carries them as well
+        for line in chart
+            .get_mut("lines")
+            .context("Failed to get lines")?
+            .as_array_mut()
+            .context("`lines` is not an array")?
+        {
+            remove_line_ids(line)?;
+        }
+
+        chart["format"] = json!(4);
+
+        Ok(chart)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_migration_4_to_5() {
+        let old = json!({
+          "format": 4,
+          "offset": 0.0,
+          "bpm_list": [
+            {
+              "beat": [0, 0, 1],
+              "bpm": 120.0,
+              "time": 0.0
+            }
+          ],
+          "lines": [
+            {
+              "name": "Parent",
+              "notes": [
+                {
+                  "kind": "tap",
+                  "above": true,
+                  "beat": [0, 1, 1],
+                  "x": 0.0,
+                  "speed": 1.0
+                }
+              ],
+              "events": [
+                {
+                  "kind": "x",
+                  "start_beat": [0, 0, 1],
+                  "end_beat": [1, 0, 1],
+                  "value": {
+                    "constant": 0.0
+                  }
+                }
+              ],
+              "children": [
+                {
+                  "name": "Child",
+                  "notes": [
+                    {
+                      "kind": "drag",
+                      "above": true,
+                      "beat": [1, 0, 1],
+                      "x": 100.0,
+                      "speed": 1.0
+                    }
+                  ],
+                  "events": [],
+                  "children": []
+                }
+              ]
+            },
+            {
+              "name": "Unnamed Line",
+              "notes": [],
+              "events": [],
+              "children": []
+            }
+          ]
+        });
+
+        let new = json!({
+          "format": 5,
+          "offset": 0.0,
+          "bpm_list": [
+            {
+              "beat": [0, 0, 1],
+              "bpm": 120.0,
+              "time": 0.0
+            }
+          ],
+          "lines": [
+            {
+              "id": 0,
+              "name": "Parent",
+              "notes": [
+                {
+                  "id": 1,
+                  "kind": "tap",
+                  "above": true,
+                  "beat": [0, 1, 1],
+                  "x": 0.0,
+                  "speed": 1.0
+                }
+              ],
+              "events": [
+                {
+                  "id": 2,
+                  "kind": "x",
+                  "start_beat": [0, 0, 1],
+                  "end_beat": [1, 0, 1],
+                  "value": {
+                    "constant": 0.0
+                  }
+                }
+              ],
+              "children": [
+                {
+                  "id": 3,
+                  "name": "Child",
+                  "notes": [
+                    {
+                      "id": 4,
+                      "kind": "drag",
+                      "above": true,
+                      "beat": [1, 0, 1],
+                      "x": 100.0,
+                      "speed": 1.0
+                    }
+                  ],
+                  "events": [],
+                  "children": []
+                }
+              ]
+            },
+            {
+              "id": 5,
+              "name": "Unnamed Line",
+              "notes": [],
+              "events": [],
+              "children": []
+            }
+          ]
+        });
+
+        assert_eq!(Migration4To5::migrate(&old).unwrap(), new);
+        assert_eq!(Migration4To5::downgrade(&new).unwrap(), old);
+    }
+}
@@ -2,19 +2,27 @@ use crate::migration::migration_0_1::Migration0To1;
 use crate::migration::migration_1_2::Migration1To2;
 use crate::migration::migration_2_3::Migration2To3;
 use crate::migration::migration_3_4::Migration3To4;
+use crate::migration::migration_4_5::Migration4To5;
 use anyhow::{bail, Context};
 use serde_json::{json, Value};
+use std::cmp::Ordering;
 
 mod migration_0_1;
 mod migration_1_2;
 mod migration_2_3;
 mod migration_3_4;
+mod migration_4_5;
 
 pub trait Migration {
     fn migrate(old: &Value) -> anyhow::Result<Value>;
+
+    /// Migrate a chart in the newer format back to the older one
+    ///
+    /// Fails with what would be lost if the older format can not represent the chart
+    fn downgrade(new: &Value) -> anyhow::Result<Value>;
 }
 
-pub const CURRENT_FORMAT: u64 = 4;
+pub const CURRENT_FORMAT: u64 = 5;
 
 fn get_format(chart: &Value) -> anyhow::Result<u64> {
     let version = chart
@@ -26,27 +34,71 @@ fn get_format(chart: &Value) -> anyhow::Result<u64> {
     Ok(version)
 }
 
+/// What downgrading a chart would lose, each entry is a description and how many times it appears
+#[derive(Debug, Default)]
+pub(crate) struct Losses(Vec<(&'static str, usize)>);
+
+impl Losses {
+    pub(crate) fn add(&mut self, what: &'static str, count: usize) {
+        if count > 0 {
+            self.0.push((what, count));
+        }
+    }
+
+    /// Fail with everything that would be lost, if anything
+    pub(crate) fn check(self, from: u64) -> anyhow::Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+
+        bail!(
+            "Downgrading the chart from format {} to {} would lose:\n{}",
+            from,
+            from - 1,
+            self.0
+                .iter()
+                .map(|(what, count)| format!("  - {} {}", count, what))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+}
+
 /// Migrate a chart to the latest format
 pub fn migrate(chart: &Value) -> anyhow::Result<Value> {
-    let format = get_format(chart)?;
+    migrate_to(chart, CURRENT_FORMAT)
+}
 
-    if format == CURRENT_FORMAT {
-        return Ok(chart.clone());
+/// Migrate a chart to the given format, upgrading or downgrading it as needed
+///
+/// Downgrading fails if an older format can not represent everything in the chart, listing what would be lost
+pub fn migrate_to(chart: &Value, target: u64) -> anyhow::Result<Value> {
+    if target > CURRENT_FORMAT {
+        bail!("Unsupported chart format {}", target);
     }
 
-    let new_chart = match format {
-        0 => Migration0To1::migrate(chart)?,
-        1 => Migration1To2::migrate(chart)?,
-        2 => Migration2To3::migrate(chart)?,
-        3 => Migration3To4::migrate(chart)?,
-        _ => bail!("Unsupported chart format {}", format),
-    };
-
-    let new_format = get_format(&new_chart)?;
+    let mut chart = chart.clone();
+    loop {
+        let format = get_format(&chart)?;
 
-    if new_format < CURRENT_FORMAT {
-        migrate(&new_chart)
-    } else {
-        Ok(new_chart)
+        chart = match format.cmp(&target) {
+            Ordering::Equal => return Ok(chart),
+            Ordering::Less => match format {
+                0 => Migration0To1::migrate(&chart)?,
+                1 => Migration1To2::migrate(&chart)?,
+                2 => Migration2To3::migrate(&chart)?,
+                3 => Migration3To4::migrate(&chart)?,
+                4 => Migration4To5::migrate(&chart)?,
+                _ => bail!("Unsupported chart format {}", format),
+            },
+            Ordering::Greater => match format {
+                1 => Migration0To1::downgrade(&chart)?,
+                2 => Migration1To2::downgrade(&chart)?,
+                3 => Migration2To3::downgrade(&chart)?,
+                4 => Migration3To4::downgrade(&chart)?,
+                5 => Migration4To5::downgrade(&chart)?,
+                _ => bail!("Unsupported chart format {}", format),
+            },
+        };
     }
 }
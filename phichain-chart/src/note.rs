@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
 use crate::beat::Beat;
+use crate::id::Id;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -30,11 +31,38 @@ impl NoteKind {
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "bevy", derive(bevy::prelude::Component))]
 pub struct Note {
+    /// The persistent id of the note, [`None`] if not assigned yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Id>,
     pub kind: NoteKind,
     pub above: bool,
     pub beat: Beat,
     pub x: f32,
     pub speed: f32,
+
+    /// Fake notes are rendered but can not be hit, they do not count toward the score
+    #[serde(default)]
+    pub fake: bool,
+    /// The opacity of the note, from `0.0` to `255.0`
+    #[serde(default = "default_alpha")]
+    pub alpha: f32,
+    /// The horizontal scale of the note
+    #[serde(default = "default_size")]
+    pub size: f32,
+    /// Offset of the note away from the line, in canvas units
+    #[serde(default)]
+    pub y_offset: f32,
+    /// The note only shows up this many seconds before it is hit, [`None`] if the note is always visible
+    #[serde(default)]
+    pub visible_time: Option<f32>,
+}
+
+fn default_alpha() -> f32 {
+    255.0
+}
+
+fn default_size() -> f32 {
+    1.0
 }
 
 impl PartialOrd for Note {
@@ -46,11 +74,27 @@ impl PartialOrd for Note {
 impl Note {
     pub fn new(kind: NoteKind, above: bool, beat: Beat, x: f32, speed: f32) -> Self {
         Self {
+            id: None,
             kind,
             above,
             beat,
             x,
             speed,
+            fake: false,
+            alpha: default_alpha(),
+            size: default_size(),
+            y_offset: 0.0,
+            visible_time: None,
+        }
+    }
+
+    /// Whether the note is visible at the given time, according to [`Note::visible_time`]
+    ///
+    /// `hit_time` is the time when the note is hit, both in seconds
+    pub fn is_visible_at(&self, hit_time: f32, time: f32) -> bool {
+        match self.visible_time {
+            Some(visible_time) => hit_time - time <= visible_time,
+            None => true,
         }
     }
 
@@ -114,3 +158,27 @@ impl NoteBundle {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat;
+
+    #[test]
+    fn test_deserialize_without_extended_properties() {
+        let note: Note = serde_json::from_str(
+            r#"{"kind":"tap","above":true,"beat":[1,0,1],"x":0.0,"speed":1.0}"#,
+        )
+        .unwrap();
+        assert_eq!(note, Note::new(NoteKind::Tap, true, beat!(1), 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_visible_time() {
+        let mut note = Note::new(NoteKind::Tap, true, beat!(1), 0.0, 1.0);
+        assert!(note.is_visible_at(10.0, 0.0));
+        note.visible_time = Some(1.0);
+        assert!(!note.is_visible_at(10.0, 8.5));
+        assert!(note.is_visible_at(10.0, 9.5));
+    }
+}
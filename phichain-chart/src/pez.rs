@@ -0,0 +1,385 @@
+//! Phira chart package (`.pez`)
+//!
+//! A pez file is a zip archive containing an `info.yml`, a chart, the music and an optional illustration
+
+use crate::format::official::OfficialChart;
+use crate::format::pec::PecChart;
+use crate::format::rpe::RpeChart;
+use crate::primitive::{Format, PrimitiveChart};
+use crate::project::{ProjectMeta, ProjectPath};
+use crate::report::ConversionReport;
+use crate::serialization::PhichainChart;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// The `info.yml` in a pez file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PezInfo {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    difficulty: f32,
+    #[serde(default)]
+    level: String,
+    #[serde(default)]
+    charter: String,
+    #[serde(default)]
+    composer: String,
+    #[serde(default)]
+    illustrator: String,
+    chart: String,
+    music: String,
+    #[serde(default)]
+    illustration: Option<String>,
+}
+
+impl PezInfo {
+    fn meta(&self) -> ProjectMeta {
+        ProjectMeta {
+            composer: self.composer.clone(),
+            charter: self.charter.clone(),
+            illustrator: self.illustrator.clone(),
+            name: self.name.clone(),
+            level: self.level.clone(),
+        }
+    }
+}
+
+/// Parse the difficulty from a level string, e.g. `IN Lv.15` is parsed as `15.0`
+fn difficulty(level: &str) -> f32 {
+    level
+        .rsplit(|c: char| !c.is_ascii_digit() && c != '.')
+        .map(|x| x.trim_matches('.'))
+        .find(|x| !x.is_empty())
+        .and_then(|x| x.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Filenames of the files written by Phichain itself in a pez file
+const RESERVED_FILENAMES: [&str; 2] = ["info.yml", "chart.json"];
+
+/// A file bundled in a pez file
+#[derive(Debug, Clone, PartialEq)]
+pub struct PezResource {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+impl PezResource {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            filename: path
+                .file_name()
+                .and_then(|x| x.to_str())
+                .context("Invalid filename")?
+                .to_owned(),
+            data: std::fs::read(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+        })
+    }
+
+    /// The filename of the resource in the archive, named after its role if the filename is already used
+    ///
+    /// A numeric suffix is appended to the role if that name is taken as well, e.g. `music-2.ogg`
+    fn archive_name(&self, role: &str, used: &mut Vec<String>) -> String {
+        let name = |stem: String| match self.extension() {
+            Some(ext) => format!("{}.{}", stem, ext),
+            None => stem,
+        };
+
+        let mut filename = self.filename.clone();
+        let mut suffix = 1;
+        while used.contains(&filename) {
+            filename = match suffix {
+                1 => name(role.to_owned()),
+                _ => name(format!("{}-{}", role, suffix)),
+            };
+            suffix += 1;
+        }
+        used.push(filename.clone());
+
+        filename
+    }
+
+    fn extension(&self) -> Option<&str> {
+        Path::new(&self.filename)
+            .extension()
+            .and_then(|x| x.to_str())
+    }
+}
+
+/// A Phira chart package
+#[derive(Debug, Clone)]
+pub struct Pez {
+    pub meta: ProjectMeta,
+    pub chart: PrimitiveChart,
+    pub music: PezResource,
+    pub illustration: Option<PezResource>,
+}
+
+impl Pez {
+    /// Create a pez from a Phichain project, the chart is given separately since it usually needs to be compiled
+    pub fn from_project(
+        path: &ProjectPath,
+        meta: ProjectMeta,
+        chart: PrimitiveChart,
+    ) -> anyhow::Result<Self> {
+        let music = path
+            .music_path()
+            .context("Could not find music file in project")?;
+
+        Ok(Self {
+            meta,
+            chart,
+            music: PezResource::load(&music)?,
+            illustration: path
+                .illustration_path()
+                .map(|x| PezResource::load(&x))
+                .transpose()?,
+        })
+    }
+
+    /// Read a pez file
+    ///
+    /// The chart in the package can be in RPE, official or pec format, losses converting it are logged as warnings
+    pub fn read(reader: impl Read + Seek) -> anyhow::Result<Self> {
+        let (pez, report) = Self::read_with_report(reader)?;
+        report.log();
+        Ok(pez)
+    }
+
+    /// Read a pez file, along with what is dropped or approximated converting the chart in the package
+    pub fn read_with_report(reader: impl Read + Seek) -> anyhow::Result<(Self, ConversionReport)> {
+        let mut archive = ZipArchive::new(reader).context("Invalid pez file")?;
+
+        let mut read_file = |name: &str| -> anyhow::Result<Vec<u8>> {
+            let mut file = archive
+                .by_name(name)
+                .with_context(|| format!("{} is missing", name))?;
+            let mut data = vec![];
+            file.read_to_end(&mut data)?;
+            Ok(data)
+        };
+
+        let info: PezInfo =
+            serde_yaml::from_slice(&read_file("info.yml")?).context("Invalid info.yml")?;
+
+        let chart_data = read_file(&info.chart)?;
+        let (chart, report) = if info.chart.ends_with(".pec") {
+            std::str::from_utf8(&chart_data)?
+                .parse::<PecChart>()?
+                .into_primitive_with_report()?
+        } else {
+            let value: Value = serde_json::from_slice(&chart_data).context("Invalid chart")?;
+            if value.get("META").is_some() {
+                serde_json::from_value::<RpeChart>(value)?.into_primitive_with_report()?
+            } else if value.get("formatVersion").is_some() {
+                serde_json::from_value::<OfficialChart>(value)?.into_primitive_with_report()?
+            } else {
+                bail!("Unsupported chart format in pez file");
+            }
+        };
+
+        let music = PezResource {
+            filename: info.music.clone(),
+            data: read_file(&info.music)?,
+        };
+        let illustration = match &info.illustration {
+            Some(illustration) => Some(PezResource {
+                filename: illustration.clone(),
+                data: read_file(illustration)?,
+            }),
+            None => None,
+        };
+
+        let pez = Self {
+            meta: info.meta(),
+            chart,
+            music,
+            illustration,
+        };
+
+        Ok((pez, report))
+    }
+
+    /// Write the pez file, the chart is written in RPE format and losses converting it are logged as warnings
+    pub fn write(&self, writer: impl Write + Seek) -> anyhow::Result<()> {
+        self.write_with_report(writer)?.log();
+        Ok(())
+    }
+
+    /// Write the pez file, returns what is dropped or approximated converting the chart into RPE format
+    ///
+    /// Resources whose filenames collide with `info.yml`, `chart.json` or each other are renamed in the archive
+    pub fn write_with_report(&self, writer: impl Write + Seek) -> anyhow::Result<ConversionReport> {
+        let mut zip = ZipWriter::new(writer);
+
+        let mut used = RESERVED_FILENAMES.map(str::to_owned).to_vec();
+        let music = self.music.archive_name("music", &mut used);
+        let illustration = self
+            .illustration
+            .as_ref()
+            .map(|x| x.archive_name("illustration", &mut used));
+
+        let info = PezInfo {
+            name: self.meta.name.clone(),
+            difficulty: difficulty(&self.meta.level),
+            level: self.meta.level.clone(),
+            charter: self.meta.charter.clone(),
+            composer: self.meta.composer.clone(),
+            illustrator: self.meta.illustrator.clone(),
+            chart: "chart.json".to_owned(),
+            music: music.clone(),
+            illustration: illustration.clone(),
+        };
+
+        zip.start_file("info.yml", SimpleFileOptions::default())?;
+        zip.write_all(serde_yaml::to_string(&info)?.as_bytes())?;
+
+        zip.start_file("chart.json", SimpleFileOptions::default())?;
+        let (chart, report) = RpeChart::from_primitive_with_report(PrimitiveChart {
+            meta: Some(self.meta.clone()),
+            ..self.chart.clone()
+        })?;
+        zip.write_all(serde_json::to_string(&chart)?.as_bytes())?;
+
+        let resources = std::iter::once((music, &self.music))
+            .chain(illustration.zip(self.illustration.as_ref()));
+        for (filename, resource) in resources {
+            zip.start_file(filename, SimpleFileOptions::default())?;
+            zip.write_all(&resource.data)?;
+        }
+
+        zip.finish()?;
+
+        Ok(report)
+    }
+
+    /// Extract the pez into a Phichain project at the given directory
+    pub fn extract(&self, root_dir: &Path) -> anyhow::Result<ProjectPath> {
+        let path = ProjectPath(root_dir.to_path_buf());
+        let chart = PhichainChart::from_primitive(self.chart.clone())?;
+        path.write(&chart, &self.meta)?;
+
+        let write_resource = |name: &str, resource: &PezResource| -> anyhow::Result<()> {
+            let mut target = path.sub_path(name);
+            if let Some(ext) = resource.extension() {
+                target.set_extension(ext);
+            }
+            std::fs::write(target, &resource.data)
+                .with_context(|| format!("Failed to write {}", name))
+        };
+
+        write_resource("music", &self.music)?;
+        if let Some(illustration) = &self.illustration {
+            write_resource("illustration", illustration)?;
+        }
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat;
+    use crate::note::{Note, NoteKind};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_difficulty() {
+        assert_eq!(difficulty("IN Lv.15"), 15.0);
+        assert_eq!(difficulty("AT Lv.16.5"), 16.5);
+        assert_eq!(difficulty("12"), 12.0);
+        assert_eq!(difficulty("??"), 0.0);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut chart = PhichainChart::default().into_primitive().unwrap();
+        chart.lines[0]
+            .notes
+            .push(Note::new(NoteKind::Tap, true, beat!(1), 0.0, 1.0));
+
+        let pez = Pez {
+            meta: ProjectMeta {
+                composer: "composer".to_owned(),
+                charter: "charter".to_owned(),
+                illustrator: "illustrator".to_owned(),
+                name: "name".to_owned(),
+                level: "IN Lv.12".to_owned(),
+            },
+            chart,
+            music: PezResource {
+                filename: "music.ogg".to_owned(),
+                data: vec![1, 2, 3],
+            },
+            illustration: None,
+        };
+
+        let mut buffer = Cursor::new(vec![]);
+        pez.write(&mut buffer).unwrap();
+        buffer.set_position(0);
+        let read = Pez::read(buffer).unwrap();
+
+        assert_eq!(read.meta, pez.meta);
+        assert_eq!(read.music, pez.music);
+        assert_eq!(read.illustration, None);
+        assert_eq!(read.chart.lines[0].notes, pez.chart.lines[0].notes);
+    }
+
+    #[test]
+    fn test_reserved_filenames() {
+        let pez = Pez {
+            meta: Default::default(),
+            chart: PhichainChart::default().into_primitive().unwrap(),
+            music: PezResource {
+                filename: "chart.json".to_owned(),
+                data: vec![1, 2, 3],
+            },
+            illustration: Some(PezResource {
+                filename: "chart.json".to_owned(),
+                data: vec![4, 5, 6],
+            }),
+        };
+
+        let mut buffer = Cursor::new(vec![]);
+        pez.write(&mut buffer).unwrap();
+        buffer.set_position(0);
+        let read = Pez::read(buffer).unwrap();
+
+        assert_eq!(read.music.filename, "music.json");
+        assert_eq!(read.music.data, vec![1, 2, 3]);
+        let illustration = read.illustration.unwrap();
+        assert_eq!(illustration.filename, "illustration.json");
+        assert_eq!(illustration.data, vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn test_renamed_filename_taken() {
+        let mut used = RESERVED_FILENAMES.map(str::to_owned).to_vec();
+        let resource = |filename: &str| PezResource {
+            filename: filename.to_owned(),
+            data: vec![],
+        };
+
+        assert_eq!(
+            resource("illustration.png").archive_name("music", &mut used),
+            "illustration.png"
+        );
+        assert_eq!(
+            resource("illustration.png").archive_name("illustration", &mut used),
+            "illustration-2.png"
+        );
+        assert_eq!(
+            resource("illustration.png").archive_name("illustration", &mut used),
+            "illustration-3.png"
+        );
+    }
+}
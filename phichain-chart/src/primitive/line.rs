@@ -1,9 +1,22 @@
+use crate::extended::ExtendedEvents;
+use crate::line::Inheritance;
 use crate::note::Note;
 use crate::primitive::event::LineEvent;
 use serde::{Deserialize, Serialize};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Line {
+    /// The name of the line, [`None`] if the source format does not have line names
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// What the line inherits from its parent line
+    #[serde(default)]
+    pub inherit: Inheritance,
     pub notes: Vec<Note>,
     pub events: Vec<LineEvent>,
+    /// Child lines, their events are relative to this line
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Line>,
+    #[serde(default, skip_serializing_if = "ExtendedEvents::is_empty")]
+    pub extended: ExtendedEvents,
 }
@@ -1,5 +1,8 @@
+use crate::binary;
 use crate::bpm_list::BpmList;
 use crate::primitive::line::Line;
+use crate::project::ProjectMeta;
+use crate::report::ConversionReport;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
@@ -12,6 +15,20 @@ pub struct PrimitiveChart {
     pub offset: f32,
     pub bpm_list: BpmList,
     pub lines: Vec<Line>,
+    /// Metadata of the chart, [`None`] if the source format does not carry any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ProjectMeta>,
+}
+
+impl PrimitiveChart {
+    /// Read a chart in JSON or in the [binary encoding](crate::binary)
+    pub fn read(data: &[u8]) -> anyhow::Result<Self> {
+        if binary::is_binary(data) {
+            return binary::decode_primitive(data);
+        }
+
+        Ok(serde_json::from_slice(data)?)
+    }
 }
 
 impl Default for PrimitiveChart {
@@ -21,27 +38,54 @@ impl Default for PrimitiveChart {
             offset: Default::default(),
             bpm_list: Default::default(),
             lines: Default::default(),
+            meta: None,
         }
     }
 }
 
 pub trait Format: Serialize + DeserializeOwned {
-    fn into_primitive(self) -> anyhow::Result<PrimitiveChart>;
+    /// Convert the chart into a primitive chart, along with what is dropped or approximated
+    fn into_primitive_with_report(self) -> anyhow::Result<(PrimitiveChart, ConversionReport)>;
 
-    fn from_primitive(phichain: PrimitiveChart) -> anyhow::Result<Self>
+    /// Convert a primitive chart into this format, along with what is dropped or approximated
+    fn from_primitive_with_report(
+        phichain: PrimitiveChart,
+    ) -> anyhow::Result<(Self, ConversionReport)>
     where
         Self: Sized;
+
+    /// Convert the chart into a primitive chart, losses are logged as warnings
+    fn into_primitive(self) -> anyhow::Result<PrimitiveChart>
+    where
+        Self: Sized,
+    {
+        let (primitive, report) = self.into_primitive_with_report()?;
+        report.log();
+        Ok(primitive)
+    }
+
+    /// Convert a primitive chart into this format, losses are logged as warnings
+    fn from_primitive(phichain: PrimitiveChart) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let (chart, report) = Self::from_primitive_with_report(phichain)?;
+        report.log();
+        Ok(chart)
+    }
 }
 
 impl Format for PrimitiveChart {
-    fn into_primitive(self) -> anyhow::Result<PrimitiveChart> {
-        Ok(self)
+    fn into_primitive_with_report(self) -> anyhow::Result<(PrimitiveChart, ConversionReport)> {
+        Ok((self, Default::default()))
     }
 
-    fn from_primitive(phichain: PrimitiveChart) -> anyhow::Result<Self>
+    fn from_primitive_with_report(
+        phichain: PrimitiveChart,
+    ) -> anyhow::Result<(Self, ConversionReport)>
     where
         Self: Sized,
     {
-        Ok(phichain)
+        Ok((phichain, Default::default()))
     }
 }
@@ -1,3 +1,5 @@
+use crate::canonical;
+use crate::serialization::PhichainChart;
 use anyhow::{anyhow, bail, Context};
 use serde::{Deserialize, Serialize};
 use std::fs::File;
@@ -33,6 +35,18 @@ impl ProjectPath {
         self.0.join("chart.json")
     }
 
+    /// The chart in the [binary encoding](crate::binary), only used when `chart.json` does not exist
+    pub fn binary_chart_path(&self) -> PathBuf {
+        self.0.join("chart.bin")
+    }
+
+    /// The chart file to load, `chart.json` if it exists, otherwise `chart.bin`
+    pub fn chart_file(&self) -> Option<PathBuf> {
+        [self.chart_path(), self.binary_chart_path()]
+            .into_iter()
+            .find(|x| x.is_file())
+    }
+
     pub fn sub_path(&self, path: impl AsRef<Path>) -> PathBuf {
         self.0.join(path)
     }
@@ -65,9 +79,24 @@ impl ProjectPath {
         self.0.join("meta.json")
     }
 
+    /// Write the chart and the meta of a project into this directory, creating the directory if needed
+    ///
+    /// The music and the illustration are not touched
+    pub fn write(&self, chart: &PhichainChart, meta: &ProjectMeta) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.0).context("Failed to create project directory")?;
+
+        let meta = serde_json::to_string_pretty(meta)?;
+        std::fs::write(self.meta_path(), meta).context("Failed to write meta")?;
+
+        let chart = canonical::to_string(chart)?;
+        std::fs::write(self.chart_path(), chart).context("Failed to write chart")?;
+
+        Ok(())
+    }
+
     pub fn into_project(self) -> anyhow::Result<Project> {
-        if !self.chart_path().is_file() {
-            bail!("chart.json is missing");
+        if self.chart_file().is_none() {
+            bail!("chart.json or chart.bin is missing");
         }
         if !self
             .music_path()
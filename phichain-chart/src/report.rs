@@ -0,0 +1,223 @@
+//! Conversion reports
+//!
+//! Not every format can represent everything in a chart. Converting between formats records what is dropped or
+//! approximated into a [`ConversionReport`], so the user can tell whether a conversion is lossless
+
+use crate::beat::Beat;
+use crate::easing::Easing;
+use crate::event::LineEventKind;
+use crate::line::LinePath;
+use crate::note::Note;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use strum::Display;
+use tracing::warn;
+
+/// A property of a note which only some formats support
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum NoteProperty {
+    Size,
+    Alpha,
+    YOffset,
+    VisibleTime,
+}
+
+impl NoteProperty {
+    /// Whether the note has a value other than the default for this property
+    pub fn is_set(&self, note: &Note) -> bool {
+        let default = Note::new(note.kind, note.above, note.beat, note.x, note.speed);
+        match self {
+            NoteProperty::Size => note.size != default.size,
+            NoteProperty::Alpha => note.alpha != default.alpha,
+            NoteProperty::YOffset => note.y_offset != default.y_offset,
+            NoteProperty::VisibleTime => note.visible_time != default.visible_time,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LossKind {
+    /// An easing id the format does not define, replaced by linear
+    UnknownEasing { id: i32 },
+    /// An easing the format does not support for this kind of event, replaced by linear
+    UnsupportedEasing { kind: LineEventKind, easing: Easing },
+    /// An eased event cut into linear events
+    ApproximatedEasing { kind: LineEventKind, easing: Easing },
+    /// Overlapping eased events of multiple event layers, summed and sampled into linear events
+    SampledLayers { kind: LineEventKind },
+    /// A speed transition, replaced by constant speed steps
+    SteppedSpeed,
+    /// Child lines of a line, dropped
+    ChildLines { count: usize },
+    /// A line inside a parent cycle, turned into a root line
+    ParentCycle,
+    /// A child line inheriting the opacity of its parent, which the format does not support
+    OpacityInheritance,
+    /// Extended events of a line, dropped
+    ExtendedEvents,
+    /// A fake note, dropped
+    FakeNote,
+    /// A property of a note, dropped
+    NoteProperty { property: NoteProperty },
+}
+
+impl Display for LossKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LossKind::UnknownEasing { id } => {
+                write!(f, "unknown easing type {}, replaced by linear", id)
+            }
+            LossKind::UnsupportedEasing { kind, easing } => write!(
+                f,
+                "{:?} event with unsupported easing {:?}, replaced by linear",
+                kind, easing
+            ),
+            LossKind::ApproximatedEasing { kind, easing } => write!(
+                f,
+                "{:?} event with easing {:?} is approximated with linear events",
+                kind, easing
+            ),
+            LossKind::SampledLayers { kind } => write!(
+                f,
+                "{:?} events of multiple event layers are sampled into linear events",
+                kind
+            ),
+            LossKind::SteppedSpeed => {
+                write!(f, "speed transition is replaced by constant speeds")
+            }
+            LossKind::ChildLines { count } => write!(
+                f,
+                "{} child line(s) are dropped, compile the chart to merge them first",
+                count
+            ),
+            LossKind::ParentCycle => {
+                write!(f, "line is inside a parent cycle, treated as root line")
+            }
+            LossKind::OpacityInheritance => {
+                write!(
+                    f,
+                    "inheriting the opacity of the parent line is not supported"
+                )
+            }
+            LossKind::ExtendedEvents => write!(f, "extended events are dropped"),
+            LossKind::FakeNote => write!(f, "fake note is dropped"),
+            LossKind::NoteProperty { property } => {
+                write!(f, "note property `{}` is dropped", property)
+            }
+        }
+    }
+}
+
+/// Something dropped or approximated when converting a chart
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Loss {
+    pub kind: LossKind,
+    /// The line where the loss happens, in the chart being converted
+    pub line: LinePath,
+    /// The beat where the loss happens, [`None`] if the loss is not related to a specific beat
+    pub beat: Option<Beat>,
+}
+
+impl Display for Loss {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}", self.line)?;
+        if let Some(beat) = self.beat {
+            write!(f, " at beat {:?}", beat)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+/// Everything dropped or approximated when converting a chart
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConversionReport {
+    pub losses: Vec<Loss>,
+}
+
+impl ConversionReport {
+    pub fn push(&mut self, kind: LossKind, line: LinePath, beat: Option<Beat>) {
+        self.losses.push(Loss { kind, line, beat });
+    }
+
+    /// Record the properties of the note the format does not support
+    pub fn note(&mut self, note: &Note, line: &LinePath, unsupported: &[NoteProperty]) {
+        for property in unsupported {
+            if property.is_set(note) {
+                self.push(
+                    LossKind::NoteProperty {
+                        property: *property,
+                    },
+                    line.clone(),
+                    Some(note.beat),
+                );
+            }
+        }
+    }
+
+    pub fn extend(&mut self, other: ConversionReport) {
+        self.losses.extend(other.losses);
+    }
+
+    pub fn is_lossless(&self) -> bool {
+        self.losses.is_empty()
+    }
+
+    /// Log every loss as a warning
+    pub fn log(&self) {
+        for loss in &self.losses {
+            warn!("{}", loss);
+        }
+    }
+}
+
+impl Display for ConversionReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, loss) in self.losses.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", loss)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat;
+    use crate::note::NoteKind;
+
+    #[test]
+    fn test_note_properties() {
+        let note = Note {
+            size: 2.0,
+            visible_time: Some(1.0),
+            ..Note::new(NoteKind::Tap, true, beat!(1), 0.0, 1.0)
+        };
+
+        let mut report = ConversionReport::default();
+        report.note(
+            &note,
+            &LinePath(vec![0]),
+            &[NoteProperty::Alpha, NoteProperty::VisibleTime],
+        );
+        assert_eq!(
+            report.losses,
+            vec![Loss {
+                kind: LossKind::NoteProperty {
+                    property: NoteProperty::VisibleTime
+                },
+                line: LinePath(vec![0]),
+                beat: Some(beat!(1)),
+            }]
+        );
+        assert_eq!(
+            report.to_string(),
+            "line 0 at beat 1+0/1: note property `visible_time` is dropped"
+        );
+    }
+}
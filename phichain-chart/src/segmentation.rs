@@ -0,0 +1,197 @@
+//! Error-bounded linear segmentation
+//!
+//! Formats without easings (e.g. the official format) can only represent linear events. [`segment`] approximates
+//! a curve with linear segments, splitting only where the linear interpolation drifts further than a given tolerance
+
+use crate::beat;
+use crate::beat::Beat;
+use num::Rational32;
+
+/// The default maximum value error of a segmented curve
+pub const DEFAULT_TOLERANCE: f32 = 0.1;
+
+/// The default resolution of [`segment`], segments are never split below `1 / DEFAULT_RESOLUTION` beat
+///
+/// This keeps discontinuous curves from recursing forever
+pub const DEFAULT_RESOLUTION: u32 = 256;
+
+/// The number of points checked inside a segment when measuring its error
+const SAMPLES: usize = 16;
+
+/// A linear segment of a curve with `N` channels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment<const N: usize> {
+    pub start_beat: Beat,
+    pub end_beat: Beat,
+    pub start: [f32; N],
+    pub end: [f32; N],
+}
+
+/// Approximate a curve between `start_beat` and `end_beat` with linear segments
+///
+/// `f` evaluates every channel of the curve at a beat inside the range, while the values at both ends are given
+/// separately so the caller can pick the side of a discontinuity. A segment is split in half while any channel
+/// differs from its linear interpolation by more than `tolerance`
+pub fn segment<const N: usize>(
+    start_beat: Beat,
+    end_beat: Beat,
+    start: [f32; N],
+    end: [f32; N],
+    tolerance: f32,
+    f: impl Fn(f32) -> [f32; N],
+) -> Vec<Segment<N>> {
+    segment_with_resolution(
+        start_beat,
+        end_beat,
+        start,
+        end,
+        tolerance,
+        DEFAULT_RESOLUTION,
+        f,
+    )
+}
+
+/// Same as [`segment`], but segments are never split below `1 / resolution` beat instead of `1 / DEFAULT_RESOLUTION`
+pub fn segment_with_resolution<const N: usize>(
+    start_beat: Beat,
+    end_beat: Beat,
+    start: [f32; N],
+    end: [f32; N],
+    tolerance: f32,
+    resolution: u32,
+    f: impl Fn(f32) -> [f32; N],
+) -> Vec<Segment<N>> {
+    let minimum = beat!(1, resolution.max(1));
+
+    let mut segments = vec![];
+    let mut stack = vec![Segment {
+        start_beat,
+        end_beat,
+        start,
+        end,
+    }];
+
+    while let Some(segment) = stack.pop() {
+        let length = segment.end_beat - segment.start_beat;
+        if length <= minimum || error(&segment, &f) <= tolerance {
+            segments.push(segment);
+            continue;
+        }
+
+        let middle_beat = Beat::from(
+            (Rational32::from(segment.start_beat) + Rational32::from(segment.end_beat))
+                / Rational32::from_integer(2),
+        );
+        let middle = f(middle_beat.value());
+
+        // push the right half first so segments come out in order
+        stack.push(Segment {
+            start_beat: middle_beat,
+            start: middle,
+            ..segment
+        });
+        stack.push(Segment {
+            end_beat: middle_beat,
+            end: middle,
+            ..segment
+        });
+    }
+
+    segments
+}
+
+/// The maximum difference between the curve and the linear interpolation of a segment
+fn error<const N: usize>(segment: &Segment<N>, f: &impl Fn(f32) -> [f32; N]) -> f32 {
+    let start_beat = segment.start_beat.value();
+    let end_beat = segment.end_beat.value();
+
+    (1..SAMPLES)
+        .map(|i| {
+            let t = i as f32 / SAMPLES as f32;
+            let actual = f(start_beat + (end_beat - start_beat) * t);
+            (0..N)
+                .map(|c| {
+                    let expected = segment.start[c] + (segment.end[c] - segment.start[c]) * t;
+                    (actual[c] - expected).abs()
+                })
+                .fold(0.0, f32::max)
+        })
+        .fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::easing::{Easing, Tween};
+
+    fn ease(easing: Easing) -> impl Fn(f32) -> [f32; 1] {
+        move |beat| [0.0.ease_to(100.0, beat / 4.0, easing)]
+    }
+
+    #[test]
+    fn test_linear() {
+        let segments = segment(
+            beat!(0),
+            beat!(4),
+            [0.0],
+            [100.0],
+            0.1,
+            ease(Easing::Linear),
+        );
+        assert_eq!(segments.len(), 1);
+    }
+
+    #[test]
+    fn test_error_bound() {
+        for tolerance in [1.0, 0.1, 0.01] {
+            let f = ease(Easing::EaseInOutSine);
+            let segments = segment(beat!(0), beat!(4), [0.0], [100.0], tolerance, &f);
+
+            assert_eq!(segments.first().unwrap().start_beat, beat!(0));
+            assert_eq!(segments.last().unwrap().end_beat, beat!(4));
+            for pair in segments.windows(2) {
+                assert_eq!(pair[0].end_beat, pair[1].start_beat);
+            }
+
+            for segment in &segments {
+                for i in 0..=32 {
+                    let t = i as f32 / 32.0;
+                    let beat = segment.start_beat.value()
+                        + (segment.end_beat.value() - segment.start_beat.value()) * t;
+                    let expected = segment.start[0] + (segment.end[0] - segment.start[0]) * t;
+                    assert!((f(beat)[0] - expected).abs() <= tolerance * 1.01);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fewer_segments_for_larger_tolerance() {
+        let count = |tolerance| {
+            segment(
+                beat!(0),
+                beat!(4),
+                [0.0],
+                [100.0],
+                tolerance,
+                ease(Easing::EaseOutCubic),
+            )
+            .len()
+        };
+
+        assert!(count(1.0) < count(0.01));
+        // a fixed 1/32 beat step would produce 128 segments
+        assert!(count(0.1) < 128);
+    }
+
+    #[test]
+    fn test_resolution() {
+        // a jump can never be approximated, it is split down to the resolution
+        let step = |beat: f32| [if beat < 1.0 { 0.0 } else { 100.0 }];
+        let segments = segment_with_resolution(beat!(0), beat!(2), [0.0], [100.0], 0.1, 4, step);
+        assert!(segments
+            .iter()
+            .all(|x| x.end_beat - x.start_beat >= beat!(1, 4)));
+        assert!(segments.len() <= 8);
+    }
+}
@@ -1,16 +1,21 @@
 use crate::beat::Beat;
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 
+use crate::binary;
 use crate::bpm_list::BpmList;
 use crate::event::{LineEvent, LineEventKind, LineEventValue};
-use crate::line::Line;
-use crate::migration::CURRENT_FORMAT;
+use crate::extended::ExtendedEvents;
+use crate::id::{assign_ids, Id};
+use crate::line::{Line, LinePath};
+use crate::migration::{migrate, CURRENT_FORMAT};
 use crate::note::Note;
 use crate::offset::Offset;
 use crate::primitive;
 use crate::primitive::{Format, PrimitiveChart};
+use crate::report::ConversionReport;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PhichainChart {
     pub format: u64,
     pub offset: Offset,
@@ -19,47 +24,73 @@ pub struct PhichainChart {
 }
 
 impl Format for PhichainChart {
-    fn into_primitive(self) -> anyhow::Result<PrimitiveChart> {
-        Ok(PrimitiveChart {
+    fn into_primitive_with_report(self) -> anyhow::Result<(PrimitiveChart, ConversionReport)> {
+        fn convert(line: &LineWrapper) -> primitive::line::Line {
+            primitive::line::Line {
+                name: Some(line.line.name.clone()),
+                inherit: line.line.inherit,
+                notes: line.notes.clone(),
+                events: line.events.iter().map(|x| (*x).into()).collect(),
+                children: line.children.iter().map(convert).collect(),
+                extended: line.extended.clone(),
+            }
+        }
+
+        let primitive = PrimitiveChart {
             offset: self.offset.0,
             bpm_list: self.bpm_list.clone(),
-            lines: self
-                .lines
-                .iter()
-                .map(|line| primitive::line::Line {
-                    notes: line.notes.clone(),
-                    events: line.events.iter().map(|x| (*x).into()).collect(),
-                })
-                .collect(),
+            lines: self.lines.iter().map(convert).collect(),
             ..Default::default()
-        })
+        };
+
+        Ok((primitive, Default::default()))
     }
 
-    fn from_primitive(primitive: PrimitiveChart) -> anyhow::Result<Self>
+    fn from_primitive_with_report(
+        primitive: PrimitiveChart,
+    ) -> anyhow::Result<(Self, ConversionReport)>
     where
         Self: Sized,
     {
-        Ok(Self {
+        fn convert(line: &primitive::line::Line) -> LineWrapper {
+            LineWrapper {
+                extended: line.extended.clone(),
+                ..LineWrapper::new(
+                    Line {
+                        id: None,
+                        name: line.name.clone().unwrap_or_else(|| Line::default().name),
+                        inherit: line.inherit,
+                    },
+                    line.notes.clone(),
+                    line.events.iter().map(|x| (*x).into()).collect(),
+                    line.children.iter().map(convert).collect(),
+                )
+            }
+        }
+
+        let chart = Self {
             offset: Offset(primitive.offset),
             bpm_list: primitive.bpm_list,
-            lines: primitive
-                .lines
-                .iter()
-                .map(|line| {
-                    LineWrapper::new(
-                        Line::default(),
-                        line.notes.clone(),
-                        line.events.iter().map(|x| (*x).into()).collect(),
-                        vec![],
-                    )
-                })
-                .collect(),
+            lines: primitive.lines.iter().map(convert).collect(),
             ..Default::default()
-        })
+        };
+
+        Ok((chart, Default::default()))
     }
 }
 
 impl PhichainChart {
+    /// Read a chart in JSON or in the [binary encoding](crate::binary), migrating it from older formats
+    pub fn read(data: &[u8]) -> anyhow::Result<Self> {
+        if binary::is_binary(data) {
+            return binary::decode_phichain(data);
+        }
+
+        let chart: serde_json::Value = serde_json::from_slice(data)?;
+        let migrated = migrate(&chart).context("Migration failed")?;
+        Ok(serde_json::from_value(migrated)?)
+    }
+
     pub fn new(offset: f32, bpm_list: BpmList, lines: Vec<LineWrapper>) -> Self {
         Self {
             format: CURRENT_FORMAT,
@@ -70,6 +101,59 @@ impl PhichainChart {
     }
 }
 
+impl PhichainChart {
+    /// Collect all lines in this chart, including nested children, along with their [`LinePath`]s
+    ///
+    /// Lines are visited in depth-first order, a parent line always comes before its children
+    pub fn iter_lines(&self) -> Vec<(LinePath, &LineWrapper)> {
+        fn walk<'a>(
+            line: &'a LineWrapper,
+            path: LinePath,
+            lines: &mut Vec<(LinePath, &'a LineWrapper)>,
+        ) {
+            lines.push((path.clone(), line));
+            for (index, child) in line.children.iter().enumerate() {
+                walk(child, path.child(index), lines);
+            }
+        }
+
+        let mut lines = vec![];
+        for (index, line) in self.lines.iter().enumerate() {
+            walk(line, LinePath(vec![index]), &mut lines);
+        }
+
+        lines
+    }
+
+    /// Give every line, note and event without an id a new one, see [`assign_ids`]
+    ///
+    /// Duplicated ids, e.g. from copied notes, are replaced for all but the first one in depth-first order
+    pub fn assign_ids(&mut self) {
+        fn walk<'a>(line: &'a mut LineWrapper, ids: &mut Vec<&'a mut Option<Id>>) {
+            let LineWrapper {
+                line,
+                notes,
+                events,
+                children,
+                ..
+            } = line;
+            ids.push(&mut line.id);
+            ids.extend(notes.iter_mut().map(|x| &mut x.id));
+            ids.extend(events.iter_mut().map(|x| &mut x.id));
+            for child in children {
+                walk(child, ids);
+            }
+        }
+
+        let mut ids = vec![];
+        for line in &mut self.lines {
+            walk(line, &mut ids);
+        }
+
+        assign_ids(ids);
+    }
+}
+
 impl Default for PhichainChart {
     fn default() -> Self {
         Self {
@@ -82,13 +166,15 @@ impl Default for PhichainChart {
 }
 
 /// A wrapper struct to handle line serialization and deserialization
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LineWrapper {
     #[serde(flatten)]
     pub line: Line,
     pub notes: Vec<Note>,
     pub events: Vec<LineEvent>,
     pub children: Vec<LineWrapper>,
+    #[serde(default, skip_serializing_if = "ExtendedEvents::is_empty")]
+    pub extended: ExtendedEvents,
 }
 
 impl LineWrapper {
@@ -103,6 +189,7 @@ impl LineWrapper {
             notes,
             events,
             children,
+            extended: Default::default(),
         }
     }
 }
@@ -115,30 +202,35 @@ impl Default for LineWrapper {
             notes: Default::default(),
             events: vec![
                 LineEvent {
+                    id: None,
                     kind: LineEventKind::X,
                     value: LineEventValue::constant(0.0),
                     start_beat: Beat::ZERO,
                     end_beat: Beat::ONE,
                 },
                 LineEvent {
+                    id: None,
                     kind: LineEventKind::Y,
                     value: LineEventValue::constant(0.0),
                     start_beat: Beat::ZERO,
                     end_beat: Beat::ONE,
                 },
                 LineEvent {
+                    id: None,
                     kind: LineEventKind::Rotation,
                     value: LineEventValue::constant(0.0),
                     start_beat: Beat::ZERO,
                     end_beat: Beat::ONE,
                 },
                 LineEvent {
+                    id: None,
                     kind: LineEventKind::Opacity,
                     value: LineEventValue::constant(0.0),
                     start_beat: Beat::ZERO,
                     end_beat: Beat::ONE,
                 },
                 LineEvent {
+                    id: None,
                     kind: LineEventKind::Speed,
                     value: LineEventValue::constant(10.0),
                     start_beat: Beat::ZERO,
@@ -146,6 +238,7 @@ impl Default for LineWrapper {
                 },
             ],
             children: vec![],
+            extended: Default::default(),
         }
     }
 }
@@ -182,6 +275,12 @@ impl LineWrapper {
             }
         }
 
-        LineWrapper::new(line.clone(), notes, events, child_lines)
+        LineWrapper {
+            extended: world
+                .get::<ExtendedEvents>(entity)
+                .cloned()
+                .unwrap_or_default(),
+            ..LineWrapper::new(line.clone(), notes, events, child_lines)
+        }
     }
 }
@@ -0,0 +1,278 @@
+//! Chart statistics and difficulty metrics
+
+use crate::beat::Beat;
+use crate::bpm_list::BpmList;
+use crate::note::{Note, NoteKind};
+use crate::primitive;
+use crate::primitive::PrimitiveChart;
+use crate::serialization::PhichainChart;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Note amounts of each [`NoteKind`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteCounts {
+    pub tap: u32,
+    pub drag: u32,
+    pub hold: u32,
+    pub flick: u32,
+}
+
+impl NoteCounts {
+    pub fn total(&self) -> u32 {
+        self.tap + self.drag + self.hold + self.flick
+    }
+
+    fn count(&mut self, kind: &NoteKind) {
+        match kind {
+            NoteKind::Tap => self.tap += 1,
+            NoteKind::Drag => self.drag += 1,
+            NoteKind::Hold { .. } => self.hold += 1,
+            NoteKind::Flick => self.flick += 1,
+        }
+    }
+}
+
+/// A time window in seconds containing a number of notes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DensityWindow {
+    pub start: f32,
+    pub end: f32,
+    pub notes: u32,
+}
+
+impl DensityWindow {
+    /// The average notes per second within this window
+    pub fn notes_per_second(&self) -> f32 {
+        self.notes as f32 / (self.end - self.start)
+    }
+}
+
+/// Statistics of all notes in a chart, fake notes are not counted
+///
+/// Unlike the score in the game, which only counts notes that have passed, this covers the whole chart
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChartStatistics {
+    pub notes: NoteCounts,
+    /// Amount of notes sharing their beat with at least one other note, these notes are highlighted in game
+    pub multi_hits: u32,
+    /// Time in seconds when the last note ends, including hold notes
+    pub duration: f32,
+    /// Hit times in seconds of all notes, sorted
+    times: Vec<f32>,
+}
+
+impl ChartStatistics {
+    pub fn new<'a>(notes: impl IntoIterator<Item = &'a Note>, bpm_list: &BpmList) -> Self {
+        let mut counts = NoteCounts::default();
+        let mut beats: HashMap<Beat, u32> = HashMap::new();
+        let mut times = vec![];
+        let mut duration: f32 = 0.0;
+
+        for note in notes.into_iter().filter(|x| !x.fake) {
+            counts.count(&note.kind);
+            *beats.entry(note.beat.reduced()).or_insert(0) += 1;
+            times.push(bpm_list.time_at(note.beat));
+            duration = duration.max(bpm_list.time_at(note.end_beat()));
+        }
+
+        times.sort_by(f32::total_cmp);
+
+        Self {
+            notes: counts,
+            multi_hits: beats.values().filter(|x| **x > 1).sum(),
+            duration,
+            times,
+        }
+    }
+
+    /// The maximum combo of the chart, each note counts as one combo
+    pub fn combo(&self) -> u32 {
+        self.notes.total()
+    }
+
+    /// The average notes per second over the whole chart
+    pub fn average_density(&self) -> f32 {
+        if self.duration <= 0.0 {
+            0.0
+        } else {
+            self.combo() as f32 / self.duration
+        }
+    }
+
+    /// The notes-per-second curve of the chart, sampled with fixed-size windows of `window` seconds
+    ///
+    /// The `i`-th element is the average notes per second within `[i * window, (i + 1) * window)`
+    pub fn density(&self, window: f32) -> Vec<f32> {
+        assert!(window > 0.0, "window must be positive");
+
+        let Some(last) = self.times.last() else {
+            return vec![];
+        };
+
+        let mut curve = vec![0.0; (last / window).floor() as usize + 1];
+        for time in &self.times {
+            let index = (time.max(0.0) / window).floor() as usize;
+            curve[index] += 1.0 / window;
+        }
+
+        curve
+    }
+
+    /// Find the `window`-second long sliding window containing the most notes
+    ///
+    /// Returns [`None`] if the chart has no notes
+    pub fn peak_density(&self, window: f32) -> Option<DensityWindow> {
+        assert!(window > 0.0, "window must be positive");
+
+        let mut peak: Option<DensityWindow> = None;
+        let mut end = 0;
+        for (start, time) in self.times.iter().enumerate() {
+            while end < self.times.len() && self.times[end] < time + window {
+                end += 1;
+            }
+            let notes = (end - start) as u32;
+            match peak {
+                Some(x) if x.notes >= notes => {}
+                _ => {
+                    peak = Some(DensityWindow {
+                        start: *time,
+                        end: time + window,
+                        notes,
+                    })
+                }
+            }
+        }
+
+        peak
+    }
+
+    /// A heuristic difficulty estimate of the chart
+    ///
+    /// The estimate is an effective notes-per-second value: a weighted mix of the peak density in a 2-second window
+    /// and the average density, increased by the ratio of multi-hit notes. It is only meant for comparing charts
+    /// with each other and does not correspond to official levels
+    pub fn difficulty(&self) -> f32 {
+        let combo = self.combo();
+        if combo == 0 {
+            return 0.0;
+        }
+
+        let peak = self.peak_density(2.0).map_or(0.0, |x| x.notes_per_second());
+        let multi_hit_ratio = self.multi_hits as f32 / combo as f32;
+
+        (0.7 * peak + 0.3 * self.average_density()) * (1.0 + 0.5 * multi_hit_ratio)
+    }
+}
+
+impl From<&PhichainChart> for ChartStatistics {
+    fn from(chart: &PhichainChart) -> Self {
+        let lines = chart.iter_lines();
+        Self::new(
+            lines.iter().flat_map(|(_, line)| line.notes.iter()),
+            &chart.bpm_list,
+        )
+    }
+}
+
+impl From<&PrimitiveChart> for ChartStatistics {
+    fn from(chart: &PrimitiveChart) -> Self {
+        fn collect<'a>(line: &'a primitive::line::Line, notes: &mut Vec<&'a Note>) {
+            notes.extend(line.notes.iter());
+            for child in &line.children {
+                collect(child, notes);
+            }
+        }
+
+        let mut notes = vec![];
+        for line in &chart.lines {
+            collect(line, &mut notes);
+        }
+
+        Self::new(notes, &chart.bpm_list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat;
+
+    fn notes() -> Vec<Note> {
+        vec![
+            Note::new(NoteKind::Tap, true, beat!(0), 0.0, 1.0),
+            Note::new(NoteKind::Drag, true, beat!(0), 100.0, 1.0),
+            Note::new(NoteKind::Flick, true, beat!(1), 0.0, 1.0),
+            Note::new(NoteKind::Tap, true, beat!(1, 1, 2), 0.0, 1.0),
+            Note::new(
+                NoteKind::Hold {
+                    hold_beat: beat!(4),
+                },
+                true,
+                beat!(4),
+                0.0,
+                1.0,
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_counts() {
+        let statistics = ChartStatistics::new(&notes(), &BpmList::single(120.0));
+        assert_eq!(
+            statistics.notes,
+            NoteCounts {
+                tap: 2,
+                drag: 1,
+                hold: 1,
+                flick: 1,
+            }
+        );
+        assert_eq!(statistics.combo(), 5);
+        assert_eq!(statistics.multi_hits, 2);
+        assert_eq!(statistics.duration, 4.0);
+    }
+
+    #[test]
+    fn test_density() {
+        let statistics = ChartStatistics::new(&notes(), &BpmList::single(120.0));
+        // note times: 0.0, 0.0, 0.5, 0.75, 2.0
+        assert_eq!(statistics.density(1.0), vec![4.0, 0.0, 1.0]);
+        assert_eq!(statistics.average_density(), 5.0 / 4.0);
+        assert_eq!(
+            statistics.peak_density(1.0),
+            Some(DensityWindow {
+                start: 0.0,
+                end: 1.0,
+                notes: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn test_empty() {
+        let statistics = ChartStatistics::from(&PhichainChart::default());
+        assert_eq!(statistics.combo(), 0);
+        assert!(statistics.density(1.0).is_empty());
+        assert_eq!(statistics.peak_density(1.0), None);
+        assert_eq!(statistics.difficulty(), 0.0);
+    }
+
+    #[test]
+    fn test_nested_lines() {
+        let child = crate::serialization::LineWrapper {
+            notes: notes(),
+            ..Default::default()
+        };
+        let parent = crate::serialization::LineWrapper {
+            notes: notes(),
+            children: vec![child],
+            ..Default::default()
+        };
+
+        let chart = PhichainChart::new(0.0, BpmList::single(120.0), vec![parent]);
+        let statistics = ChartStatistics::from(&chart);
+        assert_eq!(statistics.combo(), 10);
+        assert!(statistics.difficulty() > 0.0);
+    }
+}
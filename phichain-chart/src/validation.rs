@@ -0,0 +1,331 @@
+//! Chart validation
+//!
+//! Walks a [`PhichainChart`] and reports common mistakes that would otherwise only be noticed in game
+
+use crate::beat::Beat;
+use crate::constants::CANVAS_WIDTH;
+use crate::event::{LineEvent, LineEventKind};
+use crate::line::LinePath;
+use crate::note::{Note, NoteKind};
+use crate::serialization::{LineWrapper, PhichainChart};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use strum::IntoEnumIterator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// Two events of the same kind are affecting the line at the same time
+    OverlappingEvents { kind: LineEventKind, other: Beat },
+    /// The start beat of an event is later than its end beat
+    InvertedEvent { kind: LineEventKind, end_beat: Beat },
+    /// A hold note with a zero or negative hold beat
+    EmptyHold { hold_beat: Beat },
+    /// A note placed far outside the canvas
+    NoteOutOfCanvas { x: f32 },
+    /// A line without any speed event
+    MissingSpeedEvent,
+}
+
+impl DiagnosticKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            DiagnosticKind::OverlappingEvents { .. } => Severity::Error,
+            DiagnosticKind::InvertedEvent { .. } => Severity::Error,
+            DiagnosticKind::EmptyHold { .. } => Severity::Error,
+            DiagnosticKind::NoteOutOfCanvas { .. } => Severity::Warning,
+            DiagnosticKind::MissingSpeedEvent => Severity::Warning,
+        }
+    }
+}
+
+impl Display for DiagnosticKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiagnosticKind::OverlappingEvents { kind, other } => {
+                write!(
+                    f,
+                    "{:?} event overlaps with the event starting at {:?}",
+                    kind, other
+                )
+            }
+            DiagnosticKind::InvertedEvent { kind, end_beat } => {
+                write!(
+                    f,
+                    "{:?} event ends before it starts (end beat {:?})",
+                    kind, end_beat
+                )
+            }
+            DiagnosticKind::EmptyHold { hold_beat } => {
+                write!(f, "hold note has a non-positive length {:?}", hold_beat)
+            }
+            DiagnosticKind::NoteOutOfCanvas { x } => {
+                write!(f, "note is placed far outside the canvas (x = {})", x)
+            }
+            DiagnosticKind::MissingSpeedEvent => write!(f, "line has no speed event"),
+        }
+    }
+}
+
+/// A problem found in a chart
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    /// The line where the problem is found
+    pub line: LinePath,
+    /// The beat where the problem is found, [`None`] if the problem is not related to a specific beat
+    pub beat: Option<Beat>,
+}
+
+impl Diagnostic {
+    pub fn new(kind: DiagnosticKind, line: LinePath, beat: Option<Beat>) -> Self {
+        Self {
+            severity: kind.severity(),
+            kind,
+            line,
+            beat,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: line {}", self.severity, self.line)?;
+        if let Some(beat) = self.beat {
+            write!(f, " at beat {:?}", beat)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+/// Notes with `|x|` larger than this are considered far outside the canvas
+///
+/// The visible range is `[-CANVAS_WIDTH / 2, CANVAS_WIDTH / 2]`, this leaves another half canvas as tolerance
+const MAX_NOTE_X: f32 = CANVAS_WIDTH;
+
+fn validate_notes(notes: &[Note], path: &LinePath, diagnostics: &mut Vec<Diagnostic>) {
+    for note in notes {
+        if let NoteKind::Hold { hold_beat } = note.kind {
+            if hold_beat <= Beat::ZERO {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticKind::EmptyHold { hold_beat },
+                    path.clone(),
+                    Some(note.beat),
+                ));
+            }
+        }
+
+        if note.x.abs() > MAX_NOTE_X {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::NoteOutOfCanvas { x: note.x },
+                path.clone(),
+                Some(note.beat),
+            ));
+        }
+    }
+}
+
+fn validate_events(events: &[LineEvent], path: &LinePath, diagnostics: &mut Vec<Diagnostic>) {
+    for event in events {
+        if event.start_beat > event.end_beat {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::InvertedEvent {
+                    kind: event.kind,
+                    end_beat: event.end_beat,
+                },
+                path.clone(),
+                Some(event.start_beat),
+            ));
+        }
+    }
+
+    for kind in LineEventKind::iter() {
+        let mut events = events
+            .iter()
+            .filter(|x| x.kind == kind && x.start_beat <= x.end_beat)
+            .collect::<Vec<_>>();
+        events.sort_by_key(|x| x.start_beat);
+
+        // the event with the latest end beat so far
+        let mut latest: Option<&LineEvent> = None;
+        for event in events {
+            if let Some(previous) = latest {
+                if event.start_beat < previous.end_beat {
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticKind::OverlappingEvents {
+                            kind,
+                            other: previous.start_beat,
+                        },
+                        path.clone(),
+                        Some(event.start_beat),
+                    ));
+                }
+            }
+            match latest {
+                Some(x) if x.end_beat >= event.end_beat => {}
+                _ => latest = Some(event),
+            }
+        }
+    }
+
+    if !events.iter().any(|x| x.kind.is_speed()) {
+        diagnostics.push(Diagnostic::new(
+            DiagnosticKind::MissingSpeedEvent,
+            path.clone(),
+            None,
+        ));
+    }
+}
+
+/// Validate a single line, without its children
+pub fn validate_line(line: &LineWrapper, path: &LinePath) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    validate_notes(&line.notes, path, &mut diagnostics);
+    validate_events(&line.events, path, &mut diagnostics);
+    diagnostics
+}
+
+/// Validate a chart, including all nested child lines
+///
+/// Diagnostics are ordered by line in depth-first order, then by the order they are found in each line
+pub fn validate(chart: &PhichainChart) -> Vec<Diagnostic> {
+    chart
+        .iter_lines()
+        .into_iter()
+        .flat_map(|(path, line)| validate_line(line, &path))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat;
+    use crate::event::LineEventValue;
+
+    fn event(kind: LineEventKind, start_beat: Beat, end_beat: Beat) -> LineEvent {
+        LineEvent {
+            id: None,
+            kind,
+            start_beat,
+            end_beat,
+            value: LineEventValue::constant(0.0),
+        }
+    }
+
+    #[test]
+    fn test_default_chart_is_valid() {
+        assert!(validate(&PhichainChart::default()).is_empty());
+    }
+
+    #[test]
+    fn test_overlapping_events() {
+        let mut line = LineWrapper::default();
+        line.events
+            .push(event(LineEventKind::X, beat!(0, 1, 2), beat!(2)));
+        line.events
+            .push(event(LineEventKind::Y, beat!(1), beat!(2)));
+
+        let diagnostics = validate_line(&line, &LinePath(vec![0]));
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                DiagnosticKind::OverlappingEvents {
+                    kind: LineEventKind::X,
+                    other: beat!(0),
+                },
+                LinePath(vec![0]),
+                Some(beat!(0, 1, 2)),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_adjacent_events_do_not_overlap() {
+        let mut line = LineWrapper::default();
+        line.events
+            .push(event(LineEventKind::X, beat!(1), beat!(2)));
+
+        assert!(validate_line(&line, &LinePath(vec![0])).is_empty());
+    }
+
+    #[test]
+    fn test_inverted_event() {
+        let mut line = LineWrapper::default();
+        line.events
+            .push(event(LineEventKind::X, beat!(3), beat!(2)));
+
+        let diagnostics = validate_line(&line, &LinePath(vec![0]));
+        assert_eq!(diagnostics.len(), 1);
+        assert!(matches!(
+            diagnostics[0].kind,
+            DiagnosticKind::InvertedEvent { .. }
+        ));
+        assert!(diagnostics[0].is_error());
+    }
+
+    #[test]
+    fn test_notes() {
+        let mut line = LineWrapper::default();
+        line.notes.push(Note::new(
+            NoteKind::Hold {
+                hold_beat: beat!(0),
+            },
+            true,
+            beat!(1),
+            0.0,
+            1.0,
+        ));
+        line.notes
+            .push(Note::new(NoteKind::Tap, true, beat!(2), 2000.0, 1.0));
+
+        let diagnostics = validate_line(&line, &LinePath(vec![0]));
+        assert_eq!(diagnostics.len(), 2);
+        assert!(matches!(
+            diagnostics[0].kind,
+            DiagnosticKind::EmptyHold { .. }
+        ));
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+        assert_eq!(diagnostics[1].beat, Some(beat!(2)));
+    }
+
+    #[test]
+    fn test_nested_children() {
+        let mut child = LineWrapper::default();
+        child.events.retain(|x| !x.kind.is_speed());
+
+        let mut parent = LineWrapper::default();
+        parent.children.push(LineWrapper::default());
+        parent.children.push(child);
+
+        let chart = PhichainChart::new(0.0, Default::default(), vec![parent]);
+        let diagnostics = validate(&chart);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::MissingSpeedEvent);
+        assert_eq!(diagnostics[0].line, LinePath(vec![0, 1]));
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "warning: line 0/1: line has no speed event"
+        );
+    }
+}
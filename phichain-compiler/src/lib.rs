@@ -1,14 +1,65 @@
+pub mod pipeline;
+pub mod source_map;
 mod steps;
 mod utils;
 
-use crate::steps::merge_children_line;
-use phichain_chart::primitive::{Format, PrimitiveChart};
+use crate::pipeline::Profile;
+use crate::source_map::SourceMap;
+use phichain_chart::primitive::PrimitiveChart;
+use phichain_chart::segmentation::{DEFAULT_RESOLUTION, DEFAULT_TOLERANCE};
 use phichain_chart::serialization::PhichainChart;
 
+pub use steps::{merge_children_line, MergeChildren, Segmentation, Simplification, Validation};
+
+/// Options of the compiler
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    /// The profile providing the steps to run
+    pub profile: Profile,
+    /// The maximum value error allowed when approximating curves with linear events
+    pub tolerance: f32,
+    /// Curves are never sampled finer than `1 / resolution` beat
+    pub resolution: u32,
+    /// Names of the steps to skip
+    pub disabled_steps: Vec<String>,
+    /// Fail compiling charts with validation errors, e.g. overlapping events, instead of logging them
+    pub deny_errors: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            profile: Profile::default(),
+            tolerance: DEFAULT_TOLERANCE,
+            resolution: DEFAULT_RESOLUTION,
+            disabled_steps: vec![],
+            deny_errors: false,
+        }
+    }
+}
+
 /// Compile a Phichain chart into a primitive chart
 pub fn compile(chart: PhichainChart) -> anyhow::Result<PrimitiveChart> {
-    let chart = merge_children_line(chart);
+    compile_with_options(chart, &CompileOptions::default())
+}
+
+/// Compile a Phichain chart into a primitive chart with the given options
+pub fn compile_with_options(
+    chart: PhichainChart,
+    options: &CompileOptions,
+) -> anyhow::Result<PrimitiveChart> {
+    Ok(compile_with_source_map(chart, options)?.0)
+}
+
+/// Same as [`compile_with_options`], along with a source map tracing the primitive chart back to the Phichain chart
+pub fn compile_with_source_map(
+    chart: PhichainChart,
+    options: &CompileOptions,
+) -> anyhow::Result<(PrimitiveChart, SourceMap)> {
+    let mut pipeline = options.profile.pipeline(options);
+    for step in &options.disabled_steps {
+        pipeline.set_enabled(step, false)?;
+    }
 
-    // TODO: move into_primitive implementation here and use compile() in into_primitive
-    chart.into_primitive()
+    pipeline.compile_with_source_map(chart)
 }
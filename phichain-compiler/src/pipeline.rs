@@ -0,0 +1,216 @@
+//! The compiler pipeline
+//!
+//! A [`Pipeline`] runs a list of [`Step`]s over a chart in order. Each step carries its own options and can be
+//! switched off by name. [`Profile`]s provide pipelines for common targets
+//!
+//! Steps keep a [`SourceMap`] up to date while changing the chart, so the compiled chart can be traced back to the
+//! source chart
+
+use crate::source_map::SourceMap;
+use crate::steps::{MergeChildren, Segmentation, Simplification, Validation};
+use crate::CompileOptions;
+use anyhow::{bail, Context};
+use phichain_chart::primitive::{Format, PrimitiveChart};
+use phichain_chart::serialization::PhichainChart;
+use strum::{Display, EnumIter, EnumString};
+
+/// A step of the compiler pipeline
+pub trait Step {
+    /// The name of the step, used to switch the step on and off
+    fn name(&self) -> &'static str;
+
+    /// Run the step over the chart
+    ///
+    /// `sources` mirrors the chart and must be updated to match the returned chart if the step changes its lines,
+    /// events or notes
+    fn run(&self, chart: PhichainChart, sources: &mut SourceMap) -> anyhow::Result<PhichainChart>;
+}
+
+struct PipelineStep {
+    step: Box<dyn Step>,
+    enabled: bool,
+}
+
+/// A list of steps run over a chart in order
+#[derive(Default)]
+pub struct Pipeline {
+    steps: Vec<PipelineStep>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an enabled step to the pipeline
+    pub fn step(mut self, step: impl Step + 'static) -> Self {
+        self.steps.push(PipelineStep {
+            step: Box::new(step),
+            enabled: true,
+        });
+        self
+    }
+
+    /// Switch a step on or off by its name
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> anyhow::Result<()> {
+        let Some(step) = self.steps.iter_mut().find(|x| x.step.name() == name) else {
+            bail!(
+                "Unknown step `{}`, expected one of: {}",
+                name,
+                self.names().collect::<Vec<_>>().join(", ")
+            );
+        };
+        step.enabled = enabled;
+        Ok(())
+    }
+
+    /// The names of all steps in the pipeline, in order
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.steps.iter().map(|x| x.step.name())
+    }
+
+    /// Run all enabled steps over the chart
+    pub fn run(&self, chart: PhichainChart) -> anyhow::Result<PhichainChart> {
+        Ok(self.run_with_source_map(chart)?.0)
+    }
+
+    /// Run all enabled steps over the chart, along with the source map of the result
+    pub fn run_with_source_map(
+        &self,
+        mut chart: PhichainChart,
+    ) -> anyhow::Result<(PhichainChart, SourceMap)> {
+        let mut sources = SourceMap::new(&chart);
+
+        for step in self.steps.iter().filter(|x| x.enabled) {
+            chart = step
+                .step
+                .run(chart, &mut sources)
+                .with_context(|| format!("Failed to run step `{}`", step.step.name()))?;
+        }
+
+        Ok((chart, sources))
+    }
+
+    /// Run all enabled steps over the chart and convert the result into a primitive chart
+    pub fn compile(&self, chart: PhichainChart) -> anyhow::Result<PrimitiveChart> {
+        Ok(self.compile_with_source_map(chart)?.0)
+    }
+
+    /// Same as [`Pipeline::compile`], along with the source map of the primitive chart
+    ///
+    /// The line hierarchy, events and notes are kept in order when converting into a primitive chart, so the source
+    /// map of the compiled Phichain chart applies to the primitive chart as well
+    pub fn compile_with_source_map(
+        &self,
+        chart: PhichainChart,
+    ) -> anyhow::Result<(PrimitiveChart, SourceMap)> {
+        let (chart, sources) = self.run_with_source_map(chart)?;
+        // TODO: move into_primitive implementation here and use compile() in into_primitive
+        Ok((chart.into_primitive()?, sources))
+    }
+}
+
+/// Preset pipelines for common targets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Display, EnumString, EnumIter)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Profile {
+    /// For formats without line hierarchy and easings, e.g. the official format
+    ///
+    /// Merges child lines, removes redundant events, cuts eased events into linear events and validates the result
+    #[default]
+    OfficialSafe,
+    /// For formats supporting everything a Phichain chart has, e.g. RPE
+    ///
+    /// Removes redundant events and validates the chart
+    RpeLossless,
+}
+
+impl Profile {
+    pub fn pipeline(&self, options: &CompileOptions) -> Pipeline {
+        match self {
+            Profile::OfficialSafe => Pipeline::new()
+                .step(MergeChildren {
+                    tolerance: options.tolerance,
+                    resolution: options.resolution,
+                })
+                .step(Simplification)
+                .step(Segmentation {
+                    tolerance: options.tolerance,
+                    resolution: options.resolution,
+                })
+                .step(Validation {
+                    deny_errors: options.deny_errors,
+                    ..Default::default()
+                }),
+            Profile::RpeLossless => Pipeline::new().step(Simplification).step(Validation {
+                deny_errors: options.deny_errors,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use phichain_chart::beat;
+    use phichain_chart::easing::Easing;
+    use phichain_chart::event::{LineEvent, LineEventKind, LineEventValue};
+    use phichain_chart::serialization::LineWrapper;
+
+    #[test]
+    fn test_set_enabled() {
+        let mut pipeline = Profile::OfficialSafe.pipeline(&CompileOptions::default());
+        assert_eq!(
+            pipeline.names().collect::<Vec<_>>(),
+            vec![
+                "merge-children",
+                "simplification",
+                "segmentation",
+                "validation"
+            ]
+        );
+
+        assert!(pipeline.set_enabled("segmentation", false).is_ok());
+        assert!(pipeline.set_enabled("unknown", false).is_err());
+    }
+
+    fn overlapping_chart() -> PhichainChart {
+        let mut line = LineWrapper::default();
+        line.events.extend([
+            LineEvent {
+                id: None,
+                kind: LineEventKind::X,
+                start_beat: beat!(1),
+                end_beat: beat!(3),
+                value: LineEventValue::transition(0.0, 100.0, Easing::Linear),
+            },
+            LineEvent {
+                id: None,
+                kind: LineEventKind::X,
+                start_beat: beat!(2),
+                end_beat: beat!(4),
+                value: LineEventValue::constant(50.0),
+            },
+        ]);
+        PhichainChart::new(0.0, Default::default(), vec![line])
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        assert!(crate::compile(overlapping_chart()).is_ok());
+
+        let options = CompileOptions {
+            deny_errors: true,
+            ..Default::default()
+        };
+        assert!(crate::compile_with_options(overlapping_chart(), &options).is_err());
+    }
+
+    #[test]
+    fn test_profile_from_str() {
+        assert_eq!("official-safe".parse(), Ok(Profile::OfficialSafe));
+        assert_eq!("rpe-lossless".parse(), Ok(Profile::RpeLossless));
+        assert_eq!(Profile::RpeLossless.to_string(), "rpe-lossless");
+    }
+}
@@ -0,0 +1,168 @@
+//! Source maps of compiled charts
+//!
+//! Compiling a chart merges, splits and removes lines, events and notes. A [`SourceMap`] records for each line,
+//! event and note of the compiled chart where it comes from in the source chart, so problems found in an exported
+//! chart can be traced back to what the user edited
+
+use phichain_chart::line::LinePath;
+use phichain_chart::serialization::{LineWrapper, PhichainChart};
+use serde::{Deserialize, Serialize};
+
+/// An event of the source chart
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EventSource {
+    /// The path of the source line
+    pub line: LinePath,
+    /// The index of the event in the events of the source line
+    pub index: usize,
+}
+
+/// Where a line of the compiled chart and its events and notes come from
+///
+/// Mirrors the line hierarchy of the compiled chart
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineSource {
+    /// The path of the source line
+    pub line: LinePath,
+    /// For each note, its index in the notes of the source line
+    pub notes: Vec<usize>,
+    /// For each event, the source events its value is computed from
+    pub events: Vec<Vec<EventSource>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<LineSource>,
+}
+
+impl LineSource {
+    /// The source of a line mapping to itself, along with its child lines
+    pub fn new(line: &LineWrapper, path: LinePath) -> Self {
+        Self {
+            notes: (0..line.notes.len()).collect(),
+            events: (0..line.events.len())
+                .map(|index| {
+                    vec![EventSource {
+                        line: path.clone(),
+                        index,
+                    }]
+                })
+                .collect(),
+            children: line
+                .children
+                .iter()
+                .enumerate()
+                .map(|(index, child)| Self::new(child, path.child(index)))
+                .collect(),
+            line: path,
+        }
+    }
+
+    /// Update the sources after the events of the line have changed
+    ///
+    /// `origins` lists, for each new event, the indices of the old events it is computed from
+    pub fn remap_events(&mut self, origins: &[Vec<usize>]) {
+        self.events = remap(&self.events, origins);
+    }
+}
+
+/// Combine the sources of events for each new event, `origins` lists the indices into `sources` each new event is
+/// computed from
+pub fn remap(sources: &[Vec<EventSource>], origins: &[Vec<usize>]) -> Vec<Vec<EventSource>> {
+    origins
+        .iter()
+        .map(|origin| {
+            let mut combined: Vec<EventSource> = vec![];
+            for source in origin.iter().flat_map(|x| &sources[*x]) {
+                if !combined.contains(source) {
+                    combined.push(source.clone());
+                }
+            }
+            combined
+        })
+        .collect()
+}
+
+/// Where the lines, events and notes of a compiled chart come from
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceMap {
+    /// Mirrors the root lines of the compiled chart
+    pub lines: Vec<LineSource>,
+}
+
+impl SourceMap {
+    /// The source map of a chart which is not compiled yet, mapping everything to itself
+    pub fn new(chart: &PhichainChart) -> Self {
+        Self {
+            lines: chart
+                .lines
+                .iter()
+                .enumerate()
+                .map(|(index, line)| LineSource::new(line, LinePath(vec![index])))
+                .collect(),
+        }
+    }
+
+    /// Get the source of a line of the compiled chart
+    pub fn line(&self, path: &LinePath) -> Option<&LineSource> {
+        let (first, rest) = path.0.split_first()?;
+        let mut line = self.lines.get(*first)?;
+        for index in rest {
+            line = line.children.get(*index)?;
+        }
+        Some(line)
+    }
+
+    /// Get the source events of an event of the compiled chart
+    pub fn event(&self, line: &LinePath, index: usize) -> Option<&[EventSource]> {
+        self.line(line)?.events.get(index).map(Vec::as_slice)
+    }
+
+    /// Get the source line path and note index of a note of the compiled chart
+    pub fn note(&self, line: &LinePath, index: usize) -> Option<(&LinePath, usize)> {
+        let source = self.line(line)?;
+        source.notes.get(index).map(|x| (&source.line, *x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity() {
+        let chart = PhichainChart {
+            lines: vec![LineWrapper {
+                children: vec![Default::default()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let map = SourceMap::new(&chart);
+
+        let path = LinePath(vec![0, 0]);
+        assert_eq!(map.line(&path).unwrap().line, path);
+        assert_eq!(
+            map.event(&path, 2),
+            Some(
+                [EventSource {
+                    line: path.clone(),
+                    index: 2
+                }]
+                .as_slice()
+            )
+        );
+        assert_eq!(map.event(&path, 5), None);
+        assert_eq!(map.line(&LinePath(vec![1])), None);
+    }
+
+    #[test]
+    fn test_remap() {
+        let mut source = LineSource::new(&LineWrapper::default(), LinePath(vec![0]));
+        source.remap_events(&[vec![1, 0, 1], vec![]]);
+
+        let indices = source
+            .events
+            .iter()
+            .map(|x| x.iter().map(|x| x.index).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(indices, vec![vec![1, 0], vec![]]);
+    }
+}
@@ -1,157 +1,687 @@
+use crate::pipeline::Step;
+use crate::source_map::{remap, LineSource, SourceMap};
 use crate::utils::EventSequence;
-use nalgebra::{Isometry2, Rotation2, Vector2};
-use phichain_chart::beat;
+use nalgebra::{Point2, Rotation2};
+use phichain_chart::beat::Beat;
 use phichain_chart::easing::Easing;
 use phichain_chart::event::{LineEvent, LineEventKind, LineEventValue};
+use phichain_chart::segmentation::{segment_with_resolution, Segment};
 use phichain_chart::serialization::{LineWrapper, PhichainChart};
 
-fn merge(parent: LineWrapper) -> Vec<LineWrapper> {
-    if parent.children.is_empty() {
-        vec![parent]
-    } else {
-        let children = parent
-            .children
-            .iter()
-            .flat_map(|x| merge(x.clone()))
-            .collect::<Vec<_>>();
+/// Evaluate a sequence of events at a beat which is not a boundary of any event
+fn value_at(events: &[LineEvent], beat: f32) -> f32 {
+    let mut ret = 0.0;
 
-        let mut merged_children = vec![];
+    for event in events {
+        if let Some(value) = event.evaluate(beat).value() {
+            ret = value;
+        }
+    }
+
+    ret
+}
 
-        for child in children {
-            let mut merged_move_events = vec![];
-            let mut merged_rotate_events = vec![];
+/// Place a child transform (x, y, rotation in degrees) into its parent's space
+///
+/// The position of the child is always rotated with the parent, while its rotation is only added to the parent's one
+/// when `inherit_rotation` is set
+fn compose(parent: [f32; 3], child: [f32; 3], inherit_rotation: bool) -> [f32; 3] {
+    let [parent_x, parent_y, parent_rotation] = parent;
+    let [child_x, child_y, child_rotation] = child;
 
-            let mut splits = vec![];
+    let position = Rotation2::new(parent_rotation.to_radians()) * Point2::new(child_x, child_y);
 
-            for event in parent
-                .events
-                .iter()
-                .filter(|x| x.kind.is_x() || x.kind.is_y() || x.kind.is_rotation())
-            {
-                splits.push(event.start_beat);
-                splits.push(event.end_beat);
-            }
-            for event in child
-                .events
-                .iter()
-                .filter(|x| x.kind.is_x() || x.kind.is_y() || x.kind.is_rotation())
-            {
-                splits.push(event.start_beat);
-                splits.push(event.end_beat);
-            }
-
-            splits.dedup();
-            splits.sort();
-
-            let minimum = beat!(1, 32);
-
-            if let (Some(first), Some(last)) = (splits.first().copied(), splits.last().copied()) {
-                let mut current = first;
-                while current < last {
-                    let start_beat = current;
-                    let end_beat = current + minimum;
-
-                    macro_rules! evaluate {
-                        ($target:ident, $filter:ident) => {
-                            (
-                                $target
-                                    .events
-                                    .$filter()
-                                    .evaluate_start_no_effect(start_beat),
-                                $target.events.$filter().evaluate(end_beat),
-                            )
-                        };
-                    }
-
-                    macro_rules! evaluate_line {
-                        ($target:ident) => {{
-                            let (start_x, end_x) = evaluate!($target, x);
-                            let (start_y, end_y) = evaluate!($target, y);
-                            let (start_rotation, end_rotation) = evaluate!($target, rotation);
-
-                            let start = Isometry2::new(
-                                Vector2::new(start_x, start_y),
-                                Rotation2::new(start_rotation.to_radians()).angle(),
-                            );
-                            let end = Isometry2::new(
-                                Vector2::new(end_x, end_y),
-                                Rotation2::new(end_rotation.to_radians()).angle(),
-                            );
-
-                            (start, end)
-                        }};
-                    }
-
-                    let (parent_start, parent_end) = evaluate_line!(parent);
-                    let (child_start, child_end) = evaluate_line!(child);
-
-                    let start = parent_start * child_start;
-                    let end = parent_end * child_end;
-
-                    merged_move_events.push(LineEvent {
-                        kind: LineEventKind::X,
-                        start_beat,
-                        end_beat,
-                        value: LineEventValue::transition(
-                            start.translation.x,
-                            end.translation.x,
-                            Easing::Linear,
-                        ),
-                    });
-                    merged_move_events.push(LineEvent {
-                        kind: LineEventKind::Y,
-                        start_beat,
-                        end_beat,
-                        value: LineEventValue::transition(
-                            start.translation.y,
-                            end.translation.y,
-                            Easing::Linear,
-                        ),
-                    });
-                    merged_rotate_events.push(LineEvent {
-                        kind: LineEventKind::Rotation,
-                        start_beat,
-                        end_beat,
-                        value: LineEventValue::transition(
-                            start.rotation.angle().to_degrees(),
-                            end.rotation.angle().to_degrees(),
-                            Easing::Linear,
-                        ),
-                    });
-
-                    current += minimum;
-                }
-            }
-
-            let other_events = child
-                .events
+    // rotations are added instead of composed into an angle, so they are not wrapped into (-180, 180]
+    [
+        parent_x + position.x,
+        parent_y + position.y,
+        if inherit_rotation {
+            parent_rotation + child_rotation
+        } else {
+            child_rotation
+        },
+    ]
+}
+
+/// How a sequence of events changes inside a window without any event boundary in it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    Constant,
+    Linear,
+    Eased,
+}
+
+/// The shape of a sequence of events inside the window starting at `start_beat`
+fn shape(events: &[LineEvent], start_beat: Beat) -> Shape {
+    // the value inside the window comes from the last event in the list which has started
+    match events.iter().rev().find(|x| x.start_beat <= start_beat) {
+        Some(event) if event.end_beat > start_beat => match event.value {
+            LineEventValue::Constant(_) => Shape::Constant,
+            LineEventValue::Transition { start, end, .. } if start == end => Shape::Constant,
+            LineEventValue::Transition {
+                easing: Easing::Linear,
+                ..
+            } => Shape::Linear,
+            LineEventValue::Transition { .. } => Shape::Eased,
+        },
+        _ => Shape::Constant,
+    }
+}
+
+/// The index of the event of a kind giving the value inside the window starting at `start_beat`, see [`shape`]
+fn active(events: &[LineEvent], kind: LineEventKind, start_beat: Beat) -> Option<usize> {
+    events
+        .iter()
+        .rposition(|x| x.kind == kind && x.start_beat <= start_beat)
+}
+
+/// Collect the start and end beats of the events, sorted and deduplicated
+fn splits<'a>(events: impl Iterator<Item = &'a LineEvent>) -> Vec<Beat> {
+    let mut splits = events
+        .flat_map(|x| [x.start_beat, x.end_beat])
+        .collect::<Vec<_>>();
+    splits.sort();
+    splits.dedup();
+    splits
+}
+
+fn linear(
+    kind: LineEventKind,
+    start_beat: Beat,
+    end_beat: Beat,
+    start: f32,
+    end: f32,
+) -> LineEvent {
+    LineEvent {
+        id: None,
+        kind,
+        start_beat,
+        end_beat,
+        value: LineEventValue::transition(start, end, Easing::Linear),
+    }
+}
+
+/// Place the X, Y and Rotation events of a child line into the space of its parent
+///
+/// Along with the merged events, returns the indices of the events each merged event is computed from, counting the
+/// events of the parent first and then the events of the child
+fn merge_transform(
+    parent_events: &[LineEvent],
+    child_events: &[LineEvent],
+    inherit_rotation: bool,
+    tolerance: f32,
+    resolution: u32,
+) -> (Vec<LineEvent>, Vec<Vec<usize>>) {
+    let channels = |events: &[LineEvent]| {
+        let events = events.to_vec();
+        [events.x(), events.y(), events.rotation()]
+    };
+    let (parent, child) = (channels(parent_events), channels(child_events));
+
+    let mut events = vec![];
+    let mut origins = vec![];
+
+    for window in splits(parent.iter().chain(child.iter()).flatten()).windows(2) {
+        let (start_beat, end_beat) = (window[0], window[1]);
+
+        // the end of a window is evaluated without the events starting there, so jumps at the boundaries are kept
+        let [start_x, start_y, start_rotation] = compose(
+            parent.each_ref().map(|x| x.evaluate(start_beat)),
+            child.each_ref().map(|x| x.evaluate(start_beat)),
+            inherit_rotation,
+        );
+        let [end_x, end_y, end_rotation] = compose(
+            parent
+                .each_ref()
+                .map(|x| x.evaluate_start_no_effect(end_beat)),
+            child
+                .each_ref()
+                .map(|x| x.evaluate_start_no_effect(end_beat)),
+            inherit_rotation,
+        );
+
+        let composed = |beat: f32| {
+            compose(
+                parent.each_ref().map(|x| value_at(x, beat)),
+                child.each_ref().map(|x| value_at(x, beat)),
+                inherit_rotation,
+            )
+        };
+
+        let [parent_x, parent_y, parent_rotation] = parent.each_ref().map(|x| shape(x, start_beat));
+        let [child_x, child_y, child_rotation] = child.each_ref().map(|x| shape(x, start_beat));
+
+        // linear motion in a fixed frame stays linear, only rotating frames and eased motion are sampled
+        let translation_exact = parent_rotation == Shape::Constant
+            && [parent_x, parent_y, child_x, child_y]
                 .iter()
-                .filter(|x| !x.kind.is_x() && !x.kind.is_y() && !x.kind.is_rotation())
-                .cloned()
-                .collect::<Vec<_>>();
-
-            let merged = LineWrapper {
-                events: [other_events, merged_move_events, merged_rotate_events].concat(),
-                children: vec![],
-                ..child
-            };
+                .all(|x| *x != Shape::Eased);
+        let rotation_exact = child_rotation != Shape::Eased
+            && (!inherit_rotation || parent_rotation != Shape::Eased);
+
+        let parent_active = |kind| active(parent_events, kind, start_beat);
+        let child_active =
+            |kind| active(child_events, kind, start_beat).map(|x| x + parent_events.len());
+        let translation_origins = [
+            parent_active(LineEventKind::X),
+            parent_active(LineEventKind::Y),
+            parent_active(LineEventKind::Rotation),
+            child_active(LineEventKind::X),
+            child_active(LineEventKind::Y),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        let rotation_origins = [
+            inherit_rotation
+                .then(|| parent_active(LineEventKind::Rotation))
+                .flatten(),
+            child_active(LineEventKind::Rotation),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        let translation = if translation_exact {
+            vec![Segment {
+                start_beat,
+                end_beat,
+                start: [start_x, start_y],
+                end: [end_x, end_y],
+            }]
+        } else {
+            segment_with_resolution(
+                start_beat,
+                end_beat,
+                [start_x, start_y],
+                [end_x, end_y],
+                tolerance,
+                resolution,
+                |beat| {
+                    let [x, y, _] = composed(beat);
+                    [x, y]
+                },
+            )
+        };
+
+        let rotation = if rotation_exact {
+            vec![Segment {
+                start_beat,
+                end_beat,
+                start: [start_rotation],
+                end: [end_rotation],
+            }]
+        } else {
+            segment_with_resolution(
+                start_beat,
+                end_beat,
+                [start_rotation],
+                [end_rotation],
+                tolerance,
+                resolution,
+                |beat| [composed(beat)[2]],
+            )
+        };
 
-            merged_children.push(merged);
+        for x in translation {
+            events.push(linear(
+                LineEventKind::X,
+                x.start_beat,
+                x.end_beat,
+                x.start[0],
+                x.end[0],
+            ));
+            events.push(linear(
+                LineEventKind::Y,
+                x.start_beat,
+                x.end_beat,
+                x.start[1],
+                x.end[1],
+            ));
+            origins.push(translation_origins.clone());
+            origins.push(translation_origins.clone());
         }
+        for x in rotation {
+            events.push(linear(
+                LineEventKind::Rotation,
+                x.start_beat,
+                x.end_beat,
+                x.start[0],
+                x.end[0],
+            ));
+            origins.push(rotation_origins.clone());
+        }
+    }
+
+    (events, origins)
+}
+
+/// Multiply the opacity events of a child line by the opacity events of its parent, with `255` being fully opaque
+///
+/// The indices of the events each merged event is computed from are returned like [`merge_transform`]
+fn merge_opacity(
+    parent_events: &[LineEvent],
+    child_events: &[LineEvent],
+    tolerance: f32,
+    resolution: u32,
+) -> (Vec<LineEvent>, Vec<Vec<usize>>) {
+    let multiply = |parent: f32, child: f32| parent * child / 255.0;
+    let (parent, child) = (
+        parent_events.to_vec().opacity(),
+        child_events.to_vec().opacity(),
+    );
+
+    let mut events = vec![];
+    let mut origins = vec![];
 
-        merged_children.push(parent);
+    for window in splits(parent.iter().chain(child.iter())).windows(2) {
+        let (start_beat, end_beat) = (window[0], window[1]);
 
-        merged_children
+        let start = multiply(parent.evaluate(start_beat), child.evaluate(start_beat));
+        let end = multiply(
+            parent.evaluate_start_no_effect(end_beat),
+            child.evaluate_start_no_effect(end_beat),
+        );
+
+        // a product only stays linear when one of the factors is constant
+        let exact = match (shape(&parent, start_beat), shape(&child, start_beat)) {
+            (Shape::Constant, x) | (x, Shape::Constant) => x != Shape::Eased,
+            _ => false,
+        };
+
+        let segments = if exact {
+            vec![Segment {
+                start_beat,
+                end_beat,
+                start: [start],
+                end: [end],
+            }]
+        } else {
+            segment_with_resolution(
+                start_beat,
+                end_beat,
+                [start],
+                [end],
+                tolerance,
+                resolution,
+                |beat| [multiply(value_at(&parent, beat), value_at(&child, beat))],
+            )
+        };
+
+        let window_origins = [
+            active(parent_events, LineEventKind::Opacity, start_beat),
+            active(child_events, LineEventKind::Opacity, start_beat)
+                .map(|x| x + parent_events.len()),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        for x in segments {
+            events.push(linear(
+                LineEventKind::Opacity,
+                x.start_beat,
+                x.end_beat,
+                x.start[0],
+                x.end[0],
+            ));
+            origins.push(window_origins.clone());
+        }
     }
+
+    (events, origins)
 }
 
-/// Flatten all children lines into the root level, calculate event propagation for X, Y and Rotate events
-pub fn merge_children_line(chart: PhichainChart) -> PhichainChart {
-    let mut lines = vec![];
+/// Place a child line into the space of its parent, the parent must have been merged into the root level already
+fn merge_line(
+    (parent, parent_source): (&LineWrapper, &LineSource),
+    (child, child_source): (LineWrapper, LineSource),
+    tolerance: f32,
+    resolution: u32,
+) -> (LineWrapper, LineSource) {
+    let inherit = child.line.inherit;
 
-    for line in chart.lines {
-        lines.append(&mut merge(line));
+    let (mut events, mut origins) = merge_transform(
+        &parent.events,
+        &child.events,
+        inherit.rotation,
+        tolerance,
+        resolution,
+    );
+
+    if inherit.opacity {
+        let (opacity_events, opacity_origins) =
+            merge_opacity(&parent.events, &child.events, tolerance, resolution);
+        events.extend(opacity_events);
+        origins.extend(opacity_origins);
     }
 
+    // events of these kinds are replaced by the merged ones
+    let merged = |x: &LineEvent| {
+        x.kind.is_x()
+            || x.kind.is_y()
+            || x.kind.is_rotation()
+            || (inherit.opacity && x.kind.is_opacity())
+    };
+    let kept = (0..child.events.len())
+        .filter(|x| !merged(&child.events[*x]))
+        .collect::<Vec<_>>();
+
+    let origins = kept
+        .iter()
+        .map(|x| vec![x + parent.events.len()])
+        .chain(origins)
+        .collect::<Vec<_>>();
+    let sources = [parent_source.events.clone(), child_source.events].concat();
+
+    (
+        LineWrapper {
+            events: [kept.iter().map(|x| child.events[*x]).collect(), events].concat(),
+            children: vec![],
+            ..child
+        },
+        LineSource {
+            events: remap(&sources, &origins),
+            children: vec![],
+            ..child_source
+        },
+    )
+}
+
+/// Merge a line and all of its descendants into `lines`, children come before their parent
+///
+/// Lines are merged from the root down, so each line is placed into the space of a parent which has no parent any
+/// more. This keeps a line which does not inherit the rotation of its parent in the rotating frame of the parent,
+/// like a transform hierarchy does
+fn merge(
+    (mut line, mut source): (LineWrapper, LineSource),
+    parent: Option<(&LineWrapper, &LineSource)>,
+    tolerance: f32,
+    resolution: u32,
+    lines: &mut Vec<(LineWrapper, LineSource)>,
+) {
+    let children = std::mem::take(&mut line.children);
+    let child_sources = std::mem::take(&mut source.children);
+
+    let merged = match parent {
+        Some(parent) => merge_line(parent, (line, source), tolerance, resolution),
+        None => (line, source),
+    };
+
+    for child in children.into_iter().zip(child_sources) {
+        merge(
+            child,
+            Some((&merged.0, &merged.1)),
+            tolerance,
+            resolution,
+            lines,
+        );
+    }
+
+    lines.push(merged);
+}
+
+/// Same as [`merge_children_line`], keeping the source map of the chart up to date
+fn merge_with_sources(
+    chart: PhichainChart,
+    sources: &mut SourceMap,
+    tolerance: f32,
+    resolution: u32,
+) -> PhichainChart {
+    let mut merged = vec![];
+
+    for line in chart
+        .lines
+        .into_iter()
+        .zip(std::mem::take(&mut sources.lines))
+    {
+        merge(line, None, tolerance, resolution, &mut merged);
+    }
+
+    let (lines, line_sources) = merged.into_iter().unzip();
+    sources.lines = line_sources;
+
     PhichainChart { lines, ..chart }
 }
+
+/// Flatten all children lines into the root level, calculate event propagation for X, Y and Rotate events, as well as
+/// Opacity events for lines inheriting the opacity of their parent
+///
+/// Linear motion without a rotating parent is merged exactly. Otherwise the merged motion is approximated with
+/// linear events, `tolerance` is the maximum value error allowed and segments are never shorter than
+/// `1 / resolution` beat
+pub fn merge_children_line(chart: PhichainChart, tolerance: f32, resolution: u32) -> PhichainChart {
+    let mut sources = SourceMap::new(&chart);
+    merge_with_sources(chart, &mut sources, tolerance, resolution)
+}
+
+/// Merge child lines into the root level, for formats without line hierarchy
+pub struct MergeChildren {
+    /// The maximum value error allowed when approximating the merged motion with linear events
+    pub tolerance: f32,
+    /// Sampled segments are never shorter than `1 / resolution` beat
+    pub resolution: u32,
+}
+
+impl Step for MergeChildren {
+    fn name(&self) -> &'static str {
+        "merge-children"
+    }
+
+    fn run(&self, chart: PhichainChart, sources: &mut SourceMap) -> anyhow::Result<PhichainChart> {
+        Ok(merge_with_sources(
+            chart,
+            sources,
+            self.tolerance,
+            self.resolution,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_map::EventSource;
+    use phichain_chart::beat;
+    use phichain_chart::easing::Tween;
+    use phichain_chart::line::{Inheritance, Line, LinePath};
+    use phichain_chart::segmentation::{DEFAULT_RESOLUTION, DEFAULT_TOLERANCE};
+
+    fn event(kind: LineEventKind, start: f32, end: f32, easing: Easing) -> LineEvent {
+        LineEvent {
+            id: None,
+            kind,
+            start_beat: beat!(0),
+            end_beat: beat!(4),
+            value: LineEventValue::transition(start, end, easing),
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let child = LineWrapper {
+            events: vec![
+                event(LineEventKind::X, 100.0, 100.0, Easing::Linear),
+                event(LineEventKind::Y, 0.0, 0.0, Easing::Linear),
+            ],
+            ..Default::default()
+        };
+        let parent = LineWrapper {
+            events: vec![
+                event(LineEventKind::X, 0.0, 200.0, Easing::EaseInOutSine),
+                event(LineEventKind::Rotation, 90.0, 90.0, Easing::Linear),
+            ],
+            children: vec![child],
+            ..Default::default()
+        };
+        let chart = PhichainChart {
+            lines: vec![parent],
+            ..Default::default()
+        };
+
+        let merged = merge_children_line(chart, DEFAULT_TOLERANCE, DEFAULT_RESOLUTION);
+        assert_eq!(merged.lines.len(), 2);
+        assert!(merged.lines.iter().all(|x| x.children.is_empty()));
+
+        let child = &merged.lines[0];
+        for i in 0..=64 {
+            let beat = beat!(i, 16);
+            let parent_x = 0.0.ease_to(200.0, beat.value() / 4.0, Easing::EaseInOutSine);
+            assert!((child.events.x().evaluate(beat) - parent_x).abs() <= DEFAULT_TOLERANCE * 1.01);
+            assert!((child.events.y().evaluate(beat) - 100.0).abs() < 1e-3);
+            assert!((child.events.rotation().evaluate(beat) - 90.0).abs() < 1e-3);
+        }
+        assert!(child.events.x().len() < 4 * 32);
+        assert_eq!(child.events.x().last().unwrap().end_beat, beat!(4));
+    }
+
+    #[test]
+    fn test_merge_linear_exactly() {
+        let child = LineWrapper {
+            events: vec![
+                event(LineEventKind::X, 0.0, 100.0, Easing::Linear),
+                event(LineEventKind::Rotation, 0.0, 45.0, Easing::Linear),
+            ],
+            ..Default::default()
+        };
+        let parent = LineWrapper {
+            events: vec![
+                event(LineEventKind::X, 0.0, 200.0, Easing::Linear),
+                event(LineEventKind::Y, 50.0, -50.0, Easing::Linear),
+            ],
+            children: vec![child],
+            ..Default::default()
+        };
+        let chart = PhichainChart {
+            lines: vec![parent],
+            ..Default::default()
+        };
+
+        let merged = merge_children_line(chart, DEFAULT_TOLERANCE, DEFAULT_RESOLUTION);
+        let child = &merged.lines[0];
+        assert_eq!(
+            child.events.x(),
+            vec![event(LineEventKind::X, 0.0, 300.0, Easing::Linear)]
+        );
+        assert_eq!(
+            child.events.y(),
+            vec![event(LineEventKind::Y, 50.0, -50.0, Easing::Linear)]
+        );
+        assert_eq!(
+            child.events.rotation(),
+            vec![event(LineEventKind::Rotation, 0.0, 45.0, Easing::Linear)]
+        );
+    }
+
+    #[test]
+    fn test_sample_rotating_parent() {
+        let child = LineWrapper {
+            events: vec![event(LineEventKind::X, 100.0, 100.0, Easing::Linear)],
+            ..Default::default()
+        };
+        let parent = LineWrapper {
+            events: vec![event(LineEventKind::Rotation, 0.0, 90.0, Easing::Linear)],
+            children: vec![child],
+            ..Default::default()
+        };
+        let chart = PhichainChart {
+            lines: vec![parent],
+            ..Default::default()
+        };
+
+        let coarse = merge_children_line(chart.clone(), DEFAULT_TOLERANCE, 1);
+        let fine = merge_children_line(chart, DEFAULT_TOLERANCE, DEFAULT_RESOLUTION);
+        // the child moves along an arc, which can only be sampled
+        assert_eq!(coarse.lines[0].events.x().len(), 4);
+        assert!(fine.lines[0].events.x().len() > 4);
+        // the rotation is still exact
+        assert_eq!(fine.lines[0].events.rotation().len(), 1);
+    }
+
+    #[test]
+    fn test_inheritance() {
+        let child = LineWrapper {
+            line: Line {
+                inherit: Inheritance {
+                    opacity: true,
+                    rotation: false,
+                },
+                ..Default::default()
+            },
+            events: vec![
+                event(LineEventKind::X, 100.0, 100.0, Easing::Linear),
+                event(LineEventKind::Rotation, 10.0, 10.0, Easing::Linear),
+                event(LineEventKind::Opacity, 255.0, 0.0, Easing::Linear),
+            ],
+            ..Default::default()
+        };
+        let parent = LineWrapper {
+            events: vec![
+                event(LineEventKind::Rotation, 90.0, 90.0, Easing::Linear),
+                event(LineEventKind::Opacity, 128.0, 128.0, Easing::Linear),
+            ],
+            children: vec![child],
+            ..Default::default()
+        };
+        let chart = PhichainChart {
+            lines: vec![parent],
+            ..Default::default()
+        };
+
+        let merged = merge_children_line(chart, DEFAULT_TOLERANCE, DEFAULT_RESOLUTION);
+        let child = &merged.lines[0];
+        for i in 0..=16 {
+            let beat = beat!(i, 4);
+            // the position still follows the rotation of the parent
+            assert!((child.events.y().evaluate(beat) - 100.0).abs() < 1e-3);
+            assert!((child.events.rotation().evaluate(beat) - 10.0).abs() < 1e-3);
+            let opacity = 128.0 * (1.0 - beat.value() / 4.0);
+            assert!((child.events.opacity().evaluate(beat) - opacity).abs() < 1e-3);
+        }
+        // a constant factor keeps the opacity linear
+        assert_eq!(child.events.opacity().len(), 1);
+    }
+
+    #[test]
+    fn test_sources() {
+        let child = LineWrapper {
+            events: vec![
+                event(LineEventKind::Speed, 10.0, 10.0, Easing::Linear),
+                event(LineEventKind::X, 100.0, 100.0, Easing::Linear),
+            ],
+            ..Default::default()
+        };
+        let parent = LineWrapper {
+            events: vec![event(LineEventKind::Y, 0.0, 100.0, Easing::Linear)],
+            children: vec![child],
+            ..Default::default()
+        };
+        let chart = PhichainChart {
+            lines: vec![parent],
+            ..Default::default()
+        };
+
+        let mut sources = SourceMap::new(&chart);
+        let merged = merge_with_sources(chart, &mut sources, DEFAULT_TOLERANCE, DEFAULT_RESOLUTION);
+
+        assert_eq!(sources.lines.len(), merged.lines.len());
+        assert_eq!(sources.lines[0].line, LinePath(vec![0, 0]));
+        assert_eq!(sources.lines[1].line, LinePath(vec![0]));
+
+        let child = &merged.lines[0];
+        let source = |line: Vec<usize>, index| EventSource {
+            line: LinePath(line),
+            index,
+        };
+        for (index, event) in child.events.iter().enumerate() {
+            let expected = if event.kind.is_speed() {
+                vec![source(vec![0, 0], 0)]
+            } else if event.kind.is_rotation() {
+                vec![]
+            } else {
+                vec![source(vec![0], 0), source(vec![0, 0], 1)]
+            };
+            assert_eq!(sources.lines[0].events[index], expected);
+        }
+    }
+}
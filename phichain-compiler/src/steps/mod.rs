@@ -1,3 +1,9 @@
 mod merge_children_line;
+mod segmentation;
+mod simplification;
+mod validation;
 
-pub use merge_children_line::merge_children_line;
+pub use merge_children_line::{merge_children_line, MergeChildren};
+pub use segmentation::Segmentation;
+pub use simplification::Simplification;
+pub use validation::Validation;
@@ -0,0 +1,119 @@
+use crate::pipeline::Step;
+use crate::source_map::{LineSource, SourceMap};
+use phichain_chart::easing::Easing;
+use phichain_chart::event::{LineEvent, LineEventValue};
+use phichain_chart::segmentation::segment_with_resolution;
+use phichain_chart::serialization::{LineWrapper, PhichainChart};
+
+fn cut_event(event: LineEvent, tolerance: f32, resolution: u32) -> Vec<LineEvent> {
+    let LineEventValue::Transition { start, end, easing } = event.value else {
+        return vec![event];
+    };
+    if matches!(easing, Easing::Linear) {
+        return vec![event];
+    }
+
+    let evaluate = |beat: f32| [event.evaluate(beat).value().unwrap_or(end)];
+
+    segment_with_resolution(
+        event.start_beat,
+        event.end_beat,
+        [start],
+        [end],
+        tolerance,
+        resolution,
+        evaluate,
+    )
+    .into_iter()
+    .map(|x| LineEvent {
+        id: None,
+        kind: event.kind,
+        start_beat: x.start_beat,
+        end_beat: x.end_beat,
+        value: LineEventValue::transition(x.start[0], x.end[0], Easing::Linear),
+    })
+    .collect()
+}
+
+fn cut_line(
+    line: LineWrapper,
+    source: &mut LineSource,
+    tolerance: f32,
+    resolution: u32,
+) -> LineWrapper {
+    let mut events = vec![];
+    let mut origins = vec![];
+    for (index, event) in line.events.into_iter().enumerate() {
+        let cut = cut_event(event, tolerance, resolution);
+        origins.extend(std::iter::repeat_n(vec![index], cut.len()));
+        events.extend(cut);
+    }
+    source.remap_events(&origins);
+
+    LineWrapper {
+        events,
+        children: line
+            .children
+            .into_iter()
+            .zip(source.children.iter_mut())
+            .map(|(x, source)| cut_line(x, source, tolerance, resolution))
+            .collect(),
+        ..line
+    }
+}
+
+/// Cut all eased events into linear events, for formats without easings
+pub struct Segmentation {
+    /// The maximum value error allowed when approximating eased events with linear events
+    pub tolerance: f32,
+    /// Linear events are never shorter than `1 / resolution` beat
+    pub resolution: u32,
+}
+
+impl Step for Segmentation {
+    fn name(&self) -> &'static str {
+        "segmentation"
+    }
+
+    fn run(&self, chart: PhichainChart, sources: &mut SourceMap) -> anyhow::Result<PhichainChart> {
+        Ok(PhichainChart {
+            lines: chart
+                .lines
+                .into_iter()
+                .zip(sources.lines.iter_mut())
+                .map(|(x, source)| cut_line(x, source, self.tolerance, self.resolution))
+                .collect(),
+            ..chart
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use phichain_chart::beat;
+    use phichain_chart::event::LineEventKind;
+
+    #[test]
+    fn test_cut_event() {
+        let event = LineEvent {
+            id: None,
+            kind: LineEventKind::X,
+            start_beat: beat!(0),
+            end_beat: beat!(2),
+            value: LineEventValue::transition(0.0, 100.0, Easing::EaseInOutSine),
+        };
+
+        let events = cut_event(event, 0.1, 256);
+        assert!(events.len() > 1);
+        assert_eq!(events.first().unwrap().start_beat, beat!(0));
+        assert_eq!(events.last().unwrap().end_beat, beat!(2));
+        assert!(events.iter().all(|x| matches!(
+            x.value,
+            LineEventValue::Transition {
+                easing: Easing::Linear,
+                ..
+            }
+        )));
+    }
+}
@@ -0,0 +1,304 @@
+use crate::pipeline::Step;
+use crate::source_map::{LineSource, SourceMap};
+use phichain_chart::easing::Easing;
+use phichain_chart::event::{LineEvent, LineEventKind, LineEventValue};
+use phichain_chart::serialization::{LineWrapper, PhichainChart};
+use strum::IntoEnumIterator;
+
+/// Whether two values are equal up to float rounding
+fn approx_eq(a: f32, b: f32) -> bool {
+    (a - b).abs() <= 1e-5 * a.abs().max(b.abs()).max(1.0)
+}
+
+/// The value of the event if it is the same at every beat
+fn constant_value(event: &LineEvent) -> Option<f32> {
+    match event.value {
+        LineEventValue::Constant(value) => Some(value),
+        LineEventValue::Transition { start, end, .. } if start == end => Some(start),
+        LineEventValue::Transition { .. } => None,
+    }
+}
+
+/// The value left by the event after it ends, and while it is affecting if it is constant
+fn end_value(event: &LineEvent) -> f32 {
+    match event.value {
+        LineEventValue::Constant(value) => value,
+        LineEventValue::Transition { end, .. } => end,
+    }
+}
+
+/// Merge two linear events if the second continues the first one on the same line
+fn merge_collinear(a: &LineEvent, b: &LineEvent) -> Option<LineEvent> {
+    let LineEventValue::Transition {
+        start: a_start,
+        end: a_end,
+        easing: Easing::Linear,
+    } = a.value
+    else {
+        return None;
+    };
+    let LineEventValue::Transition {
+        start: b_start,
+        end: b_end,
+        easing: Easing::Linear,
+    } = b.value
+    else {
+        return None;
+    };
+
+    if a.end_beat != b.start_beat || a_end != b_start {
+        return None;
+    }
+
+    let a_length = (a.end_beat - a.start_beat).value();
+    let b_length = (b.end_beat - b.start_beat).value();
+    if a_length <= 0.0 || b_length <= 0.0 {
+        return None;
+    }
+
+    if !approx_eq((a_end - a_start) / a_length, (b_end - b_start) / b_length) {
+        return None;
+    }
+
+    Some(LineEvent {
+        end_beat: b.end_beat,
+        value: LineEventValue::transition(a_start, b_end, Easing::Linear),
+        ..*a
+    })
+}
+
+/// Canonicalize events of a single kind without changing their value at any beat
+///
+/// A sequence of events is evaluated with the last event in the list which has started, so:
+///
+/// - an event is shadowed when a later event in the list starts no later than it
+/// - an event is a no-op when it keeps the value left by the previous event, or the default `0.0` for the first one
+/// - two linear events on the same line can be merged
+///
+/// Along with the simplified events, returns the indices of the events each simplified event comes from
+fn simplify_events(events: Vec<LineEvent>) -> (Vec<LineEvent>, Vec<Vec<usize>>) {
+    // inverted events do not follow the rules above, leave them to the validation
+    if events.iter().any(|x| x.start_beat > x.end_beat) {
+        let origins = (0..events.len()).map(|x| vec![x]).collect();
+        return (events, origins);
+    }
+
+    let mut visible = vec![];
+    for (i, event) in events.iter().enumerate() {
+        if !events[i + 1..]
+            .iter()
+            .any(|x| x.start_beat <= event.start_beat)
+        {
+            visible.push((i, *event));
+        }
+    }
+    // with shadowed events removed, the list is sorted by start beat
+    debug_assert!(visible
+        .windows(2)
+        .all(|x| x[0].1.start_beat < x[1].1.start_beat));
+
+    let mut simplified: Vec<LineEvent> = vec![];
+    let mut origins: Vec<Vec<usize>> = vec![];
+    for (index, event) in visible {
+        let previous = simplified.last();
+
+        // the value the previous event keeps while this event is affecting, if it is constant
+        let previous_value = match previous {
+            None => Some(0.0),
+            Some(previous) if previous.end_beat <= event.start_beat => Some(end_value(previous)),
+            Some(previous) => constant_value(previous),
+        };
+        if previous_value.is_some() && previous_value == constant_value(&event) {
+            continue;
+        }
+
+        if let Some(merged) = previous.and_then(|x| merge_collinear(x, &event)) {
+            *simplified.last_mut().unwrap() = merged;
+            origins.last_mut().unwrap().push(index);
+            continue;
+        }
+
+        simplified.push(event);
+        origins.push(vec![index]);
+    }
+
+    (simplified, origins)
+}
+
+fn simplify_line(line: LineWrapper, source: &mut LineSource) -> LineWrapper {
+    let mut events = vec![];
+    let mut origins = vec![];
+
+    for kind in LineEventKind::iter() {
+        let indices = (0..line.events.len())
+            .filter(|x| line.events[*x].kind == kind)
+            .collect::<Vec<_>>();
+
+        let (simplified, simplified_origins) =
+            simplify_events(indices.iter().map(|x| line.events[*x]).collect());
+        events.extend(simplified);
+        // indices of events of this kind back into indices of all events
+        origins.extend(
+            simplified_origins
+                .into_iter()
+                .map(|x| x.into_iter().map(|x| indices[x]).collect::<Vec<_>>()),
+        );
+    }
+    source.remap_events(&origins);
+
+    LineWrapper {
+        events,
+        children: line
+            .children
+            .into_iter()
+            .zip(source.children.iter_mut())
+            .map(|(x, source)| simplify_line(x, source))
+            .collect(),
+        ..line
+    }
+}
+
+/// Remove redundant events without changing the value of any line at any beat
+pub struct Simplification;
+
+impl Step for Simplification {
+    fn name(&self) -> &'static str {
+        "simplification"
+    }
+
+    fn run(&self, chart: PhichainChart, sources: &mut SourceMap) -> anyhow::Result<PhichainChart> {
+        Ok(PhichainChart {
+            lines: chart
+                .lines
+                .into_iter()
+                .zip(sources.lines.iter_mut())
+                .map(|(x, source)| simplify_line(x, source))
+                .collect(),
+            ..chart
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::EventSequence;
+    use phichain_chart::beat;
+    use phichain_chart::beat::Beat;
+
+    fn event(start_beat: Beat, end_beat: Beat, value: LineEventValue) -> LineEvent {
+        LineEvent {
+            id: None,
+            kind: LineEventKind::X,
+            start_beat,
+            end_beat,
+            value,
+        }
+    }
+
+    fn linear(start_beat: Beat, end_beat: Beat, start: f32, end: f32) -> LineEvent {
+        event(
+            start_beat,
+            end_beat,
+            LineEventValue::transition(start, end, Easing::Linear),
+        )
+    }
+
+    fn assert_same_values(a: &Vec<LineEvent>, b: &Vec<LineEvent>) {
+        for i in -8..=128 {
+            let beat = beat!(i, 8);
+            assert!(
+                approx_eq(a.evaluate(beat), b.evaluate(beat)),
+                "value differs at {:?}: {} != {}",
+                beat,
+                a.evaluate(beat),
+                b.evaluate(beat)
+            );
+            assert!(approx_eq(
+                a.evaluate_start_no_effect(beat),
+                b.evaluate_start_no_effect(beat)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_simplify() {
+        let events = vec![
+            // no-op, same as the default value
+            event(beat!(0), beat!(1), LineEventValue::constant(0.0)),
+            linear(beat!(1), beat!(2), 0.0, 10.0),
+            linear(beat!(2), beat!(3), 10.0, 20.0),
+            linear(beat!(3), beat!(5), 20.0, 40.0),
+            // no-op, keeps the value left by the previous event
+            event(beat!(6), beat!(7), LineEventValue::constant(40.0)),
+            event(
+                beat!(7),
+                beat!(8),
+                LineEventValue::transition(40.0, 0.0, Easing::EaseInSine),
+            ),
+            // shadowed by the next event
+            linear(beat!(9), beat!(10), 1.0, 2.0),
+            event(beat!(9), beat!(12), LineEventValue::constant(5.0)),
+        ];
+
+        let (simplified, origins) = simplify_events(events.clone());
+        assert_eq!(origins, vec![vec![1, 2, 3], vec![5], vec![7]]);
+        assert_eq!(
+            simplified,
+            vec![
+                linear(beat!(1), beat!(5), 0.0, 40.0),
+                event(
+                    beat!(7),
+                    beat!(8),
+                    LineEventValue::transition(40.0, 0.0, Easing::EaseInSine),
+                ),
+                event(beat!(9), beat!(12), LineEventValue::constant(5.0)),
+            ]
+        );
+        assert_same_values(&events, &simplified);
+    }
+
+    #[test]
+    fn test_keep_values() {
+        // a deterministic pseudo random sequence, to cover overlapping and unordered events
+        let mut seed = 42u32;
+        let mut next = |max: u32| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) % max
+        };
+
+        for _ in 0..200 {
+            let events = (0..6)
+                .map(|_| {
+                    let start_beat = beat!(next(12), 2);
+                    let end_beat = start_beat + beat!(next(6) + 1, 2);
+                    let start = next(3) as f32 * 10.0;
+                    let end = next(3) as f32 * 10.0;
+                    match next(3) {
+                        0 => event(start_beat, end_beat, LineEventValue::constant(start)),
+                        1 => linear(start_beat, end_beat, start, end),
+                        _ => event(
+                            start_beat,
+                            end_beat,
+                            LineEventValue::transition(start, end, Easing::EaseOutQuad),
+                        ),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            assert_same_values(&events, &simplify_events(events.clone()).0);
+        }
+    }
+
+    #[test]
+    fn test_default_line() {
+        let line = LineWrapper::default();
+        let mut source = LineSource::new(&line, Default::default());
+        let line = simplify_line(line, &mut source);
+        // only the speed event differs from the default value
+        assert_eq!(line.events.len(), 1);
+        assert!(line.events[0].kind.is_speed());
+        assert_eq!(source.events.len(), 1);
+        assert_eq!(source.events[0][0].index, 4);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::pipeline::Step;
+use crate::source_map::SourceMap;
+use anyhow::bail;
+use phichain_chart::serialization::PhichainChart;
+use phichain_chart::validation::{validate, Diagnostic};
+use tracing::{error, warn};
+
+/// Validate the chart, logging the diagnostics
+#[derive(Debug, Clone, Default)]
+pub struct Validation {
+    /// Fail on errors instead of logging them
+    pub deny_errors: bool,
+    /// Fail on warnings as well as errors
+    pub deny_warnings: bool,
+}
+
+/// Describe a diagnostic, along with the source line if the line has been moved by earlier steps
+fn describe(diagnostic: &Diagnostic, sources: &SourceMap) -> String {
+    match sources.line(&diagnostic.line) {
+        Some(source) if source.line != diagnostic.line => {
+            format!("{} (source line {})", diagnostic, source.line)
+        }
+        _ => diagnostic.to_string(),
+    }
+}
+
+impl Step for Validation {
+    fn name(&self) -> &'static str {
+        "validation"
+    }
+
+    fn run(&self, chart: PhichainChart, sources: &mut SourceMap) -> anyhow::Result<PhichainChart> {
+        let diagnostics = validate(&chart);
+
+        let (failures, warnings): (Vec<_>, Vec<_>) = diagnostics
+            .into_iter()
+            .partition(|x| self.deny_warnings || (self.deny_errors && x.is_error()));
+
+        for diagnostic in warnings {
+            if diagnostic.is_error() {
+                error!("{}", describe(&diagnostic, sources));
+            } else {
+                warn!("{}", describe(&diagnostic, sources));
+            }
+        }
+
+        if !failures.is_empty() {
+            bail!(
+                "The chart has {} problem(s):\n{}",
+                failures.len(),
+                failures
+                    .iter()
+                    .map(|x| describe(x, sources))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+
+        Ok(chart)
+    }
+}
@@ -8,7 +8,6 @@ pub trait EventSequence {
     fn x(&self) -> Self;
     fn y(&self) -> Self;
     fn rotation(&self) -> Self;
-    #[allow(dead_code)]
     fn opacity(&self) -> Self;
     #[allow(dead_code)]
     fn speed(&self) -> Self;
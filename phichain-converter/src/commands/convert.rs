@@ -0,0 +1,472 @@
+//! Converting charts between formats
+
+use crate::input::{expand, Input, Output, STDIO};
+use crate::load::{load, Loaded};
+use crate::Formats;
+use anyhow::{bail, Context};
+use phichain_chart::binary::{encode_phichain, encode_primitive};
+use phichain_chart::canonical;
+use phichain_chart::fitting::DEFAULT_FIT_TOLERANCE;
+use phichain_chart::format::official::OfficialChart;
+use phichain_chart::format::pec::PecChart;
+use phichain_chart::format::rpe::RpeChart;
+use phichain_chart::migration::migrate_to;
+use phichain_chart::primitive::{Format, PrimitiveChart};
+use phichain_chart::project::ProjectPath;
+use phichain_chart::report::ConversionReport;
+use phichain_chart::segmentation::{DEFAULT_RESOLUTION, DEFAULT_TOLERANCE};
+use phichain_chart::serialization::PhichainChart;
+use phichain_compiler::pipeline::Profile;
+use phichain_compiler::{compile_with_source_map, CompileOptions};
+use rayon::prelude::*;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, clap::Args)]
+pub struct ConvertArgs {
+    /// The input chart format, detected from the input chart if not given
+    #[arg(short, long)]
+    input: Option<Formats>,
+    /// The output chart format
+    #[arg(short, long, required = true)]
+    output: Formats,
+
+    /// The input charts
+    ///
+    /// Each input can be a chart file, `-` for stdin, a directory or a glob pattern. A Phichain project directory is a
+    /// single chart, which is required to output `pez`, while any other directory converts every chart inside it
+    #[arg(required = true)]
+    inputs: Vec<String>,
+
+    /// The output path, `-` for stdout
+    ///
+    /// When converting multiple charts, or when the path is an existing directory, outputs are written into this
+    /// directory. Defaults to the input path with a `.{format}.{extension}` suffix, or stdout for stdin input
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// The number of charts converted in parallel when converting multiple charts, defaults to the number of CPUs
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Output a Phichain project directory with `chart.json` and `meta.json` instead of a single chart file
+    ///
+    /// Only has effect for `phichain` output. A `pez` input is always extracted into a project directory
+    #[arg(long)]
+    project: bool,
+
+    /// Write the Phichain chart in this format number instead of the current one, for older editor builds
+    ///
+    /// Only has effect for `phichain` output. Fails if the older format can not represent the chart
+    #[arg(long, value_name = "FORMAT")]
+    target_format: Option<u64>,
+
+    /// Write `phichain` and `primitive` output in the compact binary encoding, which loads faster for large charts
+    ///
+    /// A Phichain project gets a `chart.bin` instead of `chart.json`. Requires the converter to be built with the
+    /// `binary` feature
+    #[arg(long)]
+    binary: bool,
+
+    /// The compiler profile used before writing the output
+    ///
+    /// Defaults to `official-safe` for `official` and `pec` output, and `rpe-lossless` for `rpe` and `pez` output.
+    /// Charts are not compiled for `phichain` and `primitive` output
+    #[arg(long)]
+    profile: Option<Profile>,
+
+    /// Skip a step of the compiler profile, can be given multiple times
+    #[arg(long, value_name = "STEP")]
+    disable_step: Vec<String>,
+
+    /// Fail on validation errors of the compiled chart, e.g. overlapping events, instead of only reporting them
+    #[arg(long)]
+    deny_errors: bool,
+
+    /// The maximum value error allowed when approximating eased events and merged child lines with linear events
+    ///
+    /// Smaller values produce more accurate but larger charts
+    #[arg(long, default_value_t = DEFAULT_TOLERANCE)]
+    tolerance: f32,
+
+    /// The finest step when sampling eased events and rotating child lines, which is `1 / RESOLUTION` beat
+    #[arg(long, default_value_t = DEFAULT_RESOLUTION)]
+    resolution: u32,
+
+    /// The maximum value error allowed when merging linear events back into eased events
+    ///
+    /// Only has effect for `official` input
+    #[arg(long, default_value_t = DEFAULT_FIT_TOLERANCE)]
+    fit_tolerance: f32,
+
+    /// Write a JSON source map to this path, tracing the lines, events and notes of the compiled chart back to the
+    /// input chart
+    ///
+    /// Only has effect when the chart is compiled, and can not be used when converting multiple charts
+    #[arg(long, value_name = "PATH")]
+    source_map: Option<PathBuf>,
+
+    /// Write a JSON report of what is dropped or approximated by the conversion to this path
+    ///
+    /// The report is printed when this is not given, and can not be used when converting multiple charts
+    #[arg(long, value_name = "PATH")]
+    report: Option<PathBuf>,
+
+    /// Fail without writing the output if anything is dropped or approximated by the conversion
+    #[arg(long)]
+    strict: bool,
+}
+
+/// Convert a primitive chart into a format, recording the losses into the report
+fn export<T: Format>(
+    primitive: PrimitiveChart,
+    report: &mut ConversionReport,
+) -> anyhow::Result<T> {
+    let (chart, losses) = T::from_primitive_with_report(primitive)?;
+    report.extend(losses);
+    Ok(chart)
+}
+
+/// Serialize a Phichain chart, migrated to the target format if given, or in the binary encoding
+///
+/// JSON is written in the [canonical](canonical) layout if `canonical_layout` is set, which is used for the
+/// `chart.json` of projects
+fn serialize_phichain(
+    chart: &PhichainChart,
+    args: &ConvertArgs,
+    canonical_layout: bool,
+) -> anyhow::Result<Vec<u8>> {
+    match (args.target_format, args.binary) {
+        (Some(_), true) => bail!("`--target-format` can not be used with `--binary`"),
+        (Some(target), false) => {
+            let mut chart = chart.clone();
+            if canonical_layout {
+                canonical::sort(&mut chart);
+            }
+            let chart = migrate_to(&serde_json::to_value(&chart)?, target)?;
+            Ok(if canonical_layout {
+                canonical::value_to_string(&chart)?.into_bytes()
+            } else {
+                serde_json::to_vec(&chart)?
+            })
+        }
+        (None, true) => encode_phichain(chart),
+        (None, false) if canonical_layout => Ok(canonical::to_string(chart)?.into_bytes()),
+        (None, false) => Ok(serde_json::to_vec(chart)?),
+    }
+}
+
+/// Indent every line of the report, to list it under a message
+fn indent(report: &ConversionReport) -> String {
+    report
+        .losses
+        .iter()
+        .map(|x| format!("  {}", x))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The conversion of a single chart
+struct Job<'a> {
+    args: &'a ConvertArgs,
+    input: Input,
+    /// Whether this job is part of a batch, progress messages are skipped in a batch
+    batch: bool,
+}
+
+impl Job<'_> {
+    /// Print a progress message, to stderr so the output can be written to stdout
+    fn log(&self, message: impl std::fmt::Display) {
+        if !self.batch {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// Where to write the output, `project` tells if the output is a project directory rather than a file
+    fn output(&self, project: bool) -> anyhow::Result<Output> {
+        let format = &self.args.output;
+        let output_name = |path: &Path| {
+            if project {
+                path.with_extension("")
+            } else {
+                let extension = if self.args.binary {
+                    "bin"
+                } else {
+                    format.extension()
+                };
+                path.with_extension(format!("{}.{}", format, extension))
+            }
+        };
+
+        let output = match (&self.args.out, &self.input) {
+            (Some(out), _) if out == Path::new(STDIO) => {
+                if self.batch {
+                    bail!("Multiple charts can not be written to stdout");
+                }
+                Output::Stdout
+            }
+            (Some(out), input) if self.batch || out.is_dir() => {
+                let name = match input {
+                    Input::Path(path) => output_name(path),
+                    Input::Stdin => output_name(Path::new("chart")),
+                };
+                Output::Path(out.join(name.file_name().context("Invalid input path")?))
+            }
+            (Some(out), _) => Output::Path(out.clone()),
+            (None, Input::Path(path)) => Output::Path(output_name(path)),
+            (None, Input::Stdin) => Output::Stdout,
+        };
+
+        Ok(output)
+    }
+
+    /// Load the input chart
+    fn load(&self) -> anyhow::Result<Loaded> {
+        load(
+            &self.input,
+            self.args.input.as_ref(),
+            self.args.fit_tolerance,
+            |x| self.log(x),
+        )
+    }
+
+    /// Compile the chart with the compiler profile for the output format
+    fn compile(&self, primitive: PrimitiveChart) -> anyhow::Result<PrimitiveChart> {
+        let args = self.args;
+
+        let profile = args.profile.unwrap_or(match args.output {
+            Formats::Official | Formats::Pec => Profile::OfficialSafe,
+            _ => Profile::RpeLossless,
+        });
+        let options = CompileOptions {
+            profile,
+            tolerance: args.tolerance,
+            resolution: args.resolution,
+            disabled_steps: args.disable_step.clone(),
+            deny_errors: args.deny_errors,
+        };
+
+        self.log(format!("Compiling chart with profile `{}`...", profile));
+
+        let meta = primitive.meta.clone();
+        let (chart, _) = PhichainChart::from_primitive_with_report(primitive)?;
+
+        let (compiled, sources) = compile_with_source_map(chart, &options)?;
+
+        if let Some(path) = &args.source_map {
+            std::fs::write(path, serde_json::to_string(&sources)?)?;
+        }
+
+        Ok(PrimitiveChart { meta, ..compiled })
+    }
+
+    /// Write the report if requested, and fail if the conversion is lossy in strict mode
+    fn check(&self, report: &ConversionReport) -> anyhow::Result<()> {
+        if let Some(path) = &self.args.report {
+            std::fs::write(path, serde_json::to_string(report)?)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+
+        if self.args.strict && !report.is_lossless() {
+            bail!("Conversion is lossy:\n{}", indent(report));
+        }
+
+        Ok(())
+    }
+
+    /// Convert the chart, returns where the output is written and what is lost by the conversion
+    fn run(&self) -> anyhow::Result<(Output, ConversionReport)> {
+        let args = self.args;
+
+        let Loaded {
+            format: input,
+            primitive,
+            pez,
+            mut report,
+        } = self.load()?;
+
+        if args.binary && !matches!(args.output, Formats::Phichain | Formats::Primitive) {
+            bail!("Only `phichain` and `primitive` charts can be written in the binary encoding");
+        }
+
+        let primitive = match args.output {
+            Formats::Phichain | Formats::Primitive => primitive,
+            _ => self.compile(primitive)?,
+        };
+
+        self.log(format!("Converting chart into `{}` chart...", args.output));
+
+        let output = match args.output {
+            Formats::Official => {
+                let (chart, losses) =
+                    OfficialChart::from_primitive_with_tolerance(primitive, args.tolerance)?;
+                report.extend(losses);
+                serde_json::to_vec(&chart)?
+            }
+            // the project directory is named after the input
+            Formats::Phichain if args.project || input == Formats::Pez => {
+                let Output::Path(root_dir) = self.output(true)? else {
+                    bail!("A Phichain project can not be written to stdout");
+                };
+                if root_dir.exists() {
+                    bail!("{} already exists", root_dir.display());
+                }
+                // converting into a Phichain project is lossless
+                self.check(&report)?;
+
+                // fail before writing anything if the chart can not be downgraded
+                let chart: PhichainChart = export(primitive.clone(), &mut report)?;
+                let chart_data = serialize_phichain(&chart, args, true)?;
+
+                match pez {
+                    Some(mut pez) => {
+                        pez.chart = primitive;
+                        pez.extract(&root_dir)?;
+                    }
+                    None => {
+                        let meta = primitive.meta.clone().unwrap_or_default();
+                        ProjectPath(root_dir.clone()).write(&chart, &meta)?;
+                        self.log("Music and illustration are not included, copy them into the project directory as `music.*` and `illustration.*`");
+                    }
+                }
+                let project = ProjectPath(root_dir.clone());
+                if args.binary {
+                    std::fs::remove_file(project.chart_path())?;
+                    std::fs::write(project.binary_chart_path(), chart_data)
+                        .context("Failed to write chart")?;
+                } else if args.target_format.is_some() {
+                    std::fs::write(project.chart_path(), chart_data)
+                        .context("Failed to write chart")?;
+                }
+
+                return Ok((Output::Path(root_dir), report));
+            }
+            Formats::Phichain => {
+                let chart: PhichainChart = export(primitive, &mut report)?;
+                serialize_phichain(&chart, args, false)?
+            }
+            Formats::Rpe => {
+                let chart: RpeChart = export(primitive, &mut report)?;
+                serde_json::to_vec(&chart)?
+            }
+            Formats::Primitive => {
+                let chart: PrimitiveChart = export(primitive, &mut report)?;
+                if args.binary {
+                    encode_primitive(&chart)?
+                } else {
+                    serde_json::to_vec(&chart)?
+                }
+            }
+            Formats::Pec => {
+                let chart: PecChart = export(primitive, &mut report)?;
+                chart.to_string().into_bytes()
+            }
+            Formats::Pez => {
+                let Some(mut pez) = pez else {
+                    bail!("Exporting pez requires a pez file or a Phichain project directory as input");
+                };
+                pez.chart = primitive;
+                let mut buffer = Cursor::new(vec![]);
+                report.extend(pez.write_with_report(&mut buffer)?);
+                buffer.into_inner()
+            }
+        };
+
+        self.check(&report)?;
+
+        let target = self.output(false)?;
+        target.write(&output)?;
+
+        Ok((target, report))
+    }
+}
+
+/// Convert multiple charts in parallel, and print a summary of the failures
+fn convert_batch(args: &ConvertArgs, inputs: Vec<Input>) -> anyhow::Result<()> {
+    if args.source_map.is_some() {
+        bail!("`--source-map` can not be used when converting multiple charts");
+    }
+    if args.report.is_some() {
+        bail!("`--report` can not be used when converting multiple charts");
+    }
+    if let Some(out) = &args.out {
+        if out != Path::new(STDIO) {
+            std::fs::create_dir_all(out)
+                .with_context(|| format!("Failed to create {}", out.display()))?;
+        }
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or_default())
+        .build()?;
+
+    let total = inputs.len();
+    let failures = pool.install(|| {
+        inputs
+            .into_par_iter()
+            .filter_map(|input| {
+                let job = Job {
+                    args,
+                    input,
+                    batch: true,
+                };
+                match job.run() {
+                    Ok((output, report)) if report.is_lossless() => {
+                        eprintln!("Converted {} into {}", job.input, output);
+                        None
+                    }
+                    Ok((output, report)) => {
+                        eprintln!(
+                            "Converted {} into {} with {} loss(es)",
+                            job.input,
+                            output,
+                            report.losses.len()
+                        );
+                        None
+                    }
+                    Err(err) => {
+                        eprintln!("Failed to convert {}: {:#}", job.input, err);
+                        Some((job.input, err))
+                    }
+                }
+            })
+            .collect::<Vec<_>>()
+    });
+
+    eprintln!("Converted {} of {} charts", total - failures.len(), total);
+
+    if !failures.is_empty() {
+        bail!(
+            "{} chart(s) failed to convert:\n{}",
+            failures.len(),
+            failures
+                .iter()
+                .map(|(input, err)| format!("  {}: {:#}", input, err))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    Ok(())
+}
+
+/// Convert the charts, in parallel if there are multiple charts
+pub fn convert(args: ConvertArgs) -> anyhow::Result<()> {
+    let (mut inputs, batch) = expand(&args.inputs)?;
+
+    if batch {
+        return convert_batch(&args, inputs);
+    }
+
+    let job = Job {
+        args: &args,
+        input: inputs.remove(0),
+        batch: false,
+    };
+    let (_, report) = job.run()?;
+    if !report.is_lossless() && args.report.is_none() {
+        eprintln!("Conversion is lossy:\n{}", indent(&report));
+    }
+
+    Ok(())
+}
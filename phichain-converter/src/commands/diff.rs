@@ -0,0 +1,37 @@
+//! Semantic comparison of charts
+
+use crate::load::load_phichain;
+use crate::Formats;
+use phichain_chart::diff::diff as diff_charts;
+use std::process::ExitCode;
+
+#[derive(Debug, clap::Args)]
+pub struct DiffArgs {
+    /// The format of both charts, detected from each chart if not given
+    #[arg(short, long)]
+    input: Option<Formats>,
+
+    /// The old chart, `-` for stdin, or a Phichain project directory
+    old: String,
+
+    /// The new chart, `-` for stdin, or a Phichain project directory
+    new: String,
+}
+
+/// Print the changes between two charts, fails if the charts differ
+///
+/// Lines are matched by their position and name, notes and events by their kind and beat, so reordering does not
+/// count as a change
+pub fn diff(args: DiffArgs) -> anyhow::Result<ExitCode> {
+    let old = load_phichain(&args.old.as_str().into(), args.input.as_ref())?;
+    let new = load_phichain(&args.new.as_str().into(), args.input.as_ref())?;
+
+    let diff = diff_charts(&old, &new);
+    if diff.is_empty() {
+        eprintln!("Charts are identical");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    print!("{}", diff);
+    Ok(ExitCode::FAILURE)
+}
@@ -0,0 +1,99 @@
+//! Upgrading Phichain charts in older formats
+
+use anyhow::{bail, Context};
+use phichain_chart::binary::{encode_phichain, ChartKind, Header};
+use phichain_chart::canonical;
+use phichain_chart::migration::CURRENT_FORMAT;
+use phichain_chart::project::ProjectPath;
+use phichain_chart::serialization::PhichainChart;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Debug, clap::Args)]
+pub struct MigrateArgs {
+    /// The Phichain charts to upgrade in JSON or in the binary encoding, or Phichain project directories
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Only check whether the charts are in the current format, without writing anything
+    ///
+    /// Exits with a nonzero code if any chart needs to be upgraded
+    #[arg(long)]
+    check: bool,
+}
+
+/// Upgrade a chart in place, returns whether the chart was outdated
+///
+/// Upgraded charts are written in the [canonical](canonical) layout, the same as charts saved by the editor, or in
+/// the binary encoding if they were binary
+fn migrate_file(path: &Path, check: bool) -> anyhow::Result<bool> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let header = Header::read(&data)?;
+    let format = match header {
+        Some(header) if header.kind != ChartKind::Phichain => {
+            bail!("Not a binary Phichain chart")
+        }
+        Some(header) => header.format,
+        None => {
+            let chart: Value = serde_json::from_slice(&data).context("Invalid chart")?;
+            chart.get("format").and_then(Value::as_u64).unwrap_or(0)
+        }
+    };
+
+    if format == CURRENT_FORMAT {
+        return Ok(false);
+    }
+    if format > CURRENT_FORMAT {
+        bail!(
+            "Chart format {} is newer than the supported format {}",
+            format,
+            CURRENT_FORMAT
+        );
+    }
+    if check {
+        return Ok(true);
+    }
+
+    // make sure the migrated chart loads before overwriting the old one
+    let migrated = PhichainChart::read(&data).context("Migration failed")?;
+    let data = match header {
+        Some(_) => encode_phichain(&migrated)?,
+        None => canonical::to_string(&migrated)?.into_bytes(),
+    };
+    std::fs::write(path, data).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(true)
+}
+
+/// Upgrade Phichain charts to the current format in place
+pub fn migrate(args: MigrateArgs) -> anyhow::Result<ExitCode> {
+    let mut outdated = 0;
+
+    for path in &args.paths {
+        let path = if path.is_dir() {
+            ProjectPath(path.clone())
+                .chart_file()
+                .with_context(|| format!("Could not find chart in {}", path.display()))?
+        } else {
+            path.clone()
+        };
+
+        let migrated = migrate_file(&path, args.check)
+            .with_context(|| format!("Failed to migrate {}", path.display()))?;
+        match (migrated, args.check) {
+            (false, _) => eprintln!("{} is up to date", path.display()),
+            (true, true) => eprintln!("{} needs to be upgraded", path.display()),
+            (true, false) => eprintln!("Upgraded {} to format {}", path.display(), CURRENT_FORMAT),
+        }
+        if migrated {
+            outdated += 1;
+        }
+    }
+
+    Ok(if args.check && outdated > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
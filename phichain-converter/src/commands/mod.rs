@@ -0,0 +1,11 @@
+mod convert;
+mod diff;
+mod migrate;
+mod stats;
+mod validate;
+
+pub use convert::{convert, ConvertArgs};
+pub use diff::{diff, DiffArgs};
+pub use migrate::{migrate, MigrateArgs};
+pub use stats::{stats, StatsArgs};
+pub use validate::{validate, ValidateArgs};
@@ -0,0 +1,86 @@
+//! Chart statistics
+
+use crate::load::load_phichain;
+use crate::Formats;
+use phichain_chart::statistics::{ChartStatistics, DensityWindow, NoteCounts};
+use serde::Serialize;
+
+/// The length in seconds of the window used to find the peak density
+const PEAK_WINDOW: f32 = 2.0;
+
+#[derive(Debug, clap::Args)]
+pub struct StatsArgs {
+    /// The input chart format, detected from the input chart if not given
+    #[arg(short, long)]
+    input: Option<Formats>,
+
+    /// The chart, `-` for stdin, or a Phichain project directory
+    path: String,
+
+    /// Print the statistics as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    /// Amount of lines, including child lines
+    lines: usize,
+    /// Amount of events of all lines
+    events: usize,
+    notes: NoteCounts,
+    combo: u32,
+    multi_hits: u32,
+    /// Time in seconds when the last note ends
+    duration: f32,
+    /// Average notes per second over the whole chart
+    average_density: f32,
+    /// The window with the most notes
+    peak_density: Option<DensityWindow>,
+    difficulty: f32,
+}
+
+/// Print note counts, density and duration of a chart
+pub fn stats(args: StatsArgs) -> anyhow::Result<()> {
+    let chart = load_phichain(&args.path.as_str().into(), args.input.as_ref())?;
+    let lines = chart.iter_lines();
+    let statistics = ChartStatistics::from(&chart);
+
+    let stats = Stats {
+        lines: lines.len(),
+        events: lines.iter().map(|(_, x)| x.events.len()).sum(),
+        notes: statistics.notes,
+        combo: statistics.combo(),
+        multi_hits: statistics.multi_hits,
+        duration: statistics.duration,
+        average_density: statistics.average_density(),
+        peak_density: statistics.peak_density(PEAK_WINDOW),
+        difficulty: statistics.difficulty(),
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string(&stats)?);
+        return Ok(());
+    }
+
+    println!("Lines: {}", stats.lines);
+    println!("Events: {}", stats.events);
+    println!(
+        "Notes: {} (tap {}, drag {}, hold {}, flick {})",
+        stats.combo, stats.notes.tap, stats.notes.drag, stats.notes.hold, stats.notes.flick
+    );
+    println!("Multi hits: {}", stats.multi_hits);
+    println!("Duration: {:.2}s", stats.duration);
+    println!("Average density: {:.2} notes/s", stats.average_density);
+    if let Some(peak) = stats.peak_density {
+        println!(
+            "Peak density: {:.2} notes/s ({:.2}s ~ {:.2}s)",
+            peak.notes_per_second(),
+            peak.start,
+            peak.end
+        );
+    }
+    println!("Difficulty: {:.2}", stats.difficulty);
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+//! Checking charts for common mistakes
+
+use crate::load::load_phichain;
+use crate::Formats;
+use phichain_chart::validation::validate as validate_chart;
+use std::process::ExitCode;
+
+#[derive(Debug, clap::Args)]
+pub struct ValidateArgs {
+    /// The input chart format, detected from the input chart if not given
+    #[arg(short, long)]
+    input: Option<Formats>,
+
+    /// The chart to check, `-` for stdin, or a Phichain project directory
+    path: String,
+
+    /// Print the diagnostics as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// Check a chart, fails if any error is found
+pub fn validate(args: ValidateArgs) -> anyhow::Result<ExitCode> {
+    let chart = load_phichain(&args.path.as_str().into(), args.input.as_ref())?;
+    let diagnostics = validate_chart(&chart);
+
+    if args.json {
+        println!("{}", serde_json::to_string(&diagnostics)?);
+    } else {
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic);
+        }
+    }
+
+    let errors = diagnostics.iter().filter(|x| x.is_error()).count();
+    eprintln!(
+        "Found {} error(s) and {} warning(s)",
+        errors,
+        diagnostics.len() - errors
+    );
+
+    Ok(if errors > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
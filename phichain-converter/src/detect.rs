@@ -0,0 +1,152 @@
+//! Detection of the input chart format
+
+use crate::Formats;
+use anyhow::bail;
+use phichain_chart::binary::{ChartKind, Header};
+use phichain_chart::format::pec::PecChart;
+use serde_json::Value;
+
+/// The magic number at the start of zip archives, i.e. pez files
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Collect the events of all lines in a chart in Phichain or primitive format, including child lines
+fn collect_events<'a>(lines: &'a [Value], events: &mut Vec<&'a Value>) {
+    for line in lines {
+        if let Some(line_events) = line.get("events").and_then(Value::as_array) {
+            events.extend(line_events);
+        }
+        if let Some(children) = line.get("children").and_then(Value::as_array) {
+            collect_events(children, events);
+        }
+    }
+}
+
+/// The formats a JSON chart could be in
+///
+/// - `formatVersion`, or `judgeLineList` without `META`, means official
+/// - `META.RPEVersion` means RPE, whose charts have a `judgeLineList` as well
+/// - `lines` means Phichain or primitive. Phichain events have a `value` since format `3`, and PascalCase kinds
+///   before, while primitive events have `start` and `end` with snake_case kinds. Charts without events can not be
+///   told apart
+fn detect_json(chart: &Value) -> Vec<Formats> {
+    let mut candidates = vec![];
+
+    if chart.get("formatVersion").is_some()
+        || (chart.get("judgeLineList").is_some() && chart.get("META").is_none())
+    {
+        candidates.push(Formats::Official);
+    }
+    if chart.pointer("/META/RPEVersion").is_some() {
+        candidates.push(Formats::Rpe);
+    }
+
+    if let Some(lines) = chart.get("lines").and_then(Value::as_array) {
+        let mut events = vec![];
+        collect_events(lines, &mut events);
+        let primitive_event = |event: &&Value| {
+            event.get("start").is_some()
+                && event
+                    .get("kind")
+                    .and_then(Value::as_str)
+                    .is_some_and(|x| x.starts_with(|c: char| c.is_ascii_lowercase()))
+        };
+
+        // format `0` charts have no `format` field
+        if chart.get("format").is_none() || !events.iter().any(primitive_event) {
+            candidates.push(Formats::Phichain);
+        }
+        if chart.get("format").is_some()
+            && chart.get("bpm_list").is_some()
+            && events.iter().all(primitive_event)
+        {
+            candidates.push(Formats::Primitive);
+        }
+    }
+
+    candidates
+}
+
+/// Detect the format of a chart file, `name` is used in errors
+///
+/// Fails with the possible formats listed if the format can not be told
+pub fn detect(name: &str, data: &[u8]) -> anyhow::Result<Formats> {
+    if data.starts_with(ZIP_MAGIC) {
+        return Ok(Formats::Pez);
+    }
+    if let Some(header) = Header::read(data)? {
+        return Ok(match header.kind {
+            ChartKind::Phichain => Formats::Phichain,
+            ChartKind::Primitive => Formats::Primitive,
+        });
+    }
+
+    let candidates = match serde_json::from_slice::<Value>(data) {
+        Ok(chart) => detect_json(&chart),
+        Err(_) => {
+            let is_pec = std::str::from_utf8(data)
+                .ok()
+                .is_some_and(|x| x.parse::<PecChart>().is_ok());
+            if is_pec {
+                vec![Formats::Pec]
+            } else {
+                vec![]
+            }
+        }
+    };
+
+    match candidates.as_slice() {
+        [format] => Ok(format.clone()),
+        [] => bail!(
+            "Could not detect the format of {}, specify it with `--input`",
+            name
+        ),
+        _ => bail!(
+            "Could not tell the format of {}, it could be any of: {}. Specify it with `--input`",
+            name,
+            candidates
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detect_json() {
+        let official = json!({ "formatVersion": 3, "offset": 0.0, "judgeLineList": [] });
+        assert_eq!(detect_json(&official), vec![Formats::Official]);
+
+        let rpe = json!({ "META": { "RPEVersion": 140 }, "judgeLineList": [] });
+        assert_eq!(detect_json(&rpe), vec![Formats::Rpe]);
+
+        let phichain = json!({
+            "format": 4,
+            "bpm_list": [],
+            "lines": [{ "events": [{ "kind": "x", "value": { "constant": 0.0 } }], "children": [] }],
+        });
+        assert_eq!(detect_json(&phichain), vec![Formats::Phichain]);
+
+        let primitive = json!({
+            "format": 1,
+            "bpm_list": [],
+            "lines": [{ "events": [{ "kind": "x", "start": 0.0, "end": 1.0 }] }],
+        });
+        assert_eq!(detect_json(&primitive), vec![Formats::Primitive]);
+
+        let old_phichain =
+            json!({ "format": 2, "lines": [{ "events": [{ "kind": "X", "start": 0.0 }] }] });
+        assert_eq!(detect_json(&old_phichain), vec![Formats::Phichain]);
+
+        let empty = json!({ "format": 1, "bpm_list": [], "lines": [] });
+        assert_eq!(
+            detect_json(&empty),
+            vec![Formats::Phichain, Formats::Primitive]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Inputs and outputs of the converter
+
+use anyhow::{bail, Context};
+use phichain_chart::project::ProjectPath;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The path standing for stdin as input and stdout as output
+pub const STDIO: &str = "-";
+
+/// Where a chart is read from
+#[derive(Debug, Clone)]
+pub enum Input {
+    Stdin,
+    Path(PathBuf),
+}
+
+impl Input {
+    /// Read the whole input
+    pub fn read(&self) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Input::Stdin => {
+                let mut data = vec![];
+                std::io::stdin()
+                    .lock()
+                    .read_to_end(&mut data)
+                    .context("Failed to read stdin")?;
+                Ok(data)
+            }
+            Input::Path(path) => {
+                std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        }
+    }
+}
+
+impl From<&str> for Input {
+    /// `-` is stdin, anything else is a path
+    fn from(value: &str) -> Self {
+        if value == STDIO {
+            Input::Stdin
+        } else {
+            Input::Path(PathBuf::from(value))
+        }
+    }
+}
+
+impl Display for Input {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Input::Stdin => write!(f, "stdin"),
+            Input::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Where a converted chart is written to
+#[derive(Debug, Clone)]
+pub enum Output {
+    Stdout,
+    Path(PathBuf),
+}
+
+impl Output {
+    pub fn write(&self, data: &[u8]) -> anyhow::Result<()> {
+        match self {
+            Output::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(data)?;
+                stdout.flush()?;
+                Ok(())
+            }
+            Output::Path(path) => std::fs::write(path, data)
+                .with_context(|| format!("Failed to write {}", path.display())),
+        }
+    }
+}
+
+impl Display for Output {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Output::Stdout => write!(f, "stdout"),
+            Output::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Whether the directory is a Phichain project
+pub fn is_project(path: &Path) -> bool {
+    let project = ProjectPath(path.to_path_buf());
+    project.chart_file().is_some() && project.meta_path().is_file()
+}
+
+/// Expand the input arguments into the charts to convert
+///
+/// `-` is stdin, a Phichain project directory is a single chart, any other directory expands into the files and
+/// projects inside it, and a path which does not exist is matched as a glob pattern. Returns whether the inputs
+/// form a batch, i.e. more than one chart is given, or a directory or pattern is expanded
+pub fn expand(inputs: &[String]) -> anyhow::Result<(Vec<Input>, bool)> {
+    let mut expanded = vec![];
+    let mut batch = inputs.len() > 1;
+
+    for input in inputs {
+        if input == STDIO {
+            expanded.push(Input::Stdin);
+            continue;
+        }
+
+        let path = PathBuf::from(input);
+        if path.is_dir() && !is_project(&path) {
+            batch = true;
+            let mut entries = std::fs::read_dir(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            entries.retain(|x| x.is_file() || is_project(x));
+            entries.sort();
+            expanded.extend(entries.into_iter().map(Input::Path));
+        } else if !path.exists() {
+            batch = true;
+            let matches = glob::glob(input)
+                .with_context(|| format!("{} does not exist and is not a valid pattern", input))?
+                .collect::<Result<Vec<_>, _>>()?;
+            if matches.is_empty() {
+                bail!("{} does not exist and matches no files", input);
+            }
+            expanded.extend(matches.into_iter().map(Input::Path));
+        } else {
+            expanded.push(Input::Path(path));
+        }
+    }
+
+    Ok((expanded, batch))
+}
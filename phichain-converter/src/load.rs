@@ -0,0 +1,117 @@
+//! Loading input charts in any format
+
+use crate::detect;
+use crate::input::Input;
+use crate::Formats;
+use anyhow::{bail, Context};
+use phichain_chart::fitting::DEFAULT_FIT_TOLERANCE;
+use phichain_chart::format::official::OfficialChart;
+use phichain_chart::format::pec::PecChart;
+use phichain_chart::format::rpe::RpeChart;
+use phichain_chart::pez::Pez;
+use phichain_chart::primitive::{Format, PrimitiveChart};
+use phichain_chart::project::Project;
+use phichain_chart::report::ConversionReport;
+use phichain_chart::serialization::PhichainChart;
+use std::io::Cursor;
+
+/// A loaded input chart
+pub struct Loaded {
+    pub format: Formats,
+    pub primitive: PrimitiveChart,
+    /// The music and illustration, if the input carries them
+    pub pez: Option<Pez>,
+    /// What is lost converting the input into a primitive chart
+    pub report: ConversionReport,
+}
+
+/// Load a chart, the format is detected from the input if not given
+///
+/// A directory is loaded as a Phichain project. `log` receives progress messages
+pub fn load(
+    input: &Input,
+    format: Option<&Formats>,
+    fit_tolerance: f32,
+    log: impl Fn(String),
+) -> anyhow::Result<Loaded> {
+    if let Input::Path(path) = input {
+        if path.is_dir() {
+            if format.is_some_and(|x| *x != Formats::Phichain) {
+                bail!("Directories are only supported as Phichain projects");
+            }
+            let project = Project::load(path.clone())?;
+            let path = project
+                .path
+                .chart_file()
+                .context("Could not find chart in project")?;
+            let chart = PhichainChart::read(&std::fs::read(path)?)?;
+            let primitive = PrimitiveChart {
+                meta: Some(project.meta.clone()),
+                ..chart.into_primitive()?
+            };
+            let pez = Pez::from_project(&project.path, project.meta, primitive)?;
+            return Ok(Loaded {
+                format: Formats::Phichain,
+                primitive: pez.chart.clone(),
+                pez: Some(pez),
+                report: Default::default(),
+            });
+        }
+    }
+
+    let data = input.read()?;
+
+    let format = match format {
+        Some(format) => format.clone(),
+        None => {
+            let format = detect::detect(&input.to_string(), &data)?;
+            log(format!("Detected input format `{}`", format));
+            format
+        }
+    };
+
+    log("Converting chart into primitive chart...".to_owned());
+
+    let (primitive, report) = match format {
+        Formats::Official => {
+            let chart: OfficialChart = serde_json::from_slice(&data)?;
+            chart.into_primitive_with_tolerance(fit_tolerance)?
+        }
+        Formats::Phichain => PhichainChart::read(&data)?.into_primitive_with_report()?,
+        Formats::Rpe => {
+            let chart: RpeChart = serde_json::from_slice(&data)?;
+            chart.into_primitive_with_report()?
+        }
+        Formats::Primitive => PrimitiveChart::read(&data)?.into_primitive_with_report()?,
+        Formats::Pec => {
+            let chart: PecChart = std::str::from_utf8(&data)?.parse()?;
+            chart.into_primitive_with_report()?
+        }
+        Formats::Pez => {
+            let (pez, report) = Pez::read_with_report(Cursor::new(data))?;
+            let primitive = PrimitiveChart {
+                meta: Some(pez.meta.clone()),
+                ..pez.chart.clone()
+            };
+            return Ok(Loaded {
+                format,
+                primitive,
+                pez: Some(pez),
+                report,
+            });
+        }
+    };
+
+    Ok(Loaded {
+        format,
+        primitive,
+        pez: None,
+        report,
+    })
+}
+
+/// Load a chart as a Phichain chart, the format is detected from the input if not given
+pub fn load_phichain(input: &Input, format: Option<&Formats>) -> anyhow::Result<PhichainChart> {
+    let loaded = load(input, format, DEFAULT_FIT_TOLERANCE, |_| {})?;
+    PhichainChart::from_primitive(loaded.primitive)
+}
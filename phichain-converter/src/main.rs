@@ -1,13 +1,17 @@
-use clap::{Parser, ValueEnum};
-use phichain_chart::format::official::OfficialChart;
-use phichain_chart::format::rpe::RpeChart;
-use phichain_chart::primitive::{Format, PrimitiveChart};
-use phichain_chart::serialization::PhichainChart;
-use std::io::Write;
-use std::path::PathBuf;
+mod commands;
+mod detect;
+mod input;
+mod load;
+
+use crate::commands::{
+    convert, diff, migrate, stats, validate, ConvertArgs, DiffArgs, MigrateArgs, StatsArgs,
+    ValidateArgs,
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::process::ExitCode;
 use strum::Display;
 
-#[derive(ValueEnum, Debug, Display, Clone)]
+#[derive(ValueEnum, Debug, Display, Clone, PartialEq, Eq)]
 #[clap(rename_all = "kebab_case")]
 #[strum(serialize_all = "snake_case")]
 enum Formats {
@@ -15,81 +19,60 @@ enum Formats {
     Phichain,
     Rpe,
     Primitive,
+    Pec,
+    Pez,
+}
+
+impl Formats {
+    /// The file extension of charts in this format
+    fn extension(&self) -> &'static str {
+        match self {
+            Formats::Pec => "pec",
+            Formats::Pez => "pez",
+            _ => "json",
+        }
+    }
 }
 
 #[derive(Debug, Parser)]
 #[command(name = "phichain-converter")]
-#[command(about = "Converts Phigros charts between different formats")]
-struct Args {
-    /// The input chart format
-    #[arg(short, long, required = true)]
-    input: Formats,
-    /// The output chart format
-    #[arg(short, long, required = true)]
-    output: Formats,
-
-    /// The path of the input chart
-    #[arg(required = true)]
-    path: PathBuf,
+#[command(about = "Converts, checks and compares Phigros charts")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn convert(args: Args) -> anyhow::Result<()> {
-    let file = std::fs::File::open(&args.path)?;
-
-    println!("Converting chart into primitive chart...");
-
-    let primitive = match args.input {
-        Formats::Official => {
-            let chart: OfficialChart = serde_json::from_reader(file)?;
-            chart.into_primitive()?
-        }
-        Formats::Phichain => {
-            let chart: PhichainChart = serde_json::from_reader(file)?;
-            chart.into_primitive()?
-        }
-        Formats::Rpe => {
-            let chart: RpeChart = serde_json::from_reader(file)?;
-            chart.into_primitive()?
-        }
-        Formats::Primitive => {
-            let chart: PrimitiveChart = serde_json::from_reader(file)?;
-            chart.into_primitive()?
-        }
-    };
-
-    println!("Converting chart into `{}` chart...", args.output);
-
-    let output = match args.output {
-        Formats::Official => {
-            let chart = OfficialChart::from_primitive(primitive)?;
-            serde_json::to_string(&chart)?
-        }
-        Formats::Phichain => {
-            let chart = PhichainChart::from_primitive(primitive)?;
-            serde_json::to_string(&chart)?
-        }
-        Formats::Rpe => {
-            let chart = RpeChart::from_primitive(primitive)?;
-            serde_json::to_string(&chart)?
-        }
-        Formats::Primitive => {
-            let chart = PrimitiveChart::from_primitive(primitive)?;
-            serde_json::to_string(&chart)?
-        }
-    };
-
-    let output_path = args.path.with_extension(format!("{}.json", args.output));
-
-    let mut output_file = std::fs::File::create(output_path)?;
-    output_file.write_all(output.as_bytes())?;
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Convert charts between different formats
+    Convert(ConvertArgs),
+    /// Check a chart for common mistakes, exits with a nonzero code if any error is found
+    Validate(ValidateArgs),
+    /// Print note counts, density and duration of a chart
+    Stats(StatsArgs),
+    /// Upgrade Phichain charts in older formats to the current format in place
+    Migrate(MigrateArgs),
+    /// Compare two charts semantically, exits with a nonzero code if they differ
+    Diff(DiffArgs),
+}
 
-    Ok(())
+fn run(command: Command) -> anyhow::Result<ExitCode> {
+    match command {
+        Command::Convert(args) => convert(args).map(|_| ExitCode::SUCCESS),
+        Command::Validate(args) => validate(args),
+        Command::Stats(args) => stats(args).map(|_| ExitCode::SUCCESS),
+        Command::Migrate(args) => migrate(args),
+        Command::Diff(args) => diff(args),
+    }
 }
 
-fn main() {
-    let args = Args::parse();
-    if let Err(err) = convert(args) {
-        eprintln!("Error: {}", err);
-        std::process::exit(1);
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Error: {:#}", err);
+            ExitCode::FAILURE
+        }
     }
 }
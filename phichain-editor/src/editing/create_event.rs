@@ -157,6 +157,7 @@ fn create_event_system(
                         };
                         parent.spawn((
                             LineEventBundle::new(LineEvent {
+                                id: None,
                                 kind,
                                 value,
                                 start_beat: beat,
@@ -105,6 +105,10 @@ impl EditorEvent for SpawnLineEvent {
                 .id(),
         };
 
+        if !self.line.extended.is_empty() {
+            world.entity_mut(id).insert(self.line.extended);
+        }
+
         world.entity_mut(id).with_children(|parent| {
             for note in self.line.notes {
                 parent.spawn(NoteBundle::new(note));
@@ -5,8 +5,11 @@ use anyhow::{bail, Context};
 use bevy::app::App;
 use bevy::prelude::*;
 use phichain_chart::format::official::OfficialChart;
+use phichain_chart::pez::Pez;
 use phichain_chart::primitive::Format;
 use phichain_chart::serialization::PhichainChart;
+use phichain_compiler::pipeline::Profile;
+use phichain_compiler::CompileOptions;
 use std::fs;
 use std::io::{Read, Write};
 use std::path::Path;
@@ -16,7 +19,10 @@ pub struct ExportPlugin;
 
 impl Plugin for ExportPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, export_official_system.run_if(project_loaded()));
+        app.add_systems(
+            Update,
+            (export_official_system, export_pez_system).run_if(project_loaded()),
+        );
     }
 }
 
@@ -31,8 +37,7 @@ fn export_official(path: &Path, project: &Project) -> anyhow::Result<()> {
     let mut zip = zip::ZipWriter::new(file);
 
     zip.start_file("chart.json", SimpleFileOptions::default())?;
-    let chart_file = fs::File::open(project.path.chart_path())?;
-    let chart: PhichainChart = serde_json::from_reader(chart_file)?;
+    let chart = load_chart(project)?;
     let official = OfficialChart::from_primitive(phichain_compiler::compile(chart)?)?;
     zip.write_all(serde_json::to_string(&official)?.as_bytes())?;
 
@@ -110,3 +115,59 @@ fn export_official_system(
         }
     }
 }
+
+/// Load the chart of the project in JSON or in the binary encoding, migrating it from older formats
+fn load_chart(project: &Project) -> anyhow::Result<PhichainChart> {
+    let path = project
+        .path
+        .chart_file()
+        .context("Could not find chart in project")?;
+    let data = fs::read(path).context("Failed to read chart")?;
+    PhichainChart::read(&data).context("Failed to load chart")
+}
+
+fn export_pez(path: &Path, project: &Project) -> anyhow::Result<()> {
+    let pez_path = path.join("chart.pez");
+    if pez_path.exists() {
+        bail!("chart.pez already exists in the folder");
+    }
+
+    let chart = load_chart(project)?;
+    // pez files carry RPE charts, which support line hierarchy and easings
+    let options = CompileOptions {
+        profile: Profile::RpeLossless,
+        ..Default::default()
+    };
+    let pez = Pez::from_project(
+        &project.path,
+        project.meta.clone(),
+        phichain_compiler::compile_with_options(chart, &options)?,
+    )?;
+
+    pez.write(fs::File::create(pez_path)?)
+}
+
+fn export_pez_system(
+    mut event_reader: EventReader<PickingEvent>,
+    project: Res<Project>,
+    mut toasts: ResMut<ToastsStorage>,
+) {
+    for PickingEvent { path, kind } in event_reader.read() {
+        if !matches!(kind, PickingKind::ExportPez) {
+            continue;
+        }
+
+        let Some(path) = path else {
+            return;
+        };
+
+        match export_pez(path, &project) {
+            Ok(_) => {
+                toasts.success("Successfully exported pez");
+            }
+            Err(error) => {
+                toasts.error(format!("Failed to export pez: {}", error));
+            }
+        }
+    }
+}
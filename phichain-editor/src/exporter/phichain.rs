@@ -1,7 +1,13 @@
+use crate::settings::EditorSettings;
 use anyhow::Context;
 use bevy::prelude::*;
+use bevy_persistent::Persistent;
 use phichain_chart::bpm_list::BpmList;
+use phichain_chart::canonical;
+use phichain_chart::event::LineEvent;
+use phichain_chart::id::assign_ids;
 use phichain_chart::line::Line;
+use phichain_chart::note::Note;
 use phichain_chart::offset::Offset;
 
 use phichain_chart::serialization::{LineWrapper, PhichainChart};
@@ -10,8 +16,53 @@ use super::Exporter;
 
 pub struct PhichainExporter;
 
+/// Give every line, note and event in the world without an id, or sharing its id with another one, a new id
+///
+/// The ids are written back into the world, so they stay the same across saves
+fn assign_world_ids(world: &mut World) {
+    let mut ids = vec![];
+    ids.extend(
+        world
+            .query::<(Entity, &Line)>()
+            .iter(world)
+            .map(|(e, x)| (e, x.id)),
+    );
+    ids.extend(
+        world
+            .query::<(Entity, &Note)>()
+            .iter(world)
+            .map(|(e, x)| (e, x.id)),
+    );
+    ids.extend(
+        world
+            .query::<(Entity, &LineEvent)>()
+            .iter(world)
+            .map(|(e, x)| (e, x.id)),
+    );
+    // the entity spawned first keeps a duplicated id, e.g. the original of a pasted note
+    ids.sort_by_key(|(entity, _)| *entity);
+
+    let mut assigned = ids.iter().map(|(_, id)| *id).collect::<Vec<_>>();
+    assign_ids(assigned.iter_mut());
+
+    for ((entity, old), new) in ids.into_iter().zip(assigned) {
+        if old == new {
+            continue;
+        }
+        if let Some(mut line) = world.get_mut::<Line>(entity) {
+            line.id = new;
+        } else if let Some(mut note) = world.get_mut::<Note>(entity) {
+            note.id = new;
+        } else if let Some(mut event) = world.get_mut::<LineEvent>(entity) {
+            event.id = new;
+        }
+    }
+}
+
 impl Exporter for PhichainExporter {
     fn export(world: &mut World) -> anyhow::Result<String> {
+        assign_world_ids(world);
+
         let bpm_list = world.resource::<BpmList>().clone();
         let offset = world.resource::<Offset>().0;
         let mut chart = PhichainChart::new(offset, bpm_list, vec![]);
@@ -24,6 +75,15 @@ impl Exporter for PhichainExporter {
             chart.lines.push(LineWrapper::serialize_line(world, entity));
         }
 
-        serde_json::to_string(&chart).context("Failed to export chart as phichain")
+        let canonical = world
+            .resource::<Persistent<EditorSettings>>()
+            .general
+            .canonical_chart;
+        if canonical {
+            canonical::to_string(&chart)
+        } else {
+            serde_json::to_string(&chart)
+        }
+        .context("Failed to export chart as phichain")
     }
 }
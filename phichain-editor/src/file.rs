@@ -20,6 +20,7 @@ pub enum PickingKind {
     SelectMusic,
     CreateProject,
     ExportOfficial,
+    ExportPez,
 }
 
 #[derive(Event, Debug)]
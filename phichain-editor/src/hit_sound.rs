@@ -32,6 +32,10 @@ fn play_hit_sound_system(
     paused: Res<Paused>,
 ) {
     for (note, entity, played) in &query {
+        if note.fake {
+            continue;
+        }
+
         let note_time = bpm_list.time_at(note.beat);
         if note_time <= time.0 && time.0 - note_time < 0.05 && played.is_none() && !paused.0 {
             let handle = match note.kind {
@@ -364,6 +364,10 @@ fn ui_system(world: &mut World) {
                     pick_folder(world, PickingKind::ExportOfficial, FileDialog::new());
                     ui.close_menu();
                 }
+                if ui.button(t!("menu_bar.export.as_pez")).clicked() {
+                    pick_folder(world, PickingKind::ExportPez, FileDialog::new());
+                    ui.close_menu();
+                }
             });
         });
 
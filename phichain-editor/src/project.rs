@@ -13,6 +13,7 @@ use crate::recent_projects::{PersistentRecentProjectsExt, RecentProject, RecentP
 use bevy::ecs::system::SystemState;
 use bevy_kira_audio::{Audio, AudioControl, AudioSource};
 use bevy_persistent::Persistent;
+use phichain_chart::canonical;
 use phichain_chart::line::Line;
 pub use phichain_chart::project::{Project, ProjectMeta, ProjectPath};
 use phichain_chart::serialization::PhichainChart;
@@ -233,7 +234,7 @@ pub fn create_project(
     let meta_string = serde_json::to_string_pretty(&project_meta).unwrap();
     std::fs::write(project_path.meta_path(), meta_string).context("Failed to write meta")?;
 
-    let chart_string = serde_json::to_string_pretty(&PhichainChart::default()).unwrap();
+    let chart_string = canonical::to_string(&PhichainChart::default()).unwrap();
     std::fs::write(project_path.chart_path(), chart_string).context("Failed to write chart")?;
 
     Ok(())
@@ -41,6 +41,8 @@ pub struct GeneralSettings {
     pub timeline_scroll_sensitivity: f32,
     pub highlight_selected_line: bool,
     pub show_line_anchor: ShowLineAnchorOption,
+    /// Save charts with sorted notes and events and one note or event per line, see [`phichain_chart::canonical`]
+    pub canonical_chart: bool,
 }
 
 impl Default for GeneralSettings {
@@ -50,6 +52,7 @@ impl Default for GeneralSettings {
             timeline_scroll_sensitivity: 10.0,
             highlight_selected_line: true,
             show_line_anchor: ShowLineAnchorOption::Always,
+            canonical_chart: true,
         }
     }
 }
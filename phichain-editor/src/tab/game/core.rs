@@ -10,7 +10,7 @@ use phichain_chart::line::Line;
 use phichain_chart::note::Note;
 use phichain_chart::project::Project;
 use phichain_game::core::HoldComponent;
-use phichain_game::GameConfig;
+use phichain_game::{GameConfig, GameSet};
 
 pub struct CoreGamePlugin;
 
@@ -18,7 +18,13 @@ impl Plugin for CoreGamePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Update, zoom_scale_system.run_if(project_loaded()))
             .add_systems(Update, sync_game_config_system.run_if(project_loaded()))
-            .add_systems(Update, update_note_tint_system.run_if(project_loaded()))
+            .add_systems(
+                Update,
+                // the game writes the alpha of notes, the editor overrides it for pending notes
+                update_note_tint_system
+                    .after(GameSet)
+                    .run_if(project_loaded()),
+            )
             .add_systems(
                 Update,
                 sync_hold_components_tint_system
@@ -51,15 +57,15 @@ fn zoom_scale_system(
 }
 
 fn update_note_tint_system(
-    mut query: Query<(&mut Sprite, Option<&Selected>, Option<&Pending>), With<Note>>,
+    mut query: Query<(&mut Sprite, &Note, Option<&Selected>, Option<&Pending>)>,
 ) {
-    for (mut sprite, selected, pending) in &mut query {
+    for (mut sprite, note, selected, pending) in &mut query {
         let tint = if selected.is_some() {
             Color::LIME_GREEN
         } else {
             Color::WHITE
         };
-        let alpha = if pending.is_some() { 40.0 / 255.0 } else { 1.0 };
+        let alpha = if pending.is_some() { 40.0 / 255.0 } else { 1.0 } * note.alpha / 255.0;
         sprite.color = tint.with_a(alpha);
     }
 }
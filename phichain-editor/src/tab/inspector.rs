@@ -316,6 +316,50 @@ fn single_note_inspector(
                 ui.label(t!("tab.inspector.single_note.speed"));
                 let response = ui.add(egui::DragValue::new(&mut note.speed).speed(0.1));
                 finished |= response.drag_stopped() || response.lost_focus();
+                ui.end_row();
+
+                ui.label(t!("tab.inspector.single_note.fake"));
+                let response = ui.checkbox(&mut note.fake, "");
+                finished |= response.changed();
+                ui.end_row();
+
+                ui.label(t!("tab.inspector.single_note.alpha"));
+                let response = ui.add(
+                    egui::DragValue::new(&mut note.alpha)
+                        .clamp_range(0.0..=255.0)
+                        .speed(1),
+                );
+                finished |= response.drag_stopped() || response.lost_focus();
+                ui.end_row();
+
+                ui.label(t!("tab.inspector.single_note.size"));
+                let response = ui.add(egui::DragValue::new(&mut note.size).speed(0.01));
+                finished |= response.drag_stopped() || response.lost_focus();
+                ui.end_row();
+
+                ui.label(t!("tab.inspector.single_note.y_offset"));
+                let response = ui.add(egui::DragValue::new(&mut note.y_offset).speed(1));
+                finished |= response.drag_stopped() || response.lost_focus();
+                ui.end_row();
+
+                ui.label(t!("tab.inspector.single_note.visible_time"));
+                ui.horizontal(|ui| {
+                    let mut limited = note.visible_time.is_some();
+                    let response = ui.checkbox(&mut limited, "");
+                    finished |= response.changed();
+                    if response.changed() {
+                        note.visible_time = limited.then_some(1.0);
+                    }
+                    if let Some(visible_time) = note.visible_time.as_mut() {
+                        let response = ui.add(
+                            egui::DragValue::new(visible_time)
+                                .clamp_range(0.0..=f32::MAX)
+                                .speed(0.01)
+                                .suffix("s"),
+                        );
+                        finished |= response.drag_stopped() || response.lost_focus();
+                    }
+                });
 
                 finished
             });
@@ -495,6 +539,16 @@ fn line_inspector(ui: &mut Ui, line: &mut Line) {
                 finished |= response.lost_focus();
                 ui.end_row();
 
+                ui.label(t!("tab.inspector.line.inherit_opacity"));
+                let response = ui.checkbox(&mut line.inherit.opacity, "");
+                finished |= response.changed();
+                ui.end_row();
+
+                ui.label(t!("tab.inspector.line.inherit_rotation"));
+                let response = ui.checkbox(&mut line.inherit.rotation, "");
+                finished |= response.changed();
+                ui.end_row();
+
                 finished
             });
 
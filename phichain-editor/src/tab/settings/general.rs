@@ -93,6 +93,11 @@ impl SettingCategory for General {
                     finished |= changed;
                     ui.end_row();
 
+                    ui.label(t!("tab.settings.category.general.canonical_chart"));
+                    let response = ui.checkbox(&mut settings.general.canonical_chart, "");
+                    finished |= response.changed();
+                    ui.end_row();
+
                     finished
                 })
                 .is_some()
@@ -4,6 +4,7 @@ use phichain_assets::ImageAssets;
 use phichain_chart::bpm_list::BpmList;
 use phichain_chart::constants::{CANVAS_HEIGHT, CANVAS_WIDTH};
 use phichain_chart::event::{EventEvaluationResult, LineEvent, LineEventKind};
+use phichain_chart::extended::{ExtendedEvents, LineColor};
 use phichain_chart::line::{Line, LineOpacity, LinePosition, LineRotation};
 
 use crate::constants::PERFECT_COLOR;
@@ -32,7 +33,11 @@ impl Plugin for CoreGamePlugin {
         )
         .add_systems(
             Update,
-            (compute_line_system, update_line_system)
+            (
+                compute_line_system,
+                update_line_system,
+                update_line_text_system,
+            )
                 .chain()
                 .in_set(GameSet),
         )
@@ -59,23 +64,26 @@ impl Plugin for CoreGamePlugin {
 }
 
 pub fn update_note_scale_system(
-    mut query: Query<&mut Transform, With<Note>>,
+    mut query: Query<(&mut Transform, &Note)>,
     game_viewport: Res<GameViewport>,
     note_scale: Res<NoteScale>,
 ) {
-    for mut transform in &mut query {
-        transform.scale = Vec3::splat(note_scale.0 / (game_viewport.0.width() * 3.0 / 1920.0))
+    for (mut transform, note) in &mut query {
+        transform.scale = Vec3::splat(note_scale.0 / (game_viewport.0.width() * 3.0 / 1920.0));
+        transform.scale.x *= note.size;
     }
 }
 
 pub fn update_note_system(
-    mut query: Query<(&mut Transform, &mut Visibility, &Note)>,
+    mut query: Query<(&mut Transform, &mut Visibility, &mut Sprite, &Note)>,
     game_viewport: Res<GameViewport>,
     time: Res<ChartTime>,
     bpm_list: Res<BpmList>,
 ) {
     let beat = bpm_list.beat_at(time.0);
-    for (mut transform, mut visibility, note) in &mut query {
+    for (mut transform, mut visibility, mut sprite, note) in &mut query {
+        sprite.color.set_a(note.alpha / 255.0);
+
         transform.translation.x = (note.x / CANVAS_WIDTH) * game_viewport.0.width()
             / (game_viewport.0.width() * 3.0 / 1920.0);
 
@@ -151,37 +159,167 @@ pub fn compute_line_system(
     }
 }
 
+/// Components of a line needed to resolve what it inherits from its ancestors, see [`inherited`]
+type InheritanceQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Line,
+        &'static LineRotation,
+        &'static LineOpacity,
+        Option<&'static Parent>,
+    ),
+>;
+
+/// The rotation in radians and the opacity of a line, including what it inherits from its ancestors
+fn inherited(entity: Entity, query: &InheritanceQuery) -> (f32, f32) {
+    let Ok((line, rotation, opacity, parent)) = query.get(entity) else {
+        return (0.0, 1.0);
+    };
+    let Some(parent) = parent else {
+        return (rotation.0, opacity.0);
+    };
+
+    let (parent_rotation, parent_opacity) = inherited(parent.get(), query);
+    (
+        if line.inherit.rotation {
+            parent_rotation + rotation.0
+        } else {
+            rotation.0
+        },
+        if line.inherit.opacity {
+            parent_opacity * opacity.0
+        } else {
+            opacity.0
+        },
+    )
+}
+
 pub fn update_line_system(
     mut line_query: Query<
         (
+            Entity,
             &LinePosition,
-            &LineRotation,
-            &LineOpacity,
             &mut Transform,
             &mut Sprite,
+            &Handle<Image>,
+            Option<&ExtendedEvents>,
             Option<&Parent>,
         ),
         With<Line>,
     >,
+    inheritance_query: InheritanceQuery,
     game_viewport: Res<GameViewport>,
+    images: Res<Assets<Image>>,
+    time: Res<ChartTime>,
+    bpm_list: Res<BpmList>,
 
     config: Res<GameConfig>,
 ) {
-    for (position, rotation, opacity, mut transform, mut sprite, parent) in &mut line_query {
+    let beat: f32 = bpm_list.beat_at(time.0).into();
+    for (entity, position, mut transform, mut sprite, image, extended, parent) in &mut line_query {
         let scale = game_viewport.0.width() * 3.0 / 1920.0;
         transform.scale = Vec3::splat(if parent.is_some() { 1.0 } else { scale });
         transform.translation.x = position.0.x / CANVAS_WIDTH * game_viewport.0.width()
             / if parent.is_some() { scale } else { 1.0 };
         transform.translation.y = position.0.y / CANVAS_HEIGHT * game_viewport.0.height()
             / if parent.is_some() { scale } else { 1.0 };
-        transform.rotation = Quat::from_rotation_z(rotation.0);
 
-        sprite.color = if config.fc_ap_indicator {
+        // the transform of a child line always follows the rotation of its parent, take it away if the line does not
+        // inherit it
+        let (rotation, opacity) = inherited(entity, &inheritance_query);
+        let parent_rotation = parent.map_or(0.0, |x| inherited(x.get(), &inheritance_query).0);
+        transform.rotation = Quat::from_rotation_z(rotation - parent_rotation);
+
+        let color = if config.fc_ap_indicator {
             PERFECT_COLOR
         } else {
             Color::WHITE
+        };
+
+        let Some(extended) = extended else {
+            sprite.custom_size = None;
+            sprite.color = color.with_a(opacity);
+            continue;
+        };
+
+        // scale the sprite instead of the transform, otherwise notes on the line are scaled as well
+        let (scale_x, scale_y) = extended.scale_at(beat);
+        sprite.custom_size = images
+            .get(image)
+            .map(|x| x.size_f32() * Vec2::new(scale_x, scale_y));
+
+        let color = match extended.color_at(beat) {
+            Some(LineColor(r, g, b)) => Color::rgb_u8(r, g, b),
+            None => color,
+        };
+
+        // lines showing text are rendered by update_line_text_system
+        sprite.color = if extended.text_at(beat).is_some() {
+            color.with_a(0.0)
+        } else {
+            color.with_a(opacity)
+        };
+    }
+}
+
+/// Marker component for the text entity of a line with text events
+#[derive(Component, Debug)]
+pub struct LineText;
+
+const LINE_TEXT_SIZE: f32 = 30.0;
+
+pub fn update_line_text_system(
+    mut commands: Commands,
+    line_query: Query<(Entity, &ExtendedEvents, Option<&Children>), With<Line>>,
+    inheritance_query: InheritanceQuery,
+    mut text_query: Query<&mut Text, With<LineText>>,
+    time: Res<ChartTime>,
+    bpm_list: Res<BpmList>,
+    asset_server: Res<AssetServer>,
+) {
+    let beat: f32 = bpm_list.beat_at(time.0).into();
+    for (entity, extended, children) in &line_query {
+        if extended.text.is_empty() {
+            continue;
+        }
+
+        let value = extended.text_at(beat).unwrap_or_default();
+        let color = match extended.color_at(beat) {
+            Some(LineColor(r, g, b)) => Color::rgb_u8(r, g, b),
+            None => Color::WHITE,
+        }
+        .with_a(inherited(entity, &inheritance_query).1);
+
+        let text = children
+            .and_then(|children| children.iter().copied().find(|x| text_query.contains(*x)));
+
+        match text {
+            Some(text) => {
+                if let Ok(mut text) = text_query.get_mut(text) {
+                    text.sections[0].value = value;
+                    text.sections[0].style.color = color;
+                }
+            }
+            None => {
+                commands.entity(entity).with_children(|parent| {
+                    parent.spawn((
+                        Text2dBundle {
+                            text: Text::from_section(
+                                value,
+                                TextStyle {
+                                    font: asset_server.load("font/phigros.ttf"),
+                                    font_size: LINE_TEXT_SIZE,
+                                    color,
+                                },
+                            ),
+                            ..default()
+                        },
+                        LineText,
+                    ));
+                });
+            }
         }
-        .with_a(opacity.0);
     }
 }
 
@@ -213,7 +351,9 @@ pub fn update_note_y_system(
             if let Ok((mut transform, mut sprite, mut visibility, note)) =
                 note_query.get_mut(*child)
             {
-                let mut y = (distance(bpm_list.time_at(note.beat)) - current_distance) * note.speed;
+                let hit_time = bpm_list.time_at(note.beat);
+                let visible = note.is_visible_at(hit_time, time.0);
+                let mut y = (distance(hit_time) - current_distance) * note.speed;
                 match note.kind {
                     NoteKind::Hold { hold_beat } => {
                         y = y.max(0.0);
@@ -228,7 +368,7 @@ pub fn update_note_y_system(
                         transform.scale.y = height / 1900.0;
 
                         // hide notes behind line (cover)
-                        *visibility = if height < 0.0 {
+                        *visibility = if height < 0.0 || !visible {
                             Visibility::Hidden
                         } else {
                             Visibility::Inherited
@@ -239,7 +379,7 @@ pub fn update_note_y_system(
                         transform.rotation = Quat::from_rotation_z(0.0_f32.to_radians());
 
                         // hide notes behind line (cover)
-                        *visibility = if y < 0.0 {
+                        *visibility = if y < 0.0 || !visible {
                             Visibility::Hidden
                         } else {
                             Visibility::Inherited
@@ -247,7 +387,9 @@ pub fn update_note_y_system(
                     }
                 }
 
-                transform.translation.y = y * if note.above { 1.0 } else { -1.0 };
+                let y_offset = note.y_offset / CANVAS_HEIGHT * game_viewport.0.height()
+                    / (game_viewport.0.width() * 3.0 / 1920.0);
+                transform.translation.y = (y + y_offset) * if note.above { 1.0 } else { -1.0 };
             }
         }
     }
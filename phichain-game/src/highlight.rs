@@ -28,7 +28,8 @@ fn calc_highlighted_beat_system(
     mut highlighted_beat: ResMut<HighlightedBeat>,
 ) {
     highlighted_beat.0.clear();
-    for note in &query {
+    // fake notes are not judged, so they never make other notes highlighted
+    for note in query.iter().filter(|x| !x.fake) {
         let counter = highlighted_beat.0.entry(note.beat.reduced()).or_insert(0);
         *counter += 1;
     }
@@ -42,7 +43,8 @@ fn mark_highlight_system(
     settings: Res<GameConfig>,
 ) {
     for (entity, note) in &query {
-        if highlighted_beat.0.contains_key(&note.beat.reduced())
+        if !note.fake
+            && highlighted_beat.0.contains_key(&note.beat.reduced())
             && highlighted_beat.0[&note.beat.reduced()] > 1
             && settings.multi_highlight
         {
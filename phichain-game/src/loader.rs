@@ -3,12 +3,9 @@ use anyhow::Context;
 use bevy::prelude::*;
 use phichain_chart::event::LineEventBundle;
 use phichain_chart::line::LineBundle;
-use phichain_chart::migration::migrate;
 use phichain_chart::note::NoteBundle;
 use phichain_chart::project::Project;
 use phichain_chart::serialization::{LineWrapper, PhichainChart};
-use serde_json::Value;
-use std::fs::File;
 
 /// Load a project to the world using a [`Commands`]
 ///
@@ -22,8 +19,12 @@ use std::fs::File;
 /// - [phichain_chart::bpm_list::BpmList] will be inserted into the world
 /// - Entities with components [`LineBundle`] and [`NoteBundle`] will be spawned into the world, with parent-child relationship
 pub fn load_project(project: &Project, commands: &mut Commands) -> anyhow::Result<()> {
-    let file = File::open(project.path.chart_path())?;
-    load(file, commands)?;
+    let path = project
+        .path
+        .chart_file()
+        .context("Could not find chart in project")?;
+    let data = std::fs::read(path).context("Failed to read chart")?;
+    load(&data, commands)?;
 
     if let Some(illustration_path) = project.path.illustration_path() {
         load_illustration(illustration_path, commands);
@@ -33,8 +34,12 @@ pub fn load_project(project: &Project, commands: &mut Commands) -> anyhow::Resul
 }
 
 fn load_line(line: LineWrapper, commands: &mut Commands, parent: Option<Entity>) -> Entity {
-    let id = commands
-        .spawn(LineBundle::new(line.line))
+    let mut entity = commands.spawn(LineBundle::new(line.line));
+    if !line.extended.is_empty() {
+        entity.insert(line.extended);
+    }
+
+    let id = entity
         .with_children(|parent| {
             for note in line.notes {
                 parent.spawn(NoteBundle::new(note));
@@ -56,12 +61,9 @@ fn load_line(line: LineWrapper, commands: &mut Commands, parent: Option<Entity>)
     id
 }
 
-/// Load a chart to the world using a [`Commands`]
-fn load(file: File, commands: &mut Commands) -> anyhow::Result<()> {
-    let chart: Value = serde_json::from_reader(file).context("Failed to load chart")?;
-    let migrated = migrate(&chart).context("Migration failed")?;
-    let chart: PhichainChart =
-        serde_json::from_value(migrated).context("Failed to deserialize chart")?;
+/// Load a chart in JSON or in the binary encoding to the world using a [`Commands`]
+fn load(data: &[u8], commands: &mut Commands) -> anyhow::Result<()> {
+    let chart = PhichainChart::read(data).context("Failed to load chart")?;
 
     commands.insert_resource(chart.offset);
     commands.insert_resource(chart.bpm_list);